@@ -1,17 +1,34 @@
-use std::{env::current_dir, fs::File, io::Read, path::PathBuf, process::Command, time::Instant};
+use std::{
+    collections::BTreeMap, fs::File, io::Read, path::PathBuf, process::Command, time::Instant,
+};
 
+pub use crate::file_utils::{read_kzg_key, write_kzg_keys};
 use crate::{
-    convert::{convert_r1cs_wtns_gkr, Output},
-    file_utils::{execute_circom, get_name, stringify_fr, write_aggregated_input, write_output},
-    gkr::{prover, GKRCircuit, Input, Proof},
+    compiled::{load_or_compile, CompiledCircuit},
+    convert::{check_r1cs_witness, convert_r1cs_wtns_gkr, ConversionOptions, Output},
+    error::{io_error, AggregatorError},
+    field::CircuitField,
+    file_utils::{
+        calculate_witness, compile_circom, execute_circom, get_dir, get_name, read_proof,
+        read_r1cs, stringify_fr, witness_calculator, write_aggregated_input, write_output,
+        write_proofs,
+    },
+    gkr::{
+        encoding::PcsEncoding,
+        pcs::{Clear, Pcs},
+        poly::get_multi_ext,
+        prover::{self, ProverOptions},
+        transcript::HashType,
+        verifier::{verify_batch_with, VerifyError},
+        GKRCircuit, Input, Proof,
+    },
+    workspace::{Workspace, CACHE_DIR},
 };
 use colored::Colorize;
-use halo2curves::bn256::Fr;
-use r1cs_file::*;
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
-use wtns_file::*;
 
+use rand_core::OsRng;
 use rayon::prelude::*;
 
 /// Circom-GKR
@@ -47,7 +64,7 @@ impl CircomInputProof {
         }
     }
 
-    fn new_from_proof(proof: Proof<Fr>) -> Self {
+    fn new_from_proof<F: CircuitField>(proof: Proof<F>) -> Self {
         let sp: Vec<Vec<Vec<String>>> = proof
             .sumcheck_proofs
             .iter()
@@ -60,11 +77,11 @@ impl CircomInputProof {
             .map(|p| stringify_fr_vector(p))
             .collect();
         let q: Vec<Vec<String>> = proof.q.iter().map(|p| stringify_fr_vector(p)).collect();
-        let d: Vec<Vec<String>> = proof.d.iter().map(|p| stringify_fr_vector(p)).collect();
+        // the output claim is zero, D is kept only for the layout of VerifyGKR
+        let d: Vec<Vec<String>> = vec![stringify_fr_vector(&zeros::<F>(proof.k[0] + 1))];
         let z: Vec<Vec<String>> = proof.z.iter().map(|p| stringify_fr_vector(p)).collect();
         let r: Vec<String> = stringify_fr_vector(&proof.r);
-        let input_func: Vec<Vec<String>> = proof
-            .input_func
+        let input_func: Vec<Vec<String>> = input_func_terms(&proof)
             .iter()
             .map(|p| stringify_fr_vector(p))
             .collect();
@@ -81,15 +98,35 @@ impl CircomInputProof {
     }
 }
 
-fn stringify_fr_vector(v: &Vec<Fr>) -> Vec<String> {
+fn stringify_fr_vector<F: CircuitField>(v: &Vec<F>) -> Vec<String> {
     v.iter().map(|f| stringify_fr(f)).collect()
 }
 
-fn zeros(l: usize) -> Vec<Fr> {
-    vec![Fr::zero(); l]
+// VerifyGKR evaluates the whole input layer, public gates included, from its monomials
+// as [coeff, exps...] like the rest of the circom input
+fn input_func_terms<F: CircuitField>(proof: &Proof<F>) -> Vec<Vec<F>> {
+    let input_layer = proof.input_layer();
+    get_multi_ext(&input_layer.evals, input_layer.num_vars)
+        .terms
+        .iter()
+        .map(|t| {
+            let mut term = vec![t.coeff];
+            term.extend(t.exps.iter().map(|e| F::from(*e as u64)));
+            term
+        })
+        .collect()
+}
+
+fn zeros<F: CircuitField>(l: usize) -> Vec<F> {
+    vec![F::zero(); l]
 }
 
-fn get_meta(proofs: &Vec<Proof<Fr>>) -> Vec<Meta> {
+fn get_meta<F: CircuitField>(proofs: &Vec<Proof<F>>) -> Result<Vec<Meta>, AggregatorError> {
+    let empty = || {
+        AggregatorError::Unsupported(String::from(
+            "the circom verifier needs a proof with layers and sumcheck rounds",
+        ))
+    };
     let mut meta_infos = vec![];
     for proof in proofs {
         let mut meta = vec![];
@@ -97,36 +134,31 @@ fn get_meta(proofs: &Vec<Proof<Fr>>) -> Vec<Meta> {
         meta.push(proof.depth);
 
         // meta[1] = largest k
-        let largest_k = proof
-            .k
-            .iter()
-            .max()
-            .cloned()
-            .expect("Empty proof : k is None");
+        let largest_k = proof.k.iter().max().cloned().ok_or_else(empty)?;
         meta.push(largest_k);
 
         // meta[2] = k_i(0)
         meta.push(proof.k[0]);
 
-        // meta[3] = # of terms of D
-        let n_terms_d = proof.d.len();
-        meta.push(n_terms_d);
+        // meta[3] = # of terms of D, which is the zero polynomial
+        meta.push(1);
 
         // meta[4] = largest # of terms among sumcheck proofs (highest degree)
         let largest_deg = proof
             .sumcheck_proofs
             .iter()
-            .map(|p| p.iter().map(|terms| terms.len()).max().unwrap())
+            .flatten()
+            .map(|terms| terms.len())
             .max()
-            .unwrap();
+            .ok_or_else(empty)?;
         meta.push(largest_deg);
 
         // meta[5] = largest # of terms among q
-        let largest_terms_q = proof.q.iter().map(|p| p.len()).max().unwrap();
+        let largest_terms_q = proof.q.iter().map(|p| p.len()).max().ok_or_else(empty)?;
         meta.push(largest_terms_q);
 
         // meta[6] = # of terms in w_d
-        let n_terms_input_func = proof.input_func.len();
+        let n_terms_input_func = input_func_terms(proof).len();
         meta.push(n_terms_input_func);
 
         // meta[7] = k_i(d - 1)
@@ -137,10 +169,13 @@ fn get_meta(proofs: &Vec<Proof<Fr>>) -> Vec<Meta> {
 
         meta_infos.push(Meta(meta));
     }
-    meta_infos
+    Ok(meta_infos)
 }
 
-fn modify_proof_for_circom(proof: &Vec<Proof<Fr>>, meta_value: &Vec<Meta>) -> Vec<Proof<Fr>> {
+fn modify_proof_for_circom<F: CircuitField>(
+    proof: &Vec<Proof<F>>,
+    meta_value: &Vec<Meta>,
+) -> Vec<Proof<F>> {
     let mut proofs = vec![];
     for (pr, m) in proof.iter().zip(meta_value.iter()) {
         let meta = m.0.clone();
@@ -170,7 +205,7 @@ fn modify_proof_for_circom(proof: &Vec<Proof<Fr>>, meta_value: &Vec<Meta>) -> Ve
         for p in pr.sumcheck_r.iter() {
             let mut new_p = p.clone();
             if p.len() < 2 * meta[1] {
-                new_p.extend(zeros(2 * meta[1] - p.len()));
+                new_p.extend(zeros::<F>(2 * meta[1] - p.len()));
             }
             sumcheck_r.push(new_p);
         }
@@ -191,28 +226,41 @@ fn modify_proof_for_circom(proof: &Vec<Proof<Fr>>, meta_value: &Vec<Meta>) -> Ve
         for p in pr.z.iter() {
             let mut new_p = p.clone();
             if p.len() < meta[1] {
-                new_p.extend(zeros(meta[1] - p.len()));
+                new_p.extend(zeros::<F>(meta[1] - p.len()));
             }
             z.push(new_p);
         }
 
-        let new_p = Proof {
+        let new_p: Proof<F> = Proof {
             sumcheck_proofs,
             sumcheck_r,
             q,
             z,
-            d: pr.d.clone(),
             r: pr.r.clone(),
             depth: pr.depth,
-            input_func: pr.input_func.clone(),
+            commitment: pr.commitment.clone(),
+            opening: (),
+            public: pr.public.clone(),
+            masks: pr.masks.clone(),
             k: pr.k.clone(),
+            hash: pr.hash,
         };
         proofs.push(new_p);
     }
     proofs
 }
 
-fn modify_circom_file(path: String, meta_value: &Vec<Meta>) -> String {
+// absolute, since the aggregated circuit is compiled from the run directory
+const VERIFIER_CIRCUIT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../gkr-verifier-circuits/circom/circom/verifier.circom"
+);
+
+fn modify_circom_file(
+    path: String,
+    meta_value: &Vec<Meta>,
+    workspace: &Workspace,
+) -> Result<String, AggregatorError> {
     let mut added = Tera::default();
     let total = format!("{}", meta_value.len());
 
@@ -267,12 +315,20 @@ fn modify_circom_file(path: String, meta_value: &Vec<Meta>) -> String {
         }
     }
     ";
-    added.add_raw_template("verifier", source).unwrap();
-    added.add_raw_template("component", verifiers).unwrap();
+    let tera_error = |e: tera::Error| AggregatorError::Tool {
+        tool: String::from("tera"),
+        reason: e.to_string(),
+    };
+    added
+        .add_raw_template("verifier", source)
+        .map_err(tera_error)?;
+    added
+        .add_raw_template("component", verifiers)
+        .map_err(tera_error)?;
     let mut decl_ctxt = Context::new();
     decl_ctxt.insert("total", &total);
 
-    let mut v = added.render("component", &decl_ctxt).unwrap();
+    let mut v = added.render("component", &decl_ctxt).map_err(tera_error)?;
     for (i, m) in meta_value.iter().enumerate() {
         let mut ctxt = Context::new();
         let meta = format!("{:?}", m.0);
@@ -285,20 +341,19 @@ fn modify_circom_file(path: String, meta_value: &Vec<Meta>) -> String {
 
             ctxt.insert(name, &value_string);
         }
-        let s = added.render("verifier", &ctxt).unwrap();
+        let s = added.render("verifier", &ctxt).map_err(tera_error)?;
         v = format!("{}\n{}", v, s);
     }
 
     let mut new_circuit = String::new();
-    let mut f = File::open(path).expect("original circuit");
+    let mut f = File::open(&path).map_err(io_error(&path))?;
     let mut f_content = String::new();
-    f.read_to_string(&mut f_content).unwrap();
+    f.read_to_string(&mut f_content).map_err(io_error(&path))?;
 
     let mut is_added = false;
     for line in f_content.lines() {
         if line.eq("pragma circom 2.0.0;") {
-            let import =
-                String::from("include \"../gkr-verifier-circuits/circom/circom/verifier.circom\";");
+            let import = format!("include \"{}\";", VERIFIER_CIRCUIT);
             new_circuit = format!("{}\n{}\n", line, import);
         } else if line.eq("}") && !is_added {
             new_circuit = format!("{}\n{}\n}}", new_circuit, v);
@@ -308,17 +363,20 @@ fn modify_circom_file(path: String, meta_value: &Vec<Meta>) -> String {
         }
     }
 
-    let file_path = current_dir().unwrap().join("aggregated.circom");
-    std::fs::write(&file_path, new_circuit).expect("Write new circuit failed");
-    file_path.into_os_string().into_string().unwrap()
+    let file_path = workspace.aggregated_circuit();
+    std::fs::write(&file_path, new_circuit).map_err(io_error(file_path.display()))?;
+    Ok(file_path.to_string_lossy().into_owned())
 }
 
-pub fn prove_recursively_circom(
+pub fn prove_recursively_circom<F: CircuitField>(
     circuit_path: String,
-    previous_proofs: Vec<Proof<Fr>>,
+    previous_proofs: Vec<Proof<F>>,
     input_path: String,
-) -> Vec<Proof<Fr>> {
-    let meta = get_meta(&previous_proofs);
+    hash: HashType,
+    options: &ConversionOptions,
+    workspace: &Workspace,
+) -> Result<Vec<Proof<F>>, AggregatorError> {
+    let meta = get_meta(&previous_proofs)?;
     let modified_proof = modify_proof_for_circom(&previous_proofs, &meta);
     let mut p_vec = vec![];
     for proof in modified_proof {
@@ -326,10 +384,16 @@ pub fn prove_recursively_circom(
     }
 
     let input_name = get_name(&input_path);
-    let aggregated_input_path = write_aggregated_input(input_path, p_vec);
-    let aggregated_circuit_path = modify_circom_file(circuit_path.clone(), &meta);
+    let aggregated_input_path = write_aggregated_input(input_path, p_vec, workspace)?;
+    let aggregated_circuit_path = modify_circom_file(circuit_path.clone(), &meta, workspace)?;
     println!("{} generated", aggregated_circuit_path);
-    let circom_result = execute_circom(aggregated_circuit_path.clone(), &aggregated_input_path);
+    // the aggregated circuit keeps the includes of the original one
+    let circom_result = execute_circom::<F>(
+        aggregated_circuit_path.clone(),
+        &aggregated_input_path,
+        &get_dir(&circuit_path),
+        workspace,
+    )?;
 
     let name = circom_result.0;
     let r1cs_name = format!("{}.r1cs", name.clone());
@@ -338,28 +402,39 @@ pub fn prove_recursively_circom(
     let root_path = circom_result.1;
     let sym = format!("{}{}", root_path.clone(), sym_name);
     let r1cs_path = format!("{}{}", root_path.clone(), r1cs_name);
-    let r1cs = R1csFile::<32>::read(File::open(r1cs_path).unwrap()).unwrap();
+    let compiled = load_or_compile::<F>(&r1cs_path, options, &workspace.cache_dir())?;
 
-    let wtns_path = current_dir().unwrap().join("witness.wtns");
-    println!("Writing new witness..");
-    let wtns = WtnsFile::<32>::read(File::open(wtns_path).unwrap()).unwrap();
+    let wtns = circom_result.2;
 
-    let result = convert_r1cs_wtns_gkr(r1cs, wtns, sym);
+    check_r1cs_witness::<F>(&read_r1cs(&r1cs_path)?, &wtns, &sym)?;
+    let result = convert_r1cs_wtns_gkr(&compiled, wtns, sym)?;
     println!("Proving starts..");
     let now = Instant::now();
-    let circuit_input_pairs: Vec<(&GKRCircuit<Fr>, &Input<Fr>)> =
-        result.0.iter().zip(result.1.iter()).collect();
-    let proofs: Vec<Proof<Fr>> = circuit_input_pairs
+    let circuit_input_pairs: Vec<(&GKRCircuit<F>, &Input<F>)> =
+        compiled.circuits.iter().zip(result.0.iter()).collect();
+    let proofs: Vec<Proof<F>> = circuit_input_pairs
         .par_iter()
-        .map(|(circuit, input)| prover::prove(circuit, input))
-        .collect();
+        .map(|(circuit, input)| prover::prove(circuit, input, hash))
+        .collect::<Result<_, _>>()?;
 
     let time = report_elapsed(now);
     println!("{}\n", format!("Proving {}", time).blue().bold());
     let output_name = format!("{}_output.json", &input_name);
     let output_path = format!("{}{}", root_path.clone(), output_name);
-    write_output(output_path, result.2);
-    proofs
+    write_output(output_path, result.1)?;
+    write_proofs(&root_path, &input_name, &proofs)?;
+    Ok(proofs)
+}
+
+fn check_hash<F: CircuitField>(hash: HashType) -> Result<(), AggregatorError> {
+    if !hash.supports::<F>() {
+        return Err(AggregatorError::Unsupported(format!(
+            "{} transcript is not available over {}",
+            hash,
+            F::NAME
+        )));
+    }
+    Ok(())
 }
 
 fn report_elapsed(now: Instant) -> String {
@@ -369,26 +444,49 @@ fn report_elapsed(now: Instant) -> String {
     )
 }
 
-pub fn prove_groth(circuit_path: String, previous_proofs: Vec<Proof<Fr>>, input_path: String) {
-    let meta = get_meta(&previous_proofs);
+pub fn prove_groth<F: CircuitField>(
+    circuit_path: String,
+    previous_proofs: Vec<Proof<F>>,
+    input_path: String,
+    workspace: &Workspace,
+) -> Result<(), AggregatorError> {
+    let meta = get_meta(&previous_proofs)?;
     let modified_proof = modify_proof_for_circom(&previous_proofs, &meta);
     let mut p_vec = vec![];
     for proof in modified_proof {
         p_vec.push(CircomInputProof::new_from_proof(proof));
     }
-    let aggregated_input_path = write_aggregated_input(input_path, p_vec);
-    let aggregated_circuit_path = modify_circom_file(circuit_path, &meta);
-    let circom_result = execute_circom(aggregated_circuit_path.clone(), &aggregated_input_path);
+    let aggregated_input_path = write_aggregated_input(input_path, p_vec, workspace)?;
+    let aggregated_circuit_path = modify_circom_file(circuit_path.clone(), &meta, workspace)?;
+    execute_circom::<F>(
+        aggregated_circuit_path.clone(),
+        &aggregated_input_path,
+        &get_dir(&circuit_path),
+        workspace,
+    )?;
     println!("{}", format!("Proving by groth16 can be done").bold());
+    Ok(())
 }
 
-pub fn prove_all(circuit_path: String, input_paths: Vec<String>) {
+pub fn prove_all<F: CircuitField>(
+    circuit_path: String,
+    input_paths: Vec<String>,
+    hash: HashType,
+    options: &ConversionOptions,
+    workspace: &Workspace,
+) -> Result<(), AggregatorError> {
+    check_hash::<F>(hash)?;
     // circom circuit --r1cs --sym --c
     // https://docs.circom.io/getting-started/computing-the-witness/#the-witness-file
     let mut proofs = None;
     for (i, input) in input_paths.iter().enumerate() {
         if i == 0 {
-            let circom_result = execute_circom(circuit_path.clone(), input);
+            let circom_result = execute_circom::<F>(
+                circuit_path.clone(),
+                input,
+                &get_dir(&circuit_path),
+                workspace,
+            )?;
             let name = circom_result.0;
             let root_path = circom_result.1;
 
@@ -396,47 +494,437 @@ pub fn prove_all(circuit_path: String, input_paths: Vec<String>) {
 
             let r1cs_name = format!("{}.r1cs", name.clone());
             let r1cs_path = format!("{}{}", root_path.clone(), r1cs_name);
-            let r1cs = R1csFile::<32>::read(File::open(r1cs_path).unwrap()).unwrap();
+            let compiled = load_or_compile::<F>(&r1cs_path, options, &workspace.cache_dir())?;
             let sym_name = format!("{}.sym", name.clone());
 
-            let wtns_path = current_dir().unwrap().join("witness.wtns");
-            println!("Writing new witness..");
-            let wtns = WtnsFile::<32>::read(File::open(wtns_path).unwrap()).unwrap();
+            let wtns = circom_result.2;
 
             let sym = format!("{}{}", root_path.clone(), sym_name);
 
-            let result = convert_r1cs_wtns_gkr(r1cs, wtns, sym);
+            check_r1cs_witness::<F>(&read_r1cs(&r1cs_path)?, &wtns, &sym)?;
+            let result = convert_r1cs_wtns_gkr(&compiled, wtns, sym)?;
             println!("Proving starts..");
             let now = Instant::now();
-            let circuit_input_pairs: Vec<(&GKRCircuit<Fr>, &Input<Fr>)> =
-                result.0.iter().zip(result.1.iter()).collect();
-            let new_proofs: Vec<Proof<Fr>> = circuit_input_pairs
+            let circuit_input_pairs: Vec<(&GKRCircuit<F>, &Input<F>)> =
+                compiled.circuits.iter().zip(result.0.iter()).collect();
+            let new_proofs: Vec<Proof<F>> = circuit_input_pairs
                 .par_iter()
-                .map(|(circuit, input)| prover::prove(circuit, input))
-                .collect();
+                .map(|(circuit, input)| prover::prove(circuit, input, hash))
+                .collect::<Result<_, _>>()?;
 
             let time = report_elapsed(now);
             println!("{}\n", format!("Proving {}", time).blue().bold());
+            write_proofs(&root_path, &input_name, &new_proofs)?;
             proofs = Some(new_proofs);
             let output_name = format!("{}_output.json", &input_name);
             let output_path = format!("{}{}", root_path.clone(), output_name);
 
-            write_output(output_path, result.2);
-        } else if i == input_paths.len() - 1 {
-            prove_groth(circuit_path.clone(), proofs.clone().unwrap(), input.clone());
+            write_output(output_path, result.1)?;
         } else {
-            proofs = Some(prove_recursively_circom(
-                circuit_path.clone(),
-                proofs.clone().unwrap(),
-                input.clone(),
-            ));
+            let previous = match proofs.take() {
+                Some(previous) => previous,
+                None => {
+                    return Err(AggregatorError::Unsupported(String::from(
+                        "no proof of the first input to aggregate",
+                    )))
+                }
+            };
+            if i == input_paths.len() - 1 {
+                prove_groth(circuit_path.clone(), previous, input.clone(), workspace)?;
+            } else {
+                proofs = Some(prove_recursively_circom(
+                    circuit_path.clone(),
+                    previous,
+                    input.clone(),
+                    hash,
+                    options,
+                    workspace,
+                )?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Proves all inputs of one circuit together instead of recursively, with one data-parallel
+/// GKR proof per GKR circuit. The proofs are written as `batch_proof<i>.json` in the run directory.
+pub fn prove_batch_all<F: CircuitField>(
+    circuit_path: String,
+    input_paths: Vec<String>,
+    hash: HashType,
+    options: &ConversionOptions,
+    workspace: &Workspace,
+) -> Result<(), AggregatorError> {
+    check_hash::<F>(hash)?;
+    if input_paths.is_empty() {
+        return Ok(());
+    }
+    // one compilation and one witness calculator serve every input
+    let (name, root_path) = compile_circom::<F>(&circuit_path, &get_dir(&circuit_path), workspace)?;
+    let r1cs_path = format!("{}{}.r1cs", root_path, name);
+    let sym = format!("{}{}.sym", root_path, name);
+    let compiled = load_or_compile::<F>(&r1cs_path, options, &workspace.cache_dir())?;
+    let r1cs = read_r1cs(&r1cs_path)?;
+    let mut calculator = witness_calculator(&name, workspace)?;
+    let mut batch_inputs: Vec<Vec<Input<F>>> = vec![];
+    for input in input_paths.iter() {
+        let wtns = calculate_witness(&mut calculator, input)?;
+        check_r1cs_witness::<F>(&r1cs, &wtns, &sym)?;
+        let result = convert_r1cs_wtns_gkr(&compiled, wtns, sym.clone())?;
+        for (i, input) in result.0.into_iter().enumerate() {
+            if batch_inputs.len() <= i {
+                batch_inputs.push(vec![]);
+            }
+            batch_inputs[i].push(input);
+        }
+        let output_path = format!("{}{}_output.json", root_path, get_name(input));
+        write_output(output_path, result.1)?;
+    }
+
+    println!("Proving {} inputs in a batch..", input_paths.len());
+    let now = Instant::now();
+    let proofs = prove_batch_compiled(&compiled, &batch_inputs, hash, &Clear)?;
+    let time = report_elapsed(now);
+    println!("{}\n", format!("Proving {}", time).blue().bold());
+    write_proofs(&root_path, &String::from("batch"), &proofs)
+}
+
+/// Proves the inputs of every compiled circuit in one batch per circuit, `inputs[i]` being
+/// those of circuit i, with the input layers of the batch committed to by `pcs`. Fails if its
+/// parameters are too small for a batch.
+pub fn prove_batch_compiled<F: CircuitField, P>(
+    compiled: &CompiledCircuit<F>,
+    inputs: &[Vec<Input<F>>],
+    hash: HashType,
+    pcs: &P,
+) -> Result<Vec<Proof<F, F, P>>, AggregatorError>
+where
+    P: Pcs<F, F> + Sync,
+    P::Commitment: Send,
+    P::Opening: Send,
+{
+    check_hash::<F>(hash)?;
+    let n = inputs
+        .iter()
+        .map(|inputs| inputs.len().next_power_of_two().trailing_zeros() as usize)
+        .max()
+        .unwrap_or(0);
+    let needed = compiled
+        .circuits
+        .iter()
+        .map(|circuit| circuit.k(circuit.depth()) + n)
+        .max()
+        .unwrap_or(0);
+    if let Some(max_vars) = pcs.max_vars() {
+        if needed > max_vars {
+            return Err(AggregatorError::Unsupported(format!(
+                "the key commits to at most {} variables, the batch needs setup --max-vars {}",
+                max_vars, needed
+            )));
+        }
+    }
+    let circuit_input_pairs: Vec<(&GKRCircuit<F>, &Vec<Input<F>>)> =
+        compiled.circuits.iter().zip(inputs.iter()).collect();
+    let proofs = circuit_input_pairs
+        .par_iter()
+        .map(|(circuit, inputs)| prover::prove_batch_with(circuit, inputs, pcs, hash))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(proofs)
+}
+
+/// Proves the inputs of every compiled circuit, one proof per circuit, with the input layers
+/// committed to by `pcs`. Fails if its parameters are too small for a circuit, or if `options`
+/// asks for zero knowledge and `pcs` does not hide.
+pub fn prove_compiled<F: CircuitField, P>(
+    compiled: &CompiledCircuit<F>,
+    inputs: &[Input<F>],
+    hash: HashType,
+    options: &ProverOptions,
+    pcs: &P,
+) -> Result<Vec<Proof<F, F, P>>, AggregatorError>
+where
+    P: Pcs<F, F> + Sync,
+    P::Commitment: Send,
+    P::Opening: Send,
+{
+    check_hash::<F>(hash)?;
+    let needed = compiled
+        .circuits
+        .iter()
+        .map(|circuit| prover::committed_vars(circuit, options))
+        .max()
+        .unwrap_or(0);
+    if let Some(max_vars) = pcs.max_vars() {
+        if needed > max_vars {
+            return Err(AggregatorError::Unsupported(format!(
+                "the key commits to at most {} variables, the circuit needs setup --max-vars {}",
+                max_vars, needed
+            )));
+        }
+    }
+    let circuit_input_pairs: Vec<(&GKRCircuit<F>, &Input<F>)> =
+        compiled.circuits.iter().zip(inputs.iter()).collect();
+    let proofs = circuit_input_pairs
+        .par_iter()
+        .map(|(circuit, input)| {
+            prover::prove_with_options(circuit, input, pcs, hash, options, &mut OsRng)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(proofs)
+}
+
+/// Proves every input of one circuit natively, with the input layers committed to by `pcs`
+/// instead of sent in the clear, which the circom verifier cannot check, and in zero knowledge
+/// if `prover_options` asks for it. Several inputs share the circuit, so they are proven in one
+/// batch per GKR circuit, written as `batch_proof<i>.json` in the run directory; a single input
+/// or a zero-knowledge proof, which is not batched, is written as `{input}_proof<i>.json`. Both
+/// are for `verify_all` with the verifier parameters of `pcs`.
+pub fn prove_committed<F: CircuitField, P>(
+    circuit_path: String,
+    input_paths: Vec<String>,
+    hash: HashType,
+    options: &ConversionOptions,
+    prover_options: &ProverOptions,
+    pcs: &P,
+    workspace: &Workspace,
+) -> Result<(), AggregatorError>
+where
+    P: PcsEncoding<F, F> + Sync,
+    P::Commitment: Send,
+    P::Opening: Send,
+{
+    check_hash::<F>(hash)?;
+    if input_paths.is_empty() {
+        return Ok(());
+    }
+    let (name, root_path) = compile_circom::<F>(&circuit_path, &get_dir(&circuit_path), workspace)?;
+    let r1cs_path = format!("{}{}.r1cs", root_path, name);
+    let sym = format!("{}{}.sym", root_path, name);
+    let compiled = load_or_compile::<F>(&r1cs_path, options, &workspace.cache_dir())?;
+    let r1cs = read_r1cs(&r1cs_path)?;
+    let mut calculator = witness_calculator(&name, workspace)?;
+    let mut all_inputs = vec![];
+    for input in input_paths.iter() {
+        let wtns = calculate_witness(&mut calculator, input)?;
+        check_r1cs_witness::<F>(&r1cs, &wtns, &sym)?;
+        let result = convert_r1cs_wtns_gkr(&compiled, wtns, sym.clone())?;
+        let output_path = format!("{}{}_output.json", root_path, get_name(input));
+        write_output(output_path, result.1)?;
+        all_inputs.push(result.0);
+    }
+
+    if all_inputs.len() > 1 && !prover_options.zk {
+        println!(
+            "Proving {} inputs in a batch with {}..",
+            all_inputs.len(),
+            P::NAME
+        );
+        let mut batch_inputs: Vec<Vec<Input<F>>> =
+            (0..compiled.circuits.len()).map(|_| vec![]).collect();
+        for inputs in all_inputs {
+            for (i, input) in inputs.into_iter().enumerate() {
+                batch_inputs[i].push(input);
+            }
+        }
+        let now = Instant::now();
+        let proofs = prove_batch_compiled(&compiled, &batch_inputs, hash, pcs)?;
+        let time = report_elapsed(now);
+        println!("{}\n", format!("Proving {}", time).blue().bold());
+        return write_proofs(&root_path, &String::from("batch"), &proofs);
+    }
+    for (input, inputs) in input_paths.iter().zip(all_inputs.iter()) {
+        println!("Proving {} with {}..", input, P::NAME);
+        let now = Instant::now();
+        let proofs = prove_compiled(&compiled, inputs, hash, prover_options, pcs)?;
+        let time = report_elapsed(now);
+        println!("{}\n", format!("Proving {}", time).blue().bold());
+        write_proofs(&root_path, &get_name(input), &proofs)?;
+    }
+    Ok(())
+}
+
+// every layer before the failing one passed, the ones after it were not reached
+fn report_layers(depth: usize, result: &Result<(), VerifyError>) {
+    let failed_at = match result {
+        Ok(()) => depth + 1,
+        Err(VerifyError::InputLayer) | Err(VerifyError::Opening) => depth,
+        Err(e) => e.layer().unwrap_or(0),
+    };
+    for i in 0..(depth + 1) {
+        let name = if i == depth {
+            String::from("input layer")
+        } else {
+            format!("layer {}", i)
+        };
+        if i < failed_at {
+            println!("  {}: {}", name, "pass".green());
+        } else if i == failed_at {
+            let e = result.as_ref().unwrap_err();
+            println!("  {}: {} ({})", name, "fail".red().bold(), e);
+        } else {
+            println!("  {}: {}", name, "not checked".dimmed());
+        }
+    }
+}
+
+/// Verifies the proofs written by `prove_all` or `prove_batch_all`, with `pcs` `Clear`, or by
+/// `prove_committed` with the verifier parameters of its `pcs`, one proof per GKR circuit in
+/// order. The circuit is given as an r1cs file, compiled with the `options` it was proven with
+/// (the defaults if none) through the cache in `out_dir`, or as a compiled circuit from the
+/// cache, which records its options; circom and the witness are not needed. Prints the public
+/// signals the proofs attest to. Returns whether every proof verified; an error means the
+/// circuit could not be loaded.
+pub fn verify_all<F: CircuitField, P: PcsEncoding<F, F>>(
+    r1cs_path: Option<String>,
+    compiled_path: Option<String>,
+    proof_paths: Vec<String>,
+    options: Option<ConversionOptions>,
+    pcs: &P,
+    out_dir: &PathBuf,
+) -> Result<bool, AggregatorError> {
+    let compiled = match (r1cs_path, compiled_path) {
+        (Some(r1cs_path), _) => load_or_compile::<F>(
+            &r1cs_path,
+            &options.unwrap_or_default(),
+            &out_dir.join(CACHE_DIR),
+        )?,
+        (None, Some(compiled_path)) => {
+            let json = std::fs::read_to_string(&compiled_path).map_err(io_error(&compiled_path))?;
+            let compiled = CompiledCircuit::from_json(&json)?;
+            // the compiled circuit records its options, so they need not be given again
+            if let Some(given) = options {
+                if given != compiled.options {
+                    return Err(AggregatorError::OptionsMismatch {
+                        compiled: compiled.options,
+                        given,
+                    });
+                }
+            }
+            compiled
+        }
+        (None, None) => {
+            return Err(AggregatorError::Unsupported(String::from(
+                "either an r1cs or a compiled circuit is needed",
+            )))
+        }
+    };
+    let circuits = &compiled.circuits;
+    if circuits.len() != proof_paths.len() {
+        println!(
+            "{}",
+            format!(
+                "r1cs has {} GKR circuits with {} but {} proofs were given, proofs made with \
+                 other conversion options need them given to verify as well",
+                circuits.len(),
+                compiled.options,
+                proof_paths.len()
+            )
+            .red()
+        );
+        return Ok(false);
+    }
+
+    let mut ok = true;
+    // public signals per copy, which every circuit reading a signal must agree on
+    let mut signals: Vec<BTreeMap<usize, F>> = vec![];
+    for (j, (circuit, path)) in circuits.iter().zip(proof_paths.iter()).enumerate() {
+        println!("{}", path);
+        let proof = match read_proof::<F, P>(path) {
+            Ok(proof) => proof,
+            Err(e) => {
+                println!("  {} ({})", "cannot read proof".red().bold(), e);
+                ok = false;
+                continue;
+            }
+        };
+        let result = verify_batch_with(circuit, pcs, &proof);
+        report_layers(circuit.depth(), &result);
+        if let Err(VerifyError::Malformed(_)) = result {
+            println!(
+                "  {}",
+                format!(
+                    "the proof may be of a circuit compiled with other options than {}",
+                    compiled.options
+                )
+                .red()
+            );
+        }
+        ok &= result.is_ok();
+        if result.is_err() {
+            continue;
         }
+        for (copy, values) in compiled.public_signals(j, &proof).into_iter().enumerate() {
+            if signals.len() <= copy {
+                signals.push(BTreeMap::new());
+            }
+            for (i, value) in values.into_iter() {
+                if *signals[copy].entry(i).or_insert(value) != value {
+                    println!(
+                        "  {}",
+                        format!("public signal {} differs from the previous proofs", i)
+                            .red()
+                            .bold()
+                    );
+                    ok = false;
+                }
+            }
+        }
+    }
+    for (copy, values) in signals.iter().enumerate() {
+        let values: Vec<String> = values
+            .iter()
+            .map(|(i, v)| format!("{} = {}", i, stringify_fr(v)))
+            .collect();
+        println!("public signals of input {}: {}", copy, values.join(", "));
+    }
+    if ok {
+        println!("{}", "Verification passed".green().bold());
+    } else {
+        println!("{}", "Verification failed".red().bold());
     }
+    Ok(ok)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{modify_circom_file, prove_all};
+    use super::{
+        get_meta, modify_circom_file, prove_all, prove_compiled, verify_all, VERIFIER_CIRCUIT,
+    };
+    use crate::{
+        compiled::{CompiledCircuit, InputSource},
+        convert::ConversionOptions,
+        error::AggregatorError,
+        file_utils::{read_kzg_key, write_kzg_keys, write_proofs},
+        gkr::{
+            encoding::PcsEncoding,
+            pcs::{kzg::Kzg, ligero::Ligero, Clear},
+            poly::MultilinearPoly,
+            prover::{self, committed_vars, ProveError, ProverOptions},
+            transcript::HashType,
+            GKRCircuit, Input, Layer, Proof,
+        },
+        workspace::Workspace,
+    };
+    use halo2curves::bn256::Fr;
+    use rand_core::OsRng;
+    use std::path::{Path, PathBuf};
+
+    // a directory under the temp dir, removed when dropped, so even by a failing assert
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("gkr-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
 
     #[test]
     fn test_proving() {
@@ -445,7 +933,218 @@ mod tests {
         input_paths.push(String::from("./example/input1.json"));
         input_paths.push(String::from("./example/input2.json"));
         input_paths.push(String::from("./example/input3.json"));
-        prove_all(circuit_path, input_paths);
+        let workspace = Workspace::new(Path::new(".")).unwrap();
+        prove_all::<Fr>(
+            circuit_path,
+            input_paths,
+            HashType::Mimc7,
+            &Default::default(),
+            &workspace,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verifier_include() {
+        let dir = TempDir::new("include");
+        let out_dir = &dir.0;
+        let workspace = Workspace::new(out_dir).unwrap();
+        let circuit = out_dir.join("c.circom");
+        std::fs::write(&circuit, "pragma circom 2.0.0;\ntemplate A() {\n}\n").unwrap();
+        let path = modify_circom_file(circuit.display().to_string(), &vec![], &workspace).unwrap();
+        let modified = std::fs::read_to_string(path).unwrap();
+        assert!(modified.contains(&format!("include \"{}\";", VERIFIER_CIRCUIT)));
+        assert!(Path::new(VERIFIER_CIRCUIT).is_absolute());
+        assert!(Path::new(VERIFIER_CIRCUIT).exists());
+    }
+
+    #[test]
+    fn test_meta_without_rounds() {
+        // out = in + in over a single input gate, so no sumcheck has a round to pad to
+        let layer = Layer::from_wires(0, 0, (vec![vec![]], vec![]));
+        let circuit = GKRCircuit::new(vec![layer], 0);
+        let values = vec![vec![Fr::zero()], vec![Fr::zero()]];
+        let input = Input {
+            w: values.into_iter().map(MultilinearPoly::new).collect(),
+        };
+        let proof = prover::prove(&circuit, &input, HashType::Keccak256).unwrap();
+        assert!(matches!(
+            get_meta(&vec![proof]),
+            Err(AggregatorError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_recorded_options() {
+        let dir = TempDir::new("options");
+        let out_dir = &dir.0;
+        let compiled = CompiledCircuit::<Fr> {
+            circuits: vec![],
+            inputs: vec![],
+            n_public: 0,
+            options: ConversionOptions::single_circuit(),
+        };
+        let path = out_dir.join("compiled.json");
+        std::fs::write(&path, compiled.to_json()).unwrap();
+        let path = Some(path.display().to_string());
+
+        // read back from the compiled circuit, and any other options given are rejected
+        assert!(verify_all::<Fr, _>(None, path.clone(), vec![], None, &Clear, out_dir).unwrap());
+        let given = Some(ConversionOptions::single_circuit());
+        assert!(verify_all::<Fr, _>(None, path.clone(), vec![], given, &Clear, out_dir).unwrap());
+        let given = Some(ConversionOptions::default());
+        assert!(matches!(
+            verify_all::<Fr, _>(None, path, vec![], given, &Clear, out_dir),
+            Err(AggregatorError::OptionsMismatch { .. })
+        ));
+    }
+
+    fn bits(s: &str) -> Vec<Fr> {
+        s.chars()
+            .map(|c| if c == '1' { Fr::one() } else { Fr::zero() })
+            .collect()
+    }
+
+    // out[0] = x * y + c with x public, as written by the compiler
+    fn example_compiled() -> CompiledCircuit<Fr> {
+        CompiledCircuit {
+            circuits: vec![GKRCircuit::with_public(
+                vec![
+                    Layer::from_wires(1, 1, (vec![bits("001")], vec![])),
+                    Layer::from_wires(1, 2, (vec![bits("11011")], vec![bits("00001")])),
+                ],
+                2,
+                vec![0],
+            )],
+            inputs: vec![vec![
+                InputSource::Witness(1),
+                InputSource::Witness(2),
+                InputSource::Witness(3),
+                InputSource::Constant(Fr::zero()),
+            ]],
+            n_public: 1,
+            options: ConversionOptions::single_circuit(),
+        }
+    }
+
+    // the example circuit written as compiled.json, and its input for x * y = 6, whose proofs
+    // are written and verified through files as prove and verify do
+    struct RoundTrip {
+        dir: TempDir,
+        compiled: CompiledCircuit<Fr>,
+        compiled_path: Option<String>,
+        inputs: Vec<Input<Fr>>,
+    }
+
+    impl RoundTrip {
+        fn new(name: &str) -> Self {
+            let dir = TempDir::new(name);
+            let compiled = example_compiled();
+            let compiled_path = dir.0.join("compiled.json");
+            std::fs::write(&compiled_path, compiled.to_json()).unwrap();
+            let witness = vec![Fr::one(), Fr::from(2), Fr::from(3), -Fr::from(6)];
+            let inputs = compiled.assign(&witness).unwrap();
+            RoundTrip {
+                dir,
+                compiled,
+                compiled_path: Some(compiled_path.display().to_string()),
+                inputs,
+            }
+        }
+
+        fn prove<P>(
+            &self,
+            pcs: &P,
+            hash: HashType,
+            options: &ProverOptions,
+        ) -> Result<Vec<Proof<Fr, Fr, P>>, AggregatorError>
+        where
+            P: PcsEncoding<Fr, Fr> + Sync,
+            P::Commitment: Send,
+            P::Opening: Send,
+        {
+            prove_compiled(&self.compiled, &self.inputs, hash, options, pcs)
+        }
+
+        // the paths of the proofs written as `name`_proof<i>.json
+        fn write<P: PcsEncoding<Fr, Fr>>(
+            &self,
+            name: &str,
+            proofs: &Vec<Proof<Fr, Fr, P>>,
+        ) -> Vec<String> {
+            let root_path = format!("{}/", self.dir.0.display());
+            write_proofs(&root_path, &String::from(name), proofs).unwrap();
+            (0..proofs.len())
+                .map(|i| format!("{}{}_proof{}.json", root_path, name, i))
+                .collect()
+        }
+
+        fn verify<P: PcsEncoding<Fr, Fr>>(&self, pcs: &P, paths: &[String]) -> bool {
+            let compiled_path = self.compiled_path.clone();
+            verify_all::<Fr, _>(None, compiled_path, paths.to_vec(), None, pcs, &self.dir.0)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn test_kzg_round_trip() {
+        let fixture = RoundTrip::new("kzg");
+
+        // as setup, prove and verify do, through the key and proof files
+        let (prover_key, verifier_key) =
+            write_kzg_keys(&fixture.dir.0, &Kzg::setup(2, OsRng)).unwrap();
+        let prover_key = prover_key.display().to_string();
+        let verifier_key = verifier_key.display().to_string();
+        assert!(read_kzg_key(&verifier_key, true).is_err());
+        let pk = read_kzg_key(&prover_key, true).unwrap();
+        let options = ProverOptions::default();
+        let proofs = fixture.prove(&pk, HashType::Keccak256, &options).unwrap();
+        let proof_paths = fixture.write("input", &proofs);
+        let vk = read_kzg_key(&verifier_key, false).unwrap();
+        assert!(fixture.verify(&vk, &proof_paths));
+        // with the keys of another setup, or read as a proof in the clear
+        assert!(!fixture.verify(&Kzg::setup(2, OsRng).verifier_key(), &proof_paths));
+        assert!(!fixture.verify(&Clear, &proof_paths));
+
+        // a key too small for the circuit is an error rather than a panic
+        let small = Kzg::setup(1, OsRng);
+        assert!(matches!(
+            fixture.prove(&small, HashType::Keccak256, &options),
+            Err(AggregatorError::Unsupported(_))
+        ));
+
+        // in zero knowledge, with a key large enough for the masks
+        let zk = ProverOptions { zk: true };
+        let kzg = Kzg::setup(committed_vars(&fixture.compiled.circuits[0], &zk), OsRng);
+        assert!(matches!(
+            fixture.prove(&pk, HashType::Keccak256, &zk),
+            Err(AggregatorError::Unsupported(_))
+        ));
+        let proofs = fixture.prove(&kzg, HashType::Keccak256, &zk).unwrap();
+        assert!(!proofs[0].masks.is_empty());
+        let zk_paths = fixture.write("zk", &proofs);
+        assert!(fixture.verify(&kzg.verifier_key(), &zk_paths));
+        assert!(!fixture.verify(&vk, &zk_paths));
+    }
+
+    #[test]
+    fn test_ligero_round_trip() {
+        let fixture = RoundTrip::new("ligero");
+        let ligero = Ligero::default();
+        let options = ProverOptions::default();
+        let proofs = fixture
+            .prove(&ligero, HashType::Poseidon, &options)
+            .unwrap();
+        let proof_paths = fixture.write("input", &proofs);
+        assert!(fixture.verify(&ligero, &proof_paths));
+        // the verifier fixes the parameters, and other ones expect other columns
+        assert!(!fixture.verify(&Ligero::new(1, 128), &proof_paths));
+        // which hide nothing, so zero knowledge is refused
+        let zk = ProverOptions { zk: true };
+        assert!(matches!(
+            fixture.prove(&ligero, HashType::Poseidon, &zk),
+            Err(AggregatorError::Prove(ProveError::NotHiding))
+        ));
     }
 
     #[test]
@@ -453,6 +1152,14 @@ mod tests {
         let circuit_path = String::from("./t.circom");
         let mut input_paths = vec![];
         input_paths.push(String::from("./example/input1.json"));
-        prove_all(circuit_path, input_paths);
+        let workspace = Workspace::new(Path::new(".")).unwrap();
+        prove_all::<Fr>(
+            circuit_path,
+            input_paths,
+            HashType::Mimc7,
+            &Default::default(),
+            &workspace,
+        )
+        .unwrap();
     }
 }
@@ -1,9 +1,20 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::io::{self, Write};
-use std::{io::Result, process::Command};
+use std::{io::Result, path::PathBuf, process::Command};
 
 extern crate gkr;
-use gkr::aggregator::prove_all;
+use gkr::aggregator::{
+    prove_all, prove_batch_all, prove_committed, read_kzg_key, verify_all, write_kzg_keys,
+};
+use gkr::convert::ConversionOptions;
+use gkr::error::AggregatorError;
+use gkr::field::FieldType;
+use gkr::gkr::pcs::{kzg::Kzg, ligero::Ligero, Clear, PcsType};
+use gkr::gkr::prover::ProverOptions;
+use gkr::gkr::transcript::HashType;
+use gkr::workspace::Workspace;
+use halo2curves::bn256;
+use rand_core::OsRng;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,6 +23,72 @@ struct Cli {
     command: Option<Commands>,
 }
 
+/// How the r1cs is converted into GKR circuits; verifying against the r1cs needs the options
+/// prove was run with, a compiled circuit records them
+#[derive(Args, Debug)]
+struct ConversionArgs {
+    /// Inline a signal only if its definition is shallower than this [default: 10]
+    #[arg(long)]
+    depth_limit: Option<usize>,
+    /// Pack the constraints into at most this many GKR circuits [default: 20]
+    #[arg(long)]
+    width_limit: Option<usize>,
+    /// Pack every constraint into a single GKR circuit
+    #[arg(long, conflicts_with = "width_limit")]
+    single_circuit: bool,
+}
+
+impl ConversionArgs {
+    // None if no option was given
+    fn given(&self) -> Option<ConversionOptions> {
+        if self.depth_limit.is_none() && self.width_limit.is_none() && !self.single_circuit {
+            return None;
+        }
+        Some(self.options())
+    }
+
+    fn options(&self) -> ConversionOptions {
+        let default = ConversionOptions::default();
+        ConversionOptions {
+            depth_limit: self.depth_limit.unwrap_or(default.depth_limit),
+            width_limit: if self.single_circuit {
+                1
+            } else {
+                self.width_limit.unwrap_or(default.width_limit)
+            },
+        }
+    }
+}
+
+/// How the input layer is given to the verifier
+#[derive(Args, Debug)]
+struct PcsArgs {
+    /// Commitment to the input layer: clear, kzg over bn254, or ligero, which needs no setup.
+    /// Committed proofs are checked natively rather than aggregated in circom
+    #[arg(long, default_value_t = PcsType::Clear)]
+    pcs: PcsType,
+    /// KZG key written by setup: the prover key to prove, either key to verify
+    #[arg(long)]
+    key: Option<String>,
+}
+
+impl PcsArgs {
+    fn kzg(&self, field: FieldType, prover: bool) -> std::result::Result<Kzg, AggregatorError> {
+        if field != FieldType::Bn254 {
+            return Err(AggregatorError::Unsupported(format!(
+                "kzg commits over bn254, not {}",
+                field
+            )));
+        }
+        match &self.key {
+            Some(key) => read_kzg_key(key, prover),
+            None => Err(AggregatorError::Unsupported(String::from(
+                "kzg needs the --key written by setup",
+            ))),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     Prove {
@@ -19,10 +96,63 @@ enum Commands {
         circuit: String,
         #[arg(short, long, num_args=0..)]
         inputs: Vec<String>,
+        /// Fiat-Shamir hash: mimc7, poseidon or keccak256
+        #[arg(long, default_value_t = HashType::Mimc7)]
+        hash: HashType,
+        /// Field the circuit is compiled for: bn254, bls12-381, pallas or vesta
+        #[arg(long, default_value_t = FieldType::Bn254)]
+        field: FieldType,
+        /// Prove all inputs in one data-parallel GKR proof instead of recursively, which a
+        /// committed --pcs always does for several inputs
+        #[arg(long)]
+        batch: bool,
+        /// Zero knowledge, as in Libra: the proofs reveal nothing of the witness but the public
+        /// signals. Needs --pcs kzg, whose setup must allow the masks, see the error of prove
+        #[arg(long)]
+        zk: bool,
+        /// Directory for the compiled circuit cache and a new subdirectory per run
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        pcs: PcsArgs,
+    },
+    /// Sample a KZG setup and write its prover and verifier keys. Whoever runs it can forge
+    /// proofs, so it must be run by the verifier or a party it trusts, never by the prover
+    Setup {
+        /// Most variables of an input layer, see the error of prove for a circuit too large
+        #[arg(long)]
+        max_vars: usize,
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    /// Verify GKR proofs natively against the r1cs they were made for
+    Verify {
+        #[arg(short, long, required_unless_present = "compiled")]
+        r1cs: Option<String>,
+        /// Compiled circuit from the .gkr_cache directory, instead of the r1cs
+        #[arg(long, conflicts_with = "r1cs")]
+        compiled: Option<String>,
+        /// Proof files written by prove, one per GKR circuit, in order
+        #[arg(short, long, num_args = 1..)]
+        proofs: Vec<String>,
+        #[arg(long, default_value_t = FieldType::Bn254)]
+        field: FieldType,
+        /// Directory holding the compiled circuit cache
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        pcs: PcsArgs,
     },
     MockGroth {
         #[arg(short, long)]
         zkey: String,
+        /// Run directory of the prove that made aggregated.r1cs and witness.wtns
+        #[arg(long, default_value = ".")]
+        run_dir: PathBuf,
     },
 }
 
@@ -30,17 +160,151 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Some(Commands::Prove { circuit, inputs }) => {
+        Some(Commands::Prove {
+            circuit,
+            inputs,
+            hash,
+            field,
+            batch,
+            zk,
+            out_dir,
+            conversion,
+            pcs,
+        }) => {
             let circuit_path = circuit.clone();
             let input_paths = inputs.clone();
-            prove_all(circuit_path, input_paths);
+            let workspace = match Workspace::new(&out_dir) {
+                Ok(workspace) => workspace,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let options = conversion.options();
+            let prover_options = ProverOptions { zk };
+            println!("Writing to {}", workspace.run_dir().display());
+            // proofs with a committed input layer are verified natively, in one batch for several inputs
+            let result = match (pcs.pcs, batch) {
+                _ if zk && pcs.pcs != PcsType::Kzg => Err(AggregatorError::Unsupported(
+                    String::from("--zk needs a hiding commitment, --pcs kzg"),
+                )),
+                (PcsType::Clear, _) => {
+                    let prove = match (field, batch) {
+                        (FieldType::Bn254, false) => prove_all::<bn256::Fr>,
+                        (FieldType::Bn254, true) => prove_batch_all::<bn256::Fr>,
+                        (FieldType::Bls12_381, false) => prove_all::<bls12_381::Scalar>,
+                        (FieldType::Bls12_381, true) => prove_batch_all::<bls12_381::Scalar>,
+                        (FieldType::Pallas, false) => prove_all::<pasta_curves::Fp>,
+                        (FieldType::Pallas, true) => prove_batch_all::<pasta_curves::Fp>,
+                        (FieldType::Vesta, false) => prove_all::<pasta_curves::Fq>,
+                        (FieldType::Vesta, true) => prove_batch_all::<pasta_curves::Fq>,
+                    };
+                    prove(circuit_path, input_paths, hash, &options, &workspace)
+                }
+                (PcsType::Kzg, _) => pcs.kzg(field, true).and_then(|kzg| {
+                    prove_committed::<bn256::Fr, _>(
+                        circuit_path,
+                        input_paths,
+                        hash,
+                        &options,
+                        &prover_options,
+                        &kzg,
+                        &workspace,
+                    )
+                }),
+                (PcsType::Ligero, _) => {
+                    let prove = match field {
+                        FieldType::Bn254 => prove_committed::<bn256::Fr, Ligero>,
+                        FieldType::Bls12_381 => prove_committed::<bls12_381::Scalar, Ligero>,
+                        FieldType::Pallas => prove_committed::<pasta_curves::Fp, Ligero>,
+                        FieldType::Vesta => prove_committed::<pasta_curves::Fq, Ligero>,
+                    };
+                    let ligero = Ligero::default();
+                    prove(
+                        circuit_path,
+                        input_paths,
+                        hash,
+                        &options,
+                        &prover_options,
+                        &ligero,
+                        &workspace,
+                    )
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Verify {
+            r1cs,
+            compiled,
+            proofs,
+            field,
+            out_dir,
+            conversion,
+            pcs,
+        }) => {
+            let options = conversion.given();
+            let ligero = Ligero::default();
+            let result = match (pcs.pcs, field) {
+                (PcsType::Clear, FieldType::Bn254) => {
+                    verify_all::<bn256::Fr, _>(r1cs, compiled, proofs, options, &Clear, &out_dir)
+                }
+                (PcsType::Clear, FieldType::Bls12_381) => verify_all::<bls12_381::Scalar, _>(
+                    r1cs, compiled, proofs, options, &Clear, &out_dir,
+                ),
+                (PcsType::Clear, FieldType::Pallas) => verify_all::<pasta_curves::Fp, _>(
+                    r1cs, compiled, proofs, options, &Clear, &out_dir,
+                ),
+                (PcsType::Clear, FieldType::Vesta) => verify_all::<pasta_curves::Fq, _>(
+                    r1cs, compiled, proofs, options, &Clear, &out_dir,
+                ),
+                (PcsType::Kzg, _) => pcs.kzg(field, false).and_then(|kzg| {
+                    verify_all::<bn256::Fr, _>(r1cs, compiled, proofs, options, &kzg, &out_dir)
+                }),
+                (PcsType::Ligero, FieldType::Bn254) => {
+                    verify_all::<bn256::Fr, _>(r1cs, compiled, proofs, options, &ligero, &out_dir)
+                }
+                (PcsType::Ligero, FieldType::Bls12_381) => verify_all::<bls12_381::Scalar, _>(
+                    r1cs, compiled, proofs, options, &ligero, &out_dir,
+                ),
+                (PcsType::Ligero, FieldType::Pallas) => verify_all::<pasta_curves::Fp, _>(
+                    r1cs, compiled, proofs, options, &ligero, &out_dir,
+                ),
+                (PcsType::Ligero, FieldType::Vesta) => verify_all::<pasta_curves::Fq, _>(
+                    r1cs, compiled, proofs, options, &ligero, &out_dir,
+                ),
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Setup { max_vars, out_dir }) => {
+            println!("Sampling a kzg setup for {} variables..", max_vars);
+            match write_kzg_keys(&out_dir, &Kzg::setup(max_vars, OsRng)) {
+                Ok((prover, verifier)) => {
+                    println!("prover key: {}", prover.display());
+                    println!("verifier key: {}", verifier.display());
+                    println!("the trapdoor is not written; hand the prover only its key");
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
         }
-        Some(Commands::MockGroth { zkey }) => {
+        Some(Commands::MockGroth { zkey, run_dir }) => {
             println!("mock groth16 running..");
             let output = Command::new("snarkjs")
                 .arg("zkey")
                 .arg("verify")
-                .arg("aggregated.r1cs")
+                .arg(run_dir.join("aggregated.r1cs"))
                 .arg("pot.ptau")
                 .arg(zkey.clone())
                 .output()
@@ -50,9 +314,9 @@ fn main() -> Result<()> {
                 .arg("groth16")
                 .arg("prove")
                 .arg(zkey.clone())
-                .arg("witness.wtns")
-                .arg("proof.json")
-                .arg("public.json")
+                .arg(run_dir.join("witness.wtns"))
+                .arg(run_dir.join("proof.json"))
+                .arg(run_dir.join("public.json"))
                 .output()
                 .expect("proving failed");
             std::io::stdout().write_all(&output.stdout).unwrap();
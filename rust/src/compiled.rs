@@ -0,0 +1,419 @@
+use crate::convert::{compile_r1cs, ConversionOptions};
+use crate::error::{io_error, AggregatorError};
+use crate::field::CircuitField;
+use crate::gkr::{
+    encoding::{DecodeError, ProofField},
+    pcs::Pcs,
+    poly::{bits_to_index, MultilinearPoly},
+    GKRCircuit, Input, Layer, Proof,
+};
+use ethers_core::utils::keccak256;
+use r1cs_file::R1csFile;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// Version of the compiled circuit encoding. A cached circuit of another version is recompiled.
+pub const COMPILED_VERSION: u32 = 2;
+
+/// Where an input gate takes its value from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputSource<F: CircuitField> {
+    Constant(F),
+    /// Index into the witness, as in the wtns file.
+    Witness(usize),
+}
+
+/// Witness-independent result of converting an r1cs: the GKR circuits, and for each of them
+/// the source of every input gate. Made once per r1cs, then assigned to every witness.
+pub struct CompiledCircuit<F: CircuitField> {
+    pub circuits: Vec<GKRCircuit<F>>,
+    pub inputs: Vec<Vec<InputSource<F>>>,
+    /// Number of public inputs and outputs of the r1cs.
+    pub n_public: usize,
+    /// How the r1cs was converted, which verifying against the r1cs has to repeat.
+    pub options: ConversionOptions,
+}
+
+/// Input gates that read a public input or output, which are signals 1..=n_public.
+pub fn public_gates<F: CircuitField>(sources: &Vec<InputSource<F>>, n_public: usize) -> Vec<usize> {
+    let mut res = vec![];
+    for (gate, source) in sources.iter().enumerate() {
+        if let InputSource::Witness(i) = source {
+            if *i >= 1 && *i <= n_public {
+                res.push(gate);
+            }
+        }
+    }
+    res
+}
+
+// evaluates the layers from the input up, every gate not wired is zero
+fn evaluate<F: CircuitField>(
+    circuit: &GKRCircuit<F>,
+    input: Vec<F>,
+) -> Result<Input<F>, AggregatorError> {
+    let mut w_values = vec![input];
+    for layer in circuit.layer.iter().rev() {
+        let next = &w_values[w_values.len() - 1];
+        let mut values = vec![F::zero(); 1 << layer.k];
+        let gate = |wire: &Vec<F>| {
+            let k_next = (wire.len() - layer.k) / 2;
+            (
+                bits_to_index(&wire[..layer.k]),
+                bits_to_index(&wire[layer.k..layer.k + k_next]),
+                bits_to_index(&wire[layer.k + k_next..]),
+            )
+        };
+        for wire in layer.wire.0.iter() {
+            let (curr, left, right) = gate(wire);
+            values[curr] = next[left] + next[right];
+        }
+        for wire in layer.wire.1.iter() {
+            let (curr, left, right) = gate(wire);
+            values[curr] = next[left] * next[right];
+        }
+        w_values.push(values);
+    }
+    w_values.reverse();
+
+    // check constraint, every output gate is a constraint or padding
+    for (i, d_value) in w_values[0].iter().enumerate() {
+        if *d_value != F::zero() {
+            return Err(AggregatorError::WitnessMismatch(format!(
+                "output gate {} is not zero",
+                i
+            )));
+        }
+    }
+
+    let w = w_values.into_iter().map(MultilinearPoly::new).collect();
+    Ok(Input { w })
+}
+
+impl<F: CircuitField> CompiledCircuit<F> {
+    /// The values of every layer of every circuit on a witness. Fails if the witness is too
+    /// short for the circuit or does not satisfy it.
+    pub fn assign(&self, witness: &Vec<F>) -> Result<Vec<Input<F>>, AggregatorError> {
+        let mut res = vec![];
+        for (j, (circuit, sources)) in self.circuits.iter().zip(self.inputs.iter()).enumerate() {
+            let mut input = vec![];
+            for source in sources.iter() {
+                input.push(match source {
+                    InputSource::Constant(c) => *c,
+                    InputSource::Witness(i) => *witness.get(*i).ok_or_else(|| {
+                        AggregatorError::WitnessMismatch(format!(
+                            "witness has {} signals, circuit {} reads signal {}",
+                            witness.len(),
+                            j,
+                            i
+                        ))
+                    })?,
+                });
+            }
+            let input = evaluate(circuit, input).map_err(|e| match e {
+                AggregatorError::WitnessMismatch(reason) => {
+                    AggregatorError::WitnessMismatch(format!("circuit {}: {}", j, reason))
+                }
+                e => e,
+            })?;
+            res.push(input);
+        }
+        Ok(res)
+    }
+
+    /// Public signals a verified proof of circuit j attests to, one map from signal to value
+    /// per copy in the proof.
+    pub fn public_signals<P: Pcs<F, F>>(
+        &self,
+        j: usize,
+        proof: &Proof<F, F, P>,
+    ) -> Vec<BTreeMap<usize, F>> {
+        let input_k = self.circuits[j].k(self.circuits[j].depth());
+        let mut res = vec![];
+        for (pos, value) in proof.public.iter() {
+            let copy = pos >> input_k;
+            while res.len() <= copy {
+                res.push(BTreeMap::new());
+            }
+            if let Some(InputSource::Witness(i)) = self.inputs[j].get(pos & ((1 << input_k) - 1)) {
+                res[copy].insert(*i, *value);
+            }
+        }
+        res
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonCompiled {
+    version: u32,
+    field: String,
+    n_public: usize,
+    depth_limit: usize,
+    width_limit: usize,
+    circuits: Vec<JsonCircuit>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonCircuit {
+    input_k: usize,
+    layers: Vec<JsonLayer>,
+    inputs: Vec<JsonSource>,
+}
+
+// wires as bit strings, curr || left || right
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonLayer {
+    k: usize,
+    add: Vec<String>,
+    mult: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonSource {
+    Constant(String),
+    Witness(usize),
+}
+
+fn wire_to_string<F: CircuitField>(wire: &Vec<F>) -> String {
+    wire.iter()
+        .map(|b| if *b == F::one() { '1' } else { '0' })
+        .collect()
+}
+
+fn wires_from_strings<F: CircuitField>(
+    wires: &Vec<String>,
+    len: usize,
+) -> Result<Vec<Vec<F>>, DecodeError> {
+    wires
+        .iter()
+        .map(|s| {
+            if s.len() != len {
+                return Err(DecodeError::Length(format!(
+                    "wire {} should have {} bits",
+                    s, len
+                )));
+            }
+            s.chars()
+                .map(|c| match c {
+                    '0' => Ok(F::zero()),
+                    '1' => Ok(F::one()),
+                    _ => Err(DecodeError::Format(format!("bad wire {}", s))),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl<F: CircuitField> CompiledCircuit<F> {
+    /// JSON encoding with a header of the version and field, as stored in the cache.
+    pub fn to_json(&self) -> String {
+        let circuits = self
+            .circuits
+            .iter()
+            .zip(self.inputs.iter())
+            .map(|(circuit, sources)| JsonCircuit {
+                input_k: circuit.k(circuit.depth()),
+                layers: circuit
+                    .layer
+                    .iter()
+                    .map(|layer| JsonLayer {
+                        k: layer.k,
+                        add: layer.wire.0.iter().map(wire_to_string).collect(),
+                        mult: layer.wire.1.iter().map(wire_to_string).collect(),
+                    })
+                    .collect(),
+                inputs: sources
+                    .iter()
+                    .map(|source| match source {
+                        InputSource::Constant(c) => JsonSource::Constant(hex::encode(c.to_bytes())),
+                        InputSource::Witness(i) => JsonSource::Witness(*i),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let json = JsonCompiled {
+            version: COMPILED_VERSION,
+            field: String::from(F::NAME),
+            n_public: self.n_public,
+            depth_limit: self.options.depth_limit,
+            width_limit: self.options.width_limit,
+            circuits,
+        };
+        serde_json::to_string(&json).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DecodeError> {
+        let json: JsonCompiled =
+            serde_json::from_str(json).map_err(|e| DecodeError::Format(e.to_string()))?;
+        if json.version != COMPILED_VERSION {
+            return Err(DecodeError::Version {
+                expected: COMPILED_VERSION,
+                got: json.version,
+            });
+        }
+        if json.field != F::NAME {
+            return Err(DecodeError::Field {
+                expected: String::from(F::NAME),
+                got: json.field,
+            });
+        }
+
+        let mut circuits = vec![];
+        let mut inputs = vec![];
+        for c in json.circuits.iter() {
+            if c.layers.is_empty() || c.input_k >= 32 || c.inputs.len() != 1 << c.input_k {
+                return Err(DecodeError::Length(String::from("bad input layer")));
+            }
+            let mut layers = vec![];
+            for (i, layer) in c.layers.iter().enumerate() {
+                let k_next = match c.layers.get(i + 1) {
+                    Some(next) => next.k,
+                    None => c.input_k,
+                };
+                if layer.k >= 32 || k_next >= 32 {
+                    return Err(DecodeError::Length(format!("layer {} is too large", i)));
+                }
+                let len = layer.k + 2 * k_next;
+                let wire = (
+                    wires_from_strings(&layer.add, len)?,
+                    wires_from_strings(&layer.mult, len)?,
+                );
+                layers.push(Layer::from_wires(layer.k, k_next, wire));
+            }
+
+            let mut sources = vec![];
+            for source in c.inputs.iter() {
+                sources.push(match source {
+                    JsonSource::Constant(s) => {
+                        let bytes = hex::decode(s).map_err(|_| DecodeError::Element)?;
+                        InputSource::Constant(F::from_bytes(&bytes).ok_or(DecodeError::Element)?)
+                    }
+                    JsonSource::Witness(i) => InputSource::Witness(*i),
+                });
+            }
+            let public = public_gates(&sources, json.n_public);
+            circuits.push(GKRCircuit::with_public(layers, c.input_k, public));
+            inputs.push(sources);
+        }
+        Ok(CompiledCircuit {
+            circuits,
+            inputs,
+            n_public: json.n_public,
+            options: ConversionOptions {
+                depth_limit: json.depth_limit,
+                width_limit: json.width_limit,
+            },
+        })
+    }
+}
+
+/// Hex keccak256 of an r1cs file, which names its compiled circuit in the cache.
+pub fn r1cs_hash(r1cs_bytes: &[u8]) -> String {
+    hex::encode(keccak256(r1cs_bytes))
+}
+
+/// The compiled circuit of an r1cs file from `cache_dir`, compiling and caching it on a miss.
+/// Entries are keyed by the hash of the r1cs and by the options, so a changed circuit is never
+/// served stale.
+pub fn load_or_compile<F: CircuitField>(
+    r1cs_path: &String,
+    options: &ConversionOptions,
+    cache_dir: &PathBuf,
+) -> Result<CompiledCircuit<F>, AggregatorError> {
+    let bytes = fs::read(r1cs_path).map_err(io_error(r1cs_path))?;
+    let path = cache_dir.join(format!(
+        "{}_{}{}.json",
+        r1cs_hash(&bytes),
+        F::NAME,
+        options.cache_suffix()
+    ));
+    if let Ok(json) = fs::read_to_string(&path) {
+        match CompiledCircuit::from_json(&json) {
+            Ok(compiled) => {
+                println!("Using compiled circuit {}", path.display());
+                return Ok(compiled);
+            }
+            Err(e) => println!(
+                "Recompiling, cached circuit {} is unusable: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    let r1cs =
+        R1csFile::<32>::read(&bytes[..]).map_err(|e| AggregatorError::R1cs(e.to_string()))?;
+    let compiled = compile_r1cs::<F>(&r1cs, options)?;
+    fs::create_dir_all(cache_dir).map_err(io_error(cache_dir.display()))?;
+    // written aside and renamed, so a concurrent run never reads half a circuit
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, compiled.to_json()).map_err(io_error(tmp.display()))?;
+    fs::rename(&tmp, &path).map_err(io_error(path.display()))?;
+    Ok(compiled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompiledCircuit, InputSource};
+    use crate::convert::ConversionOptions;
+    use crate::gkr::{prover, transcript::HashType, GKRCircuit, Layer};
+    use halo2curves::bn256::Fr;
+
+    fn bits(s: &str) -> Vec<Fr> {
+        s.chars()
+            .map(|c| if c == '1' { Fr::one() } else { Fr::zero() })
+            .collect()
+    }
+
+    #[test]
+    fn test_assign_round_trip() {
+        // out[0] = x * y + c, with a zero padding gate next to it, and x public
+        let compiled = CompiledCircuit {
+            circuits: vec![GKRCircuit::with_public(
+                vec![
+                    Layer::from_wires(1, 1, (vec![bits("001")], vec![])),
+                    Layer::from_wires(1, 2, (vec![bits("11011")], vec![bits("00001")])),
+                ],
+                2,
+                vec![0],
+            )],
+            inputs: vec![vec![
+                InputSource::Witness(1),
+                InputSource::Witness(2),
+                InputSource::Witness(3),
+                InputSource::Constant(Fr::zero()),
+            ]],
+            n_public: 1,
+            options: ConversionOptions::single_circuit(),
+        };
+        let decoded = CompiledCircuit::<Fr>::from_json(&compiled.to_json()).unwrap();
+        assert_eq!(decoded.to_json(), compiled.to_json());
+        assert_eq!(decoded.circuits[0].public(), &vec![0]);
+        assert_eq!(decoded.options, ConversionOptions::single_circuit());
+
+        let witness = vec![
+            Fr::one(),
+            Fr::from(2),
+            Fr::from(3),
+            Fr::zero() - Fr::from(6),
+        ];
+        let inputs = decoded.assign(&witness).unwrap();
+        assert_eq!(
+            inputs[0].w(1).evals,
+            vec![Fr::from(6), Fr::zero() - Fr::from(6)]
+        );
+        assert_eq!(inputs[0].w(0).evals, vec![Fr::zero(), Fr::zero()]);
+        let proof = prover::prove(&decoded.circuits[0], &inputs[0], HashType::Keccak256).unwrap();
+        let signals = decoded.public_signals(0, &proof);
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].get(&1), Some(&Fr::from(2)));
+
+        let unsatisfied = vec![Fr::one(), Fr::from(2), Fr::from(3), Fr::zero()];
+        assert!(decoded.assign(&unsatisfied).is_err());
+        assert!(decoded.assign(&witness[..3].to_vec()).is_err());
+    }
+}
@@ -1,14 +1,70 @@
 use r1cs_file::{Constraint, FieldElement, R1csFile};
 use wtns_file::*;
 
-use crate::gkr::{poly::*, GKRCircuit, Input, Layer};
-use halo2curves::bn256::Fr;
+use crate::compiled::{public_gates, CompiledCircuit, InputSource};
+use crate::error::{io_error, AggregatorError};
+use crate::field::{modulus_le, CircuitField};
+use crate::file_utils::stringify_fr;
+use crate::gkr::{GKRCircuit, Input, Layer};
 use halo2curves::group::ff::PrimeField;
 use rayon::prelude::*;
-use std::{collections::HashMap, fmt::Debug, fs::File, io::Read, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    hash::Hash,
+    io::Read,
+};
 
-const DEPTH_LIMIT: usize = 10;
-const WIDTH_LIMIT: usize = 20;
+/// How constraints are turned into GKR circuits. Deeper substitutions and fewer, wider
+/// circuits mean fewer proofs, each deeper and with a larger verifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConversionOptions {
+    /// A signal is replaced by its definition only if the definition is shallower than this,
+    /// so 0 turns inlining off.
+    pub depth_limit: usize,
+    /// Constraints are packed into at most this many GKR circuits.
+    pub width_limit: usize,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        ConversionOptions {
+            depth_limit: 10,
+            width_limit: 20,
+        }
+    }
+}
+
+impl ConversionOptions {
+    /// Packs every constraint into a single GKR circuit.
+    pub fn single_circuit() -> Self {
+        ConversionOptions {
+            width_limit: 1,
+            ..Default::default()
+        }
+    }
+
+    // suffix of the cached circuit, empty for the default options
+    pub(crate) fn cache_suffix(&self) -> String {
+        if *self == ConversionOptions::default() {
+            String::new()
+        } else {
+            format!("_d{}_w{}", self.depth_limit, self.width_limit)
+        }
+    }
+}
+
+// as the command line flags that select them
+impl fmt::Display for ConversionOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "--depth-limit {} --width-limit {}",
+            self.depth_limit, self.width_limit
+        )
+    }
+}
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 enum Expression<T> {
@@ -16,87 +72,89 @@ enum Expression<T> {
     Variable(u32),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 enum NodeType<T> {
     Mult,
     Add,
     Value(Expression<T>),
 }
 
-#[derive(Clone)]
+/// Index of a node in a `NodeArena`.
+type NodeId = usize;
+
+/// A gate over two earlier nodes of the arena, or a leaf.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 struct IntermediateNode<T> {
     node_type: NodeType<T>,
-    left: Option<Box<IntermediateNode<T>>>,
-    right: Option<Box<IntermediateNode<T>>>,
+    left: Option<NodeId>,
+    right: Option<NodeId>,
 }
 
-impl<T: PartialEq + Debug> PartialEq for IntermediateNode<T> {
-    fn eq(&self, other: &IntermediateNode<T>) -> bool {
-        let mut property = self.node_type == other.node_type;
-        if !((self.left.is_some() == other.left.is_some())
-            && (self.right.is_some() == self.right.is_some()))
-        {
-            return false;
-        }
-        if self.left.is_some() && other.left.is_some() {
-            property &= self.left.as_ref().unwrap().deref().eq(other
-                .left
-                .as_ref()
-                .unwrap()
-                .deref());
-        }
-        if self.right.is_some() && other.right.is_some() {
-            property &= self.right.as_ref().unwrap().deref().eq(other
-                .right
-                .as_ref()
-                .unwrap()
-                .deref());
+/// The constraints as one DAG. Nodes are interned by their type and operand ids, so equal
+/// subexpressions share an id, and comparing two nodes is comparing their ids.
+struct NodeArena<T> {
+    nodes: Vec<IntermediateNode<T>>,
+    depths: Vec<usize>,
+    ids: HashMap<IntermediateNode<T>, NodeId>,
+}
+
+impl<T: Copy + Hash + Eq> NodeArena<T> {
+    fn new() -> Self {
+        NodeArena {
+            nodes: vec![],
+            depths: vec![],
+            ids: HashMap::new(),
         }
-        return property;
     }
-}
 
-impl<T: Copy> IntermediateNode<T> {
-    fn copy(&self) -> Self {
-        IntermediateNode {
-            node_type: self.node_type,
-            left: self.left.as_ref().map(|node| Box::new(node.copy())),
-            right: self.right.as_ref().map(|node| Box::new(node.copy())),
+    fn intern(&mut self, node: IntermediateNode<T>) -> NodeId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
         }
+        let left_depth = node.left.map(|id| self.depths[id]).unwrap_or(0);
+        let right_depth = node.right.map(|id| self.depths[id]).unwrap_or(0);
+        let id = self.nodes.len();
+        self.nodes.push(node);
+        self.depths.push(std::cmp::max(left_depth, right_depth) + 1);
+        self.ids.insert(node, id);
+        id
     }
-}
 
-impl<T> IntermediateNode<T> {
-    fn new_from_value(value: T) -> Self {
-        IntermediateNode {
+    fn value(&mut self, value: T) -> NodeId {
+        self.intern(IntermediateNode {
             node_type: NodeType::Value(Expression::Value(value)),
             left: None,
             right: None,
-        }
+        })
     }
 
-    fn new_from_variable(var: u32) -> Self {
-        IntermediateNode {
+    fn variable(&mut self, var: u32) -> NodeId {
+        self.intern(IntermediateNode {
             node_type: NodeType::Value(Expression::Variable(var)),
             left: None,
             right: None,
-        }
+        })
     }
 
-    fn depth(&self) -> usize {
-        let left_depth = self.left.as_ref().map(|node| node.depth()).unwrap_or(0);
-        let right_depth = self.right.as_ref().map(|node| node.depth()).unwrap_or(0);
-        std::cmp::max(left_depth, right_depth) + 1
+    fn gate(&mut self, node_type: NodeType<T>, left: NodeId, right: NodeId) -> NodeId {
+        self.intern(IntermediateNode {
+            node_type,
+            left: Some(left),
+            right: Some(right),
+        })
     }
-}
 
-fn zero_node() -> IntermediateNode<FieldElement<32>> {
-    let zero = FieldElement::from((Fr::zero()).to_repr());
-    IntermediateNode {
-        node_type: NodeType::Value(Expression::Value(zero)),
-        left: None,
-        right: None,
+    fn get(&self, id: NodeId) -> &IntermediateNode<T> {
+        &self.nodes[id]
     }
+
+    fn depth(&self, id: NodeId) -> usize {
+        self.depths[id]
+    }
+}
+
+fn zero_node<F: CircuitField>(arena: &mut NodeArena<FieldElement<32>>) -> NodeId {
+    arena.value(FieldElement::from((F::zero()).to_repr()))
 }
 
 #[derive(Clone)]
@@ -105,35 +163,28 @@ struct IntermediateLayer<T> {
     operand_index: Vec<(usize, usize)>,
 }
 
-fn merge_nodes(
-    nodes: Vec<IntermediateNode<FieldElement<32>>>,
-) -> IntermediateNode<FieldElement<32>> {
+// the sum of `nodes`, which is zero if there are none, as for the empty C of A * B = 0
+fn merge_nodes<F: CircuitField>(
+    arena: &mut NodeArena<FieldElement<32>>,
+    nodes: Vec<NodeId>,
+) -> NodeId {
+    if nodes.is_empty() {
+        return zero_node::<F>(arena);
+    }
     if nodes.len() == 1 {
-        return nodes[0].clone();
+        return nodes[0];
     }
 
     let mut new = vec![];
     let width = nodes.len() / 2;
     for i in 0..width {
-        let left = nodes[2 * i].clone();
-        let right = nodes[2 * i + 1].clone();
-        let node = IntermediateNode::<FieldElement<32>> {
-            node_type: NodeType::Add,
-            left: Some(Box::new(left)),
-            right: Some(Box::new(right)),
-        };
-        new.push(node);
+        new.push(arena.gate(NodeType::Add, nodes[2 * i], nodes[2 * i + 1]));
     }
     if nodes.len() % 2 == 1 {
-        let merged = merge_nodes(new);
-        let new_node = IntermediateNode::<FieldElement<32>> {
-            node_type: NodeType::Add,
-            left: Some(Box::new(merged)),
-            right: Some(Box::new(nodes[nodes.len() - 1].clone())),
-        };
-        new_node
+        let merged = merge_nodes::<F>(arena, new);
+        arena.gate(NodeType::Add, merged, nodes[nodes.len() - 1])
     } else {
-        merge_nodes(new)
+        merge_nodes::<F>(arena, new)
     }
 }
 
@@ -151,31 +202,42 @@ fn get_k(n: usize) -> usize {
     }
 }
 
-fn compile(
-    nodes: Vec<Vec<IntermediateNode<FieldElement<32>>>>,
-) -> (
-    Vec<Vec<IntermediateLayer<FieldElement<32>>>>,
-    Vec<Vec<NodeType<FieldElement<32>>>>,
-) {
+// position of `id` in the next layer, which it is added to if it is not there yet
+fn place(next_nodes: &mut Vec<NodeId>, position: &mut HashMap<NodeId, usize>, id: NodeId) -> usize {
+    *position.entry(id).or_insert_with(|| {
+        next_nodes.push(id);
+        next_nodes.len() - 1
+    })
+}
+
+fn compile<F: CircuitField>(
+    mut arena: NodeArena<FieldElement<32>>,
+    nodes: Vec<Vec<NodeId>>,
+    options: &ConversionOptions,
+) -> Result<
+    (
+        Vec<Vec<IntermediateLayer<FieldElement<32>>>>,
+        Vec<Vec<NodeType<FieldElement<32>>>>,
+    ),
+    AggregatorError,
+> {
     println!("Compile nodes..");
     let mut total = vec![];
     let mut total_inputs = vec![];
+    let zero = zero_node::<F>(&mut arena);
 
-    let mut nodes_sorted = nodes.clone();
-    nodes_sorted.sort_by(|a, b| {
-        let a_height = a.iter().map(|node| node.depth()).max().unwrap_or(0);
-        let b_height = b.iter().map(|node| node.depth()).max().unwrap_or(0);
-        a_height.cmp(&b_height)
-    });
+    let mut nodes_sorted = nodes;
+    let height =
+        |circuit: &Vec<NodeId>| circuit.iter().map(|id| arena.depth(*id)).max().unwrap_or(0);
+    nodes_sorted.sort_by_key(|circuit| height(circuit));
 
     let mut width = nodes_sorted.len();
-    while width > WIDTH_LIMIT {
+    while width > options.width_limit.max(1) {
         let mut new_nodes = vec![];
         let new_width = width / 2;
         for i in 0..new_width {
             let mut first = nodes_sorted[2 * i].clone();
-            let second = nodes_sorted[2 * i + 1].clone();
-            first.extend(second);
+            first.extend_from_slice(&nodes_sorted[2 * i + 1]);
             new_nodes.push(first);
         }
         if width % 2 == 1 {
@@ -187,158 +249,46 @@ fn compile(
     for one_circuit in nodes_sorted.iter() {
         let mut layers = vec![];
 
-        let zero = FieldElement::from((Fr::zero()).to_repr());
-
-        let height = one_circuit
-            .iter()
-            .map(|node| node.depth())
-            .max()
-            .unwrap_or(0);
-        if height == 0 {
-            return (vec![layers], vec![]);
-        }
+        // a circuit of leaves alone still gets a layer passing them on
+        let height = height(one_circuit).max(1);
         let mut inputs = vec![];
 
-        let mut used: HashMap<Expression<FieldElement<32>>, usize> = HashMap::new();
         let mut current_nodes = one_circuit.clone();
-        let mut next_nodes = vec![];
-        let mut zero_index = None;
         for d in 0..(height + 1) {
-            let mut layer_operand_idx = vec![];
-            let mut node_types = vec![];
             let k = get_k(current_nodes.len());
-            let full_num = 1 << k;
-            let diff = full_num - current_nodes.len();
-            for _ in 0..diff {
-                current_nodes.push(zero_node());
-            }
+            current_nodes.resize(1 << k, zero);
             if d == height {
                 inputs = current_nodes
                     .iter()
-                    .map(|node| node.node_type.clone())
+                    .map(|id| arena.get(*id).node_type)
                     .collect();
                 break;
             }
-            if d == height - 1 {
-                for node in current_nodes.iter() {
-                    match node.node_type {
-                        NodeType::Mult | NodeType::Add => {
-                            panic!("Unsupported");
-                        }
-                        NodeType::Value(e) => {
-                            if used.contains_key(&e) {
-                                node_types.push(NodeType::Add);
-                                let operand_index =
-                                    (used.get(&e).unwrap().clone(), zero_index.unwrap());
-                                layer_operand_idx.push(operand_index);
-                            } else {
-                                if zero_index == None {
-                                    zero_index = Some(next_nodes.len());
-                                    next_nodes.push(zero_node());
-                                }
-                                match e {
-                                    Expression::Value(v) => {
-                                        node_types.push(NodeType::Add);
-                                        if v == zero {
-                                            used.insert(e, zero_index.unwrap());
-                                            let operand_index =
-                                                (zero_index.unwrap(), zero_index.unwrap());
-                                            layer_operand_idx.push(operand_index);
-                                        } else {
-                                            used.insert(e, next_nodes.len());
-                                            let operand_index =
-                                                (next_nodes.len(), zero_index.unwrap());
-                                            next_nodes.push(IntermediateNode::new_from_value(v));
-                                            layer_operand_idx.push(operand_index);
-                                        }
-                                    }
-                                    Expression::Variable(var) => {
-                                        node_types.push(NodeType::Add);
-                                        used.insert(e, next_nodes.len());
-                                        let operand_index = (next_nodes.len(), zero_index.unwrap());
-                                        next_nodes.push(IntermediateNode::new_from_variable(var));
-                                        layer_operand_idx.push(operand_index);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                layers.push(IntermediateLayer {
-                    node_types,
-                    operand_index: layer_operand_idx,
-                });
-                zero_index = None;
-                current_nodes = next_nodes;
-                next_nodes = vec![];
-                used = HashMap::new();
-                continue;
-            }
 
-            for node in current_nodes.iter() {
+            let mut next_nodes = vec![];
+            let mut position = HashMap::new();
+            let mut layer_operand_idx = vec![];
+            let mut node_types = vec![];
+            for id in current_nodes.iter() {
+                let node = arena.get(*id);
                 match node.node_type {
                     NodeType::Mult | NodeType::Add => {
-                        node_types.push(node.node_type);
-                        let left = node.left.as_ref().unwrap().deref();
-                        let right = node.right.as_ref().unwrap().deref();
-
-                        let mut left_index = next_nodes.len();
-                        let mut right_index = next_nodes.len();
-
-                        if next_nodes.contains(left) {
-                            left_index = next_nodes.iter().position(|node| node == left).unwrap();
-                        } else {
-                            node.left
-                                .as_ref()
-                                .map(|node| next_nodes.push(*(node.clone())));
-                            left_index = next_nodes.len() - 1;
+                        if d == height - 1 {
+                            return Err(AggregatorError::Unsupported(String::from(
+                                "a gate below the deepest layer takes another gate as an operand",
+                            )));
                         }
-                        if next_nodes.contains(right) {
-                            right_index = next_nodes.iter().position(|node| node == right).unwrap();
-                        } else {
-                            node.right
-                                .as_ref()
-                                .map(|node| next_nodes.push(*(node.clone())));
-                            right_index = next_nodes.len() - 1;
-                        }
-                        let operand_index = (left_index, right_index);
-                        layer_operand_idx.push(operand_index);
+                        node_types.push(node.node_type);
+                        let left = place(&mut next_nodes, &mut position, node.left.unwrap());
+                        let right = place(&mut next_nodes, &mut position, node.right.unwrap());
+                        layer_operand_idx.push((left, right));
                     }
-                    NodeType::Value(e) => {
-                        if used.contains_key(&e) {
-                            node_types.push(NodeType::Add);
-                            let operand_index =
-                                (used.get(&e).unwrap().clone(), zero_index.unwrap());
-                            layer_operand_idx.push(operand_index);
-                        } else {
-                            if zero_index == None {
-                                zero_index = Some(next_nodes.len());
-                                next_nodes.push(zero_node());
-                            }
-                            match e {
-                                Expression::Value(v) => {
-                                    node_types.push(NodeType::Add);
-                                    if v == zero {
-                                        used.insert(e, zero_index.unwrap());
-                                        let operand_index =
-                                            (zero_index.unwrap(), zero_index.unwrap());
-                                        layer_operand_idx.push(operand_index);
-                                    } else {
-                                        used.insert(e, next_nodes.len());
-                                        let operand_index = (next_nodes.len(), zero_index.unwrap());
-                                        next_nodes.push(IntermediateNode::new_from_value(v));
-                                        layer_operand_idx.push(operand_index);
-                                    }
-                                }
-                                Expression::Variable(var) => {
-                                    node_types.push(NodeType::Add);
-                                    used.insert(e, next_nodes.len());
-                                    let operand_index = (next_nodes.len(), zero_index.unwrap());
-                                    next_nodes.push(IntermediateNode::new_from_variable(var));
-                                    layer_operand_idx.push(operand_index);
-                                }
-                            }
-                        }
+                    NodeType::Value(_) => {
+                        // a leaf above the input layer is passed down as itself plus zero
+                        node_types.push(NodeType::Add);
+                        let zero_index = place(&mut next_nodes, &mut position, zero);
+                        let index = place(&mut next_nodes, &mut position, *id);
+                        layer_operand_idx.push((index, zero_index));
                     }
                 }
             }
@@ -346,29 +296,38 @@ fn compile(
                 node_types,
                 operand_index: layer_operand_idx,
             });
-            zero_index = None;
             current_nodes = next_nodes;
-            next_nodes = vec![];
-            used = HashMap::new();
         }
         total.push(layers);
         total_inputs.push(inputs);
     }
-    (total, total_inputs)
+    Ok((total, total_inputs))
 }
 
-fn convert_constraints_to_nodes(
-    r1cs: &R1csFile<32>,
-) -> Vec<Vec<IntermediateNode<FieldElement<32>>>> {
-    fn count_mult(v: &Vec<(FieldElement<32>, u32)>) -> (i32, i32) {
-        let one = FieldElement(Fr::one().to_repr());
-        let minus_one = FieldElement::from((Fr::zero() - Fr::one()).to_repr());
+// a signal x as node = coeff * x, from the constraint `constraint`, which also reads `others`
+struct Definition {
+    node: NodeId,
+    constraint: usize,
+    coeff: FieldElement<32>,
+    others: Vec<u32>,
+}
+
+// the constraints as a DAG, one root per constraint except the dropped definitions of inlined
+// signals
+fn convert_constraints_to_nodes<F: CircuitField>(
+    constraints: &Vec<Constraint<32>>,
+    n_public: usize,
+    options: &ConversionOptions,
+) -> (NodeArena<FieldElement<32>>, Vec<Vec<NodeId>>) {
+    fn count_mult<F: CircuitField>(v: &Vec<(FieldElement<32>, u32)>) -> (i32, i32) {
+        let one = FieldElement(F::one().to_repr());
+        let minus_one = FieldElement::from((F::zero() - F::one()).to_repr());
         let mut a = 0;
         let mut b = 0;
-        for (coeff, x_i) in v {
-            if coeff.clone() == one {
+        for (coeff, _) in v {
+            if *coeff == one {
                 b += 1;
-            } else if coeff.clone() == minus_one {
+            } else if *coeff == minus_one {
                 a += 1;
             } else {
                 a += 1;
@@ -377,105 +336,107 @@ fn convert_constraints_to_nodes(
         }
         (a, b)
     }
-    fn update_symbol_table(
-        symbol_table: &mut HashMap<
-            u32,
-            (IntermediateNode<FieldElement<32>>, usize, FieldElement<32>),
-        >,
-        a: &IntermediateNode<FieldElement<32>>,
+    // coeff * x, or x alone if coeff is one
+    fn make_term(
+        arena: &mut NodeArena<FieldElement<32>>,
+        coeff: FieldElement<32>,
+        x: u32,
+        one: &FieldElement<32>,
+    ) -> NodeId {
+        let right = arena.variable(x);
+        if coeff == *one {
+            right
+        } else {
+            let left = arena.value(coeff);
+            arena.gate(NodeType::Mult, left, right)
+        }
+    }
+    fn negate<F: CircuitField>(coeff: &FieldElement<32>) -> FieldElement<32> {
+        let coeff_fr = F::from_repr(coeff.0).unwrap();
+        FieldElement((coeff_fr * (F::zero() - F::one())).to_repr())
+    }
+    // every private signal of c, as the node a equals plus the rest of c
+    fn update_symbol_table<F: CircuitField>(
+        arena: &mut NodeArena<FieldElement<32>>,
+        symbol_table: &mut HashMap<u32, Definition>,
+        a: NodeId,
         c: &Vec<(FieldElement<32>, u32)>,
         idx: usize,
         neg: &bool,
-    ) -> () {
-        fn make_node_except_i(
+        n_public: usize,
+    ) {
+        fn make_node_except_i<F: CircuitField>(
+            arena: &mut NodeArena<FieldElement<32>>,
             i: usize,
             v: &Vec<(FieldElement<32>, u32)>,
             neg: &bool,
-        ) -> IntermediateNode<FieldElement<32>> {
-            let minus_one = FieldElement::from((Fr::zero() - Fr::one()).to_repr());
+        ) -> NodeId {
+            let one = FieldElement(F::one().to_repr());
             let mut node_c = vec![];
-            let one = FieldElement(Fr::one().to_repr());
             for (idx, (coeff, x_i)) in v.iter().enumerate() {
                 if idx == i {
                     continue;
                 }
-                if neg.clone() == true {
-                    if coeff.clone() == one {
-                        let node = IntermediateNode::new_from_variable(x_i.clone());
-                        node_c.push(node);
-                    } else {
-                        let left = IntermediateNode::new_from_value(coeff.clone());
-                        let right = IntermediateNode::new_from_variable(x_i.clone());
-                        let node = IntermediateNode::<FieldElement<32>> {
-                            node_type: NodeType::Mult,
-                            left: Some(Box::new(left)),
-                            right: Some(Box::new(right)),
-                        };
-                        node_c.push(node);
-                    }
+                if *neg {
+                    node_c.push(make_term(arena, *coeff, *x_i, &one));
                 } else {
-                    if coeff.clone() == minus_one {
-                        let node = IntermediateNode::new_from_variable(x_i.clone());
-                        node_c.push(node);
-                    } else {
-                        let coeff_fr = Fr::from_repr(coeff.clone().0).unwrap();
-                        let new_coeff =
-                            FieldElement((coeff_fr * (Fr::zero() - Fr::one())).to_repr());
-                        let left = IntermediateNode::new_from_value(new_coeff.clone());
-                        let right = IntermediateNode::new_from_variable(x_i.clone());
-                        let node = IntermediateNode::<FieldElement<32>> {
-                            node_type: NodeType::Mult,
-                            left: Some(Box::new(left)),
-                            right: Some(Box::new(right)),
-                        };
-                        node_c.push(node);
-                    }
+                    node_c.push(make_term(arena, negate::<F>(coeff), *x_i, &one));
                 }
             }
 
-            merge_nodes(node_c)
+            merge_nodes::<F>(arena, node_c)
         }
-        if c.len() == 1 {
-            if neg.clone() {
-                let coeff = Fr::from_repr(c[0].0.clone().0).unwrap();
-                let new_coeff = FieldElement((coeff * (Fr::zero() - Fr::one())).to_repr());
-                symbol_table.insert(c[0].1.clone(), (a.clone(), idx, new_coeff));
-            } else {
-                symbol_table.insert(c[0].1.clone(), (a.clone(), idx, c[0].0.clone()));
-            }
-        } else {
-            for (i, (coeff, x_i)) in c.iter().enumerate() {
-                let node = make_node_except_i(i, &c, neg);
-                let res = IntermediateNode {
-                    node_type: NodeType::Add,
-                    left: Some(Box::new(a.clone())),
-                    right: Some(Box::new(node)),
-                };
-                symbol_table.insert(x_i.clone(), (res, idx, coeff.clone()));
+        for (i, (coeff, x_i)) in c.iter().enumerate() {
+            // the constant wire and public signals stay in the input layer
+            if *x_i == 0 || *x_i as usize <= n_public {
+                continue;
             }
+            let node = if c.len() == 1 {
+                a
+            } else {
+                let rest = make_node_except_i::<F>(arena, i, c, neg);
+                arena.gate(NodeType::Add, a, rest)
+            };
+            // with neg, a is -A * B and node is -coeff * x_i
+            let coeff = if *neg { negate::<F>(coeff) } else { *coeff };
+            let others = c
+                .iter()
+                .filter(|(_, x_j)| x_j != x_i)
+                .map(|(_, x_j)| *x_j)
+                .collect();
+            symbol_table.insert(
+                *x_i,
+                Definition {
+                    node,
+                    constraint: idx,
+                    coeff,
+                    others,
+                },
+            );
         }
     }
-    let mut used = vec![];
+    // signals inlined somewhere, and how often every signal is still read as itself
+    let mut used = HashSet::new();
+    let mut raw: HashMap<u32, usize> = HashMap::new();
 
-    let constraints = &r1cs.constraints;
+    let mut arena = NodeArena::new();
     let mut nodes = vec![];
-    let mut sym_tbl: HashMap<u32, (IntermediateNode<FieldElement<32>>, usize, FieldElement<32>)> =
-        HashMap::new();
-    let one = FieldElement(Fr::one().to_repr());
-    let minus_one = FieldElement::from((Fr::zero() - Fr::one()).to_repr());
-    for (i, constraint) in constraints.0.iter().enumerate() {
+    let mut sym_tbl: HashMap<u32, Definition> = HashMap::new();
+    let one = FieldElement(F::one().to_repr());
+    let minus_one = FieldElement::from((F::zero() - F::one()).to_repr());
+    for (i, constraint) in constraints.iter().enumerate() {
         let mut neg = false;
-        let mut a = &constraint.0;
-        let mut b = &constraint.1;
-        let mut c = &constraint.2;
+        let a = &constraint.0;
+        let b = &constraint.1;
+        let c = &constraint.2;
 
         let mut node_a = vec![];
         let mut node_b = vec![];
         let mut node_c = vec![];
 
-        let cnt_a = count_mult(a);
-        let cnt_b = count_mult(b);
-        let cnt_c = count_mult(c);
+        let cnt_a = count_mult::<F>(a);
+        let cnt_b = count_mult::<F>(b);
+        let cnt_c = count_mult::<F>(c);
 
         let mult_cnt = cnt_a.0 + cnt_b.0 + cnt_c.1;
         let m_mult_cnt = cnt_a.1 + cnt_b.1 + cnt_c.0;
@@ -484,151 +445,92 @@ fn convert_constraints_to_nodes(
             neg = true;
         }
         for (coeff, x_i) in a {
-            let e = sym_tbl.get(x_i);
-            if let Some(lookup_res) = e && lookup_res.0.depth() < DEPTH_LIMIT {
-                let lookup_res_cloned = lookup_res.clone();
-                let coeff_fr = Fr::from_repr(coeff.clone().0).unwrap();
-                let new_coeff = FieldElement((coeff_fr * (Fr::zero() - Fr::one())).to_repr());
-                if coeff.clone() == lookup_res_cloned.2 {
+            let inlinable = sym_tbl
+                .get(x_i)
+                .filter(|def| arena.depth(def.node) < options.depth_limit);
+            if let Some(def) = inlinable {
+                let inlined = if *coeff == def.coeff {
                     if neg {
-                        let minus_one_node = IntermediateNode::new_from_value(minus_one.clone());
-                        let new_node = IntermediateNode::<FieldElement<32>> {
-                            node_type: NodeType::Mult,
-                            left: Some(Box::new(lookup_res_cloned.0)),
-                            right: Some(Box::new(minus_one_node)),
-                        };
-                        node_a.push(new_node);
+                        let minus_one_node = arena.value(minus_one);
+                        Some(arena.gate(NodeType::Mult, def.node, minus_one_node))
                     } else {
-                        node_a.push(lookup_res_cloned.0);
+                        Some(def.node)
                     }
-                    used.push(lookup_res_cloned.1);
-                    continue;
-                } else if new_coeff == lookup_res_cloned.2 && neg {
-                    node_a.push(lookup_res_cloned.0);
-                    used.push(lookup_res_cloned.1);
-                    continue;
-                }
-            }
-            if neg == true {
-                if coeff.clone() == minus_one {
-                    let node = IntermediateNode::new_from_variable(x_i.clone());
-                    node_a.push(node);
+                } else if negate::<F>(coeff) == def.coeff && neg {
+                    Some(def.node)
                 } else {
-                    let coeff_fr = Fr::from_repr(coeff.clone().0).unwrap();
-                    let new_coeff = FieldElement((coeff_fr * (Fr::zero() - Fr::one())).to_repr());
-                    let left = IntermediateNode::new_from_value(new_coeff.clone());
-                    let right = IntermediateNode::new_from_variable(x_i.clone());
-                    let node = IntermediateNode::<FieldElement<32>> {
-                        node_type: NodeType::Mult,
-                        left: Some(Box::new(left)),
-                        right: Some(Box::new(right)),
-                    };
+                    None
+                };
+                if let Some(node) = inlined {
                     node_a.push(node);
+                    used.insert(*x_i);
+                    for x_j in def.others.iter() {
+                        *raw.entry(*x_j).or_insert(0) += 1;
+                    }
+                    continue;
                 }
+            }
+            *raw.entry(*x_i).or_insert(0) += 1;
+            if neg {
+                node_a.push(make_term(&mut arena, negate::<F>(coeff), *x_i, &one));
             } else {
-                if coeff.clone() == one {
-                    let node = IntermediateNode::new_from_variable(x_i.clone());
-                    node_a.push(node);
-                } else {
-                    let left = IntermediateNode::new_from_value(coeff.clone());
-                    let right = IntermediateNode::new_from_variable(x_i.clone());
-                    let node = IntermediateNode::<FieldElement<32>> {
-                        node_type: NodeType::Mult,
-                        left: Some(Box::new(left)),
-                        right: Some(Box::new(right)),
-                    };
-                    node_a.push(node);
-                }
+                node_a.push(make_term(&mut arena, *coeff, *x_i, &one));
             }
         }
         for (coeff, x_i) in b {
-            let e = sym_tbl.get(x_i);
-            if let Some(lookup_res) = e && lookup_res.0.depth() < DEPTH_LIMIT {
-                let lookup_res_cloned = lookup_res.clone();
-                if coeff.clone() == lookup_res_cloned.2 {
-                    node_b.push(lookup_res_cloned.0);
-                    used.push(lookup_res_cloned.1);
+            let inlinable = sym_tbl
+                .get(x_i)
+                .filter(|def| arena.depth(def.node) < options.depth_limit);
+            if let Some(def) = inlinable {
+                if *coeff == def.coeff {
+                    node_b.push(def.node);
+                    used.insert(*x_i);
+                    for x_j in def.others.iter() {
+                        *raw.entry(*x_j).or_insert(0) += 1;
+                    }
                     continue;
                 }
             }
-            if coeff.clone() == one {
-                let node = IntermediateNode::new_from_variable(x_i.clone());
-                node_b.push(node);
+            *raw.entry(*x_i).or_insert(0) += 1;
+            node_b.push(make_term(&mut arena, *coeff, *x_i, &one));
+        }
+        for (coeff, x_i) in c {
+            *raw.entry(*x_i).or_insert(0) += 1;
+            if neg {
+                node_c.push(make_term(&mut arena, *coeff, *x_i, &one));
             } else {
-                let left = IntermediateNode::new_from_value(coeff.clone());
-                let right = IntermediateNode::new_from_variable(x_i.clone());
-                let node = IntermediateNode::<FieldElement<32>> {
-                    node_type: NodeType::Mult,
-                    left: Some(Box::new(left)),
-                    right: Some(Box::new(right)),
-                };
-                node_b.push(node);
+                node_c.push(make_term(&mut arena, negate::<F>(coeff), *x_i, &one));
             }
         }
-        if node_a.len() != 0 && node_b.len() != 0 {
-            let root_a = merge_nodes(node_a);
-            let root_b = merge_nodes(node_b);
-            let a_times_b = IntermediateNode {
-                node_type: NodeType::Mult,
-                left: Some(Box::new(root_a)),
-                right: Some(Box::new(root_b)),
-            };
-            // update_symbol_table(&mut sym_tbl, &a_times_b, c, i, &neg);
+        if !node_a.is_empty() && !node_b.is_empty() {
+            let root_a = merge_nodes::<F>(&mut arena, node_a);
+            let root_b = merge_nodes::<F>(&mut arena, node_b);
+            let a_times_b = arena.gate(NodeType::Mult, root_a, root_b);
+            update_symbol_table::<F>(&mut arena, &mut sym_tbl, a_times_b, c, i, &neg, n_public);
 
-            for (coeff, x_i) in c {
-                if neg == true {
-                    if coeff.clone() == one {
-                        let node = IntermediateNode::new_from_variable(x_i.clone());
-                        node_c.push(node);
-                    } else {
-                        let left = IntermediateNode::new_from_value(coeff.clone());
-                        let right = IntermediateNode::new_from_variable(x_i.clone());
-                        let node = IntermediateNode::<FieldElement<32>> {
-                            node_type: NodeType::Mult,
-                            left: Some(Box::new(left)),
-                            right: Some(Box::new(right)),
-                        };
-                        node_c.push(node);
-                    }
-                } else {
-                    if coeff.clone() == minus_one {
-                        let node = IntermediateNode::new_from_variable(x_i.clone());
-                        node_c.push(node);
-                    } else {
-                        let coeff_fr = Fr::from_repr(coeff.clone().0).unwrap();
-                        let new_coeff =
-                            FieldElement((coeff_fr * (Fr::zero() - Fr::one())).to_repr());
-                        let left = IntermediateNode::new_from_value(new_coeff.clone());
-                        let right = IntermediateNode::new_from_variable(x_i.clone());
-                        let node = IntermediateNode::<FieldElement<32>> {
-                            node_type: NodeType::Mult,
-                            left: Some(Box::new(left)),
-                            right: Some(Box::new(right)),
-                        };
-                        node_c.push(node);
-                    }
-                }
-            }
-            let root_c = merge_nodes(node_c);
-
-            nodes.push(IntermediateNode {
-                node_type: NodeType::Add,
-                left: Some(Box::new(a_times_b)),
-                right: Some(Box::new(root_c)),
-            });
+            let root_c = merge_nodes::<F>(&mut arena, node_c);
+            nodes.push(arena.gate(NodeType::Add, a_times_b, root_c));
         } else {
             // [] * [] - C = 0
-            nodes.push(merge_nodes(node_c));
+            nodes.push(merge_nodes::<F>(&mut arena, node_c));
         }
     }
 
+    // A definition is dropped only if its signal is read nowhere but in it, so that any values
+    // of the other signals satisfying the circuit extend to one of the signal satisfying it.
+    let mut dropped = HashSet::new();
+    for (x, def) in sym_tbl.iter() {
+        if used.contains(x) && raw.get(x) == Some(&1) {
+            dropped.insert(def.constraint);
+        }
+    }
     let mut opt_nodes = vec![];
     for (i, node) in nodes.iter().enumerate() {
-        if !used.contains(&i) {
-            opt_nodes.push(vec![node.clone()]);
+        if !dropped.contains(&i) {
+            opt_nodes.push(vec![*node]);
         }
     }
-    opt_nodes
+    (arena, opt_nodes)
 }
 
 pub struct Output<S: PrimeField> {
@@ -649,75 +551,74 @@ impl<S: PrimeField> Output<S> {
     }
 }
 
-fn make_output(witness: &Vec<wtns_file::FieldElement<32>>, sym: Vec<String>) -> Output<Fr> {
+fn make_output<F: CircuitField>(
+    witness: &Vec<F>,
+    sym: Vec<String>,
+) -> Result<Output<F>, AggregatorError> {
     let n_public = sym.len();
+    if witness.len() <= n_public {
+        return Err(AggregatorError::WitnessMismatch(format!(
+            "witness has {} signals but {} are public",
+            witness.len(),
+            n_public
+        )));
+    }
 
-    let mut public = Output::<Fr>::new();
+    let mut public = Output::<F>::new();
 
     for i in 0..n_public {
-        public
-            .wire_map
-            .insert(i + 1, Fr::from_repr(witness[i + 1].0).unwrap());
+        public.wire_map.insert(i + 1, witness[i + 1]);
         public.name_map.insert(i + 1, sym[i].clone());
     }
 
-    public
+    Ok(public)
 }
 
-pub fn convert_r1cs_wtns_gkr(
-    r1cs: R1csFile<32>,
-    wtns: WtnsFile<32>,
-    sym: String,
-) -> (Vec<GKRCircuit<Fr>>, Vec<Input<Fr>>, Output<Fr>) {
-    fn append_binary_set(a: &Vec<Vec<Fr>>, b: &Vec<Vec<Fr>>) -> Vec<Vec<Fr>> {
-        let mut res = a.clone();
-        assert!(b.len() == 1);
-        res.push(b[0].clone());
-        res
+fn check_prime<F: CircuitField>(r1cs: &R1csFile<32>) -> Result<(), AggregatorError> {
+    if r1cs.header.prime.0 != modulus_le::<F>() {
+        return Err(AggregatorError::R1cs(format!(
+            "r1cs should be over {}, compile the circuit with circom --prime {}",
+            F::NAME,
+            F::CIRCOM_PRIME
+        )));
     }
-    fn convert_binary_to_vec(b: &String) -> Vec<Fr> {
+    Ok(())
+}
+
+fn make_circuit<F: CircuitField>(
+    layers: &Vec<IntermediateLayer<FieldElement<32>>>,
+    input: &Vec<NodeType<FieldElement<32>>>,
+    public: Vec<usize>,
+) -> GKRCircuit<F> {
+    fn convert_binary_to_vec<F: CircuitField>(b: &String) -> Vec<F> {
         let mut res = vec![];
         for c in b.chars() {
             if c == '0' {
-                res.push(Fr::zero());
+                res.push(F::zero());
             } else {
-                res.push(Fr::one());
+                res.push(F::one());
             }
         }
         res
     }
-    let circuit_info = compile(convert_constraints_to_nodes(&r1cs));
-    println!("r1cs is converted to GKR intermediate layers");
+    let input_k = get_k(input.len());
 
-    let output_gkr = make_output(
-        &wtns.witness.0,
-        parse_sym(sym, r1cs.header.n_pub_in + r1cs.header.n_pub_out),
-    );
-
-    let mut circuits = vec![];
-    let mut inputs = vec![];
-    for (layers, input) in circuit_info.0.iter().zip(circuit_info.1.iter()) {
-        let mut input_k = get_k(input.len());
-        let input_gkr = calculate_input(layers, input, &wtns.witness);
-
-        let mut gkr_layers = vec![];
-        for i in 0..layers.len() {
-            let k_i = get_k(layers[i].node_types.len());
-            let mut v = 0;
-            let mut k_next = 0;
-            if i == layers.len() - 1 {
-                k_next = input_k;
-            } else {
-                k_next = get_k(layers[i + 1].node_types.len());
-            }
-            v = k_i + 2 * k_next;
+    let mut gkr_layers = vec![];
+    for i in 0..layers.len() {
+        let k_i = get_k(layers[i].node_types.len());
+        let k_next = if i == layers.len() - 1 {
+            input_k
+        } else {
+            get_k(layers[i + 1].node_types.len())
+        };
 
-            let mut add_bin_strings: Vec<String> = layers[i]
+        let wires = |gate: NodeType<FieldElement<32>>| -> Vec<Vec<F>> {
+            layers[i]
                 .node_types
                 .par_iter()
                 .enumerate()
-                .filter(|(_, node)| **node == NodeType::Add)
-                .map(|(curr, node)| {
+                .filter(|(_, node)| **node == gate)
+                .map(|(curr, _)| {
                     let mut curr_string = format!("{:0k$b}", curr, k = k_i);
                     if k_i == 0 {
                         curr_string = String::new();
@@ -725,147 +626,432 @@ pub fn convert_r1cs_wtns_gkr(
                     let operand_index = layers[i].operand_index[curr];
                     let left_string = format!("{:0k$b}", operand_index.0, k = k_next);
                     let right_string = format!("{:0k$b}", operand_index.1, k = k_next);
-                    format!("{}{}{}", curr_string, left_string, right_string)
+                    let s = format!("{}{}{}", curr_string, left_string, right_string);
+                    convert_binary_to_vec::<F>(&s)
                 })
-                .collect();
-
-            let mut add_bin: Vec<Vec<Fr>> = add_bin_strings
-                .par_iter()
-                .map(|s| convert_binary_to_vec(s))
-                .collect();
+                .collect()
+        };
 
-            let mut add_i = add_bin_strings
-                .par_iter()
-                .map(|s| chi_w_for_binary::<Fr>(s))
-                .reduce(|| get_empty::<Fr>(v), |a, b| add_poly(&a, &b));
+        let wire = (wires(NodeType::Add), wires(NodeType::Mult));
+        gkr_layers.push(Layer::from_wires(k_i, k_next, wire));
+    }
+    GKRCircuit::with_public(gkr_layers, input_k, public)
+}
 
-            let mut mult_bin_strings: Vec<String> = layers[i]
-                .node_types
-                .par_iter()
-                .enumerate()
-                .filter(|(_, node)| **node == NodeType::Mult)
-                .map(|(curr, node)| {
-                    let mut curr_string = format!("{:0k$b}", curr, k = k_i);
-                    if k_i == 0 {
-                        curr_string = String::new();
-                    }
-                    let operand_index = layers[i].operand_index[curr];
-                    let left_string = format!("{:0k$b}", operand_index.0, k = k_next);
-                    let right_string = format!("{:0k$b}", operand_index.1, k = k_next);
-                    format!("{}{}{}", curr_string, left_string, right_string)
-                })
-                .collect();
+fn input_sources<F: CircuitField>(
+    input: &Vec<NodeType<FieldElement<32>>>,
+) -> Result<Vec<InputSource<F>>, AggregatorError> {
+    input
+        .iter()
+        .map(|node| match node {
+            NodeType::Value(Expression::Value(v)) => Option::from(F::from_repr(v.0))
+                .map(InputSource::Constant)
+                .ok_or_else(|| AggregatorError::R1cs(String::from("non-canonical coefficient"))),
+            NodeType::Value(Expression::Variable(var)) => Ok(InputSource::Witness(*var as usize)),
+            _ => Err(AggregatorError::Unsupported(String::from(
+                "an input gate is not a constant or a signal",
+            ))),
+        })
+        .collect()
+}
 
-            let mut mult_bin: Vec<Vec<Fr>> = mult_bin_strings
-                .par_iter()
-                .map(|s| convert_binary_to_vec(s))
-                .collect();
+/// Converts an r1cs into GKR circuits once, independently of any witness.
+pub fn compile_r1cs<F: CircuitField>(
+    r1cs: &R1csFile<32>,
+    options: &ConversionOptions,
+) -> Result<CompiledCircuit<F>, AggregatorError> {
+    check_prime::<F>(r1cs)?;
+    let n_public = (r1cs.header.n_pub_in + r1cs.header.n_pub_out) as usize;
+    compile_constraints(&r1cs.constraints.0, n_public, options)
+}
 
-            let mut mult_i = mult_bin_strings
-                .par_iter()
-                .map(|s| chi_w_for_binary::<Fr>(s))
-                .reduce(|| get_empty::<Fr>(v), |a, b| add_poly(&a, &b));
+// GKR circuits of constraints over signals 1..=n_public public
+fn compile_constraints<F: CircuitField>(
+    constraints: &Vec<Constraint<32>>,
+    n_public: usize,
+    options: &ConversionOptions,
+) -> Result<CompiledCircuit<F>, AggregatorError> {
+    let (arena, nodes) = convert_constraints_to_nodes::<F>(constraints, n_public, options);
+    let circuit_info = compile::<F>(arena, nodes, options)?;
+    let mut circuits = vec![];
+    let mut inputs = vec![];
+    for (layers, input) in circuit_info.0.iter().zip(circuit_info.1.iter()) {
+        let sources = input_sources(input)?;
+        circuits.push(make_circuit(
+            layers,
+            input,
+            public_gates(&sources, n_public),
+        ));
+        inputs.push(sources);
+    }
+    println!("r1cs is converted to GKR circuits");
+    Ok(CompiledCircuit {
+        circuits,
+        inputs,
+        n_public,
+        options: *options,
+    })
+}
 
-            if add_i.len() == 0 {
-                add_i = get_empty::<Fr>(v);
-            }
-            if mult_i.len() == 0 {
-                mult_i = get_empty::<Fr>(v);
+fn witness_values<F: CircuitField>(wtns: &WtnsFile<32>) -> Result<Vec<F>, AggregatorError> {
+    if wtns.header.prime.0 != modulus_le::<F>() {
+        return Err(AggregatorError::Wtns(format!(
+            "witness should be over {}, compute it with the circuit compiled for circom --prime {}",
+            F::NAME,
+            F::CIRCOM_PRIME
+        )));
+    }
+    let mut witness: Vec<F> = vec![];
+    for v in wtns.witness.0.iter() {
+        match Option::from(F::from_repr(v.0)) {
+            Some(f) => witness.push(f),
+            None => {
+                return Err(AggregatorError::Wtns(String::from(
+                    "non-canonical signal value",
+                )))
             }
-            let wire = (add_bin, mult_bin);
-            gkr_layers.push(Layer::new(k_i, add_i, mult_i, wire));
         }
-        let circuit = GKRCircuit::new(gkr_layers, input_k);
-        circuits.push(circuit);
-        inputs.push(input_gkr);
     }
+    Ok(witness)
+}
 
-    println!("Convert done.");
-    (circuits, inputs, output_gkr)
-}
-
-fn calculate_input(
-    ir_circuit: &Vec<IntermediateLayer<FieldElement<32>>>,
-    input_layer: &Vec<NodeType<FieldElement<32>>>,
-    wtns: &Witness<32>,
-) -> Input<Fr> {
-    let witness = &wtns.0;
-    let mut w_values = vec![];
-    let mut input = vec![];
-
-    for node in input_layer {
-        match node {
-            NodeType::Value(e) => match e {
-                Expression::Value(v) => {
-                    let v_fr = Fr::from_repr(v.0).unwrap();
-                    input.push(v_fr);
-                }
-                Expression::Variable(var) => {
-                    let value = witness[var.clone() as usize];
-                    input.push(Fr::from_repr(value.0).unwrap());
-                }
-            },
-            _ => panic!("Input value should be an expression"),
+// a constraint A·B = C the witness does not satisfy, with the wires it reads
+struct Unsatisfied<F> {
+    index: usize,
+    ab: F,
+    c: F,
+    wires: Vec<u32>,
+}
+
+fn unsatisfied_constraints<F: CircuitField>(
+    constraints: &Vec<Constraint<32>>,
+    witness: &Vec<F>,
+) -> Result<Vec<Unsatisfied<F>>, AggregatorError> {
+    let eval = |terms: &Vec<(FieldElement<32>, u32)>| -> Result<F, AggregatorError> {
+        let mut res = F::zero();
+        for (coeff, wire) in terms.iter() {
+            let coeff: F = Option::from(F::from_repr(coeff.0))
+                .ok_or_else(|| AggregatorError::R1cs(String::from("non-canonical coefficient")))?;
+            let value = witness.get(*wire as usize).ok_or_else(|| {
+                AggregatorError::WitnessMismatch(format!(
+                    "witness has {} signals, r1cs reads wire {}",
+                    witness.len(),
+                    wire
+                ))
+            })?;
+            res += coeff * value;
         }
-    }
-    w_values.push(input);
-    for layer in ir_circuit.iter().rev() {
-        let mut values = vec![];
-        for i in 0..layer.node_types.len() {
-            match layer.node_types[i] {
-                NodeType::Add => {
-                    let left = w_values[w_values.len() - 1][layer.operand_index[i].0];
-                    let right = w_values[w_values.len() - 1][layer.operand_index[i].1];
-                    values.push(left + right);
-                }
-                NodeType::Mult => {
-                    let left = w_values[w_values.len() - 1][layer.operand_index[i].0];
-                    let right = w_values[w_values.len() - 1][layer.operand_index[i].1];
-                    values.push(left * right);
+        Ok(res)
+    };
+
+    let mut res = vec![];
+    for (index, constraint) in constraints.iter().enumerate() {
+        let ab = eval(&constraint.0)? * eval(&constraint.1)?;
+        let c = eval(&constraint.2)?;
+        if ab != c {
+            let mut wires = vec![];
+            for (_, wire) in constraint
+                .0
+                .iter()
+                .chain(constraint.1.iter())
+                .chain(constraint.2.iter())
+            {
+                if !wires.contains(wire) {
+                    wires.push(*wire);
                 }
-                NodeType::Value(_) => panic!("Layer types should not be a value"),
             }
+            res.push(Unsatisfied {
+                index,
+                ab,
+                c,
+                wires,
+            });
         }
-        w_values.push(values);
     }
-    w_values.reverse();
+    Ok(res)
+}
+
+/// Checks the witness against every constraint of the r1cs before it is converted. The error
+/// lists each failing constraint with A·B, C and the names of its signals from the sym file.
+pub fn check_r1cs_witness<F: CircuitField>(
+    r1cs: &R1csFile<32>,
+    wtns: &WtnsFile<32>,
+    sym: &String,
+) -> Result<(), AggregatorError> {
+    check_prime::<F>(r1cs)?;
+    let witness = witness_values::<F>(wtns)?;
+    let failed = unsatisfied_constraints(&r1cs.constraints.0, &witness)?;
+    if failed.is_empty() {
+        return Ok(());
+    }
 
-    let mut w = vec![];
-    // d = w[0]
-    let d_values = w_values[0].clone();
+    let names = parse_sym_names(sym)?;
+    let mut report = format!(
+        "{} of {} constraints are not satisfied",
+        failed.len(),
+        r1cs.constraints.0.len()
+    );
+    for u in failed.iter() {
+        let signals: Vec<String> = u
+            .wires
+            .iter()
+            .map(|wire| match names.get(wire) {
+                Some(name) => name.clone(),
+                None if *wire == 0 => String::from("one"),
+                None => format!("wire {}", wire),
+            })
+            .collect();
+        report = format!(
+            "{}\n  constraint {}: A·B = {}, C = {}, signals {}",
+            report,
+            u.index,
+            stringify_fr(&u.ab),
+            stringify_fr(&u.c),
+            signals.join(", ")
+        );
+    }
+    Err(AggregatorError::WitnessMismatch(report))
+}
 
-    // check constraint
-    assert_eq!(Fr::zero(), d_values[0]);
+// wire of every signal in the sym file, as `label,wire,component,name` lines
+fn parse_sym_names(sym: &String) -> Result<HashMap<u32, String>, AggregatorError> {
+    let mut f = File::open(sym).map_err(io_error(sym))?;
+    let mut sym_content = String::new();
+    f.read_to_string(&mut sym_content).map_err(io_error(sym))?;
 
-    let d = get_multi_ext(&d_values, get_k(d_values.len()));
-    w.push(d.clone());
-    for (i, layer_value) in w_values.iter().enumerate() {
-        if i == 0 {
-            continue;
+    let mut res = HashMap::new();
+    for line in sym_content.lines() {
+        let l: Vec<&str> = line.split(',').collect();
+        if l.len() < 4 {
+            return Err(AggregatorError::Sym(format!("bad line {}", line)));
+        }
+        // signals removed by the optimizer have wire -1
+        if let Ok(wire) = l[1].parse::<u32>() {
+            res.entry(wire).or_insert_with(|| l[3].to_string());
         }
-        w.push(get_multi_ext(layer_value, get_k(layer_value.len())));
     }
-    Input { w, d }
+    Ok(res)
 }
 
-fn parse_sym(sym: String, num_public: u32) -> Vec<String> {
+/// Inputs of the compiled circuits on a witness, and the named public values.
+pub fn convert_r1cs_wtns_gkr<F: CircuitField>(
+    compiled: &CompiledCircuit<F>,
+    wtns: WtnsFile<32>,
+    sym: String,
+) -> Result<(Vec<Input<F>>, Output<F>), AggregatorError> {
+    let witness = witness_values::<F>(&wtns)?;
+    let output_gkr = make_output(&witness, parse_sym(sym, compiled.n_public as u32)?)?;
+    let inputs = compiled.assign(&witness)?;
+
+    println!("Convert done.");
+    Ok((inputs, output_gkr))
+}
+
+fn parse_sym(sym: String, num_public: u32) -> Result<Vec<String>, AggregatorError> {
     let mut res = vec![];
     if num_public == 0 {
-        return res;
+        return Ok(res);
     }
 
-    let mut f = File::open(sym).expect("sym file not found");
+    let mut f = File::open(&sym).map_err(io_error(&sym))?;
     let mut sym_content = String::new();
-    f.read_to_string(&mut sym_content).expect("Reading error");
+    f.read_to_string(&mut sym_content).map_err(io_error(&sym))?;
 
     for line in sym_content.lines() {
         let l: Vec<&str> = line.split(',').collect();
-        let name_main: Vec<&str> = l[3].split('.').collect();
-        let name = name_main[1].to_string();
+        let name_main: Vec<&str> = match l.get(3) {
+            Some(name) => name.split('.').collect(),
+            None => return Err(AggregatorError::Sym(format!("bad line {}", line))),
+        };
+        let name = match name_main.get(1) {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(AggregatorError::Sym(format!(
+                    "{} is not a main signal",
+                    l[3]
+                )))
+            }
+        };
         res.push(name);
         if res.len() == (num_public as usize) {
             break;
         }
     }
-    res
+    if res.len() != num_public as usize {
+        return Err(AggregatorError::Sym(format!(
+            "expected {} public signals",
+            num_public
+        )));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compile, compile_constraints, unsatisfied_constraints, ConversionOptions, NodeArena,
+        NodeType,
+    };
+    use halo2curves::bn256::Fr;
+    use halo2curves::group::ff::{Field, PrimeField};
+    use r1cs_file::{Constraint, FieldElement};
+
+    #[test]
+    fn test_unsatisfied_constraints() {
+        let one = FieldElement(Fr::one().to_repr());
+        // a * b = c and a * a = b
+        let constraints = vec![
+            Constraint(vec![(one, 1)], vec![(one, 2)], vec![(one, 3)]),
+            Constraint(vec![(one, 1)], vec![(one, 1)], vec![(one, 2)]),
+        ];
+        let witness = vec![Fr::one(), Fr::from(2), Fr::from(4), Fr::from(8)];
+        assert!(unsatisfied_constraints(&constraints, &witness)
+            .unwrap()
+            .is_empty());
+
+        let witness = vec![Fr::one(), Fr::from(2), Fr::from(3), Fr::from(6)];
+        let failed = unsatisfied_constraints(&constraints, &witness).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].index, 1);
+        assert_eq!((failed[0].ab, failed[0].c), (Fr::from(4), Fr::from(3)));
+        assert_eq!(failed[0].wires, vec![1, 2]);
+
+        assert!(unsatisfied_constraints(&constraints, &witness[..3].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        let one = FieldElement(Fr::one().to_repr());
+        let mut arena = NodeArena::new();
+        // x1 * x2 + x3 and (x1 * x2) * x3, built twice over
+        let mut roots = vec![];
+        for _ in 0..2 {
+            let (x1, x2, x3) = (arena.variable(1), arena.variable(2), arena.variable(3));
+            let x1_x2 = arena.gate(NodeType::Mult, x1, x2);
+            roots.push(arena.gate(NodeType::Add, x1_x2, x3));
+            roots.push(arena.gate(NodeType::Mult, x1_x2, x3));
+        }
+        assert_eq!(roots[0], roots[2]);
+        assert_eq!(arena.nodes.len(), 6);
+        assert_eq!(arena.depth(roots[1]), 3);
+        assert_eq!(arena.value(one), arena.value(one));
+
+        let (layers, inputs) =
+            compile::<Fr>(arena, vec![roots[..2].to_vec()], &Default::default()).unwrap();
+        // x1 * x2 and x3 are each one gate of the middle layer
+        assert_eq!(layers[0][0].operand_index, vec![(0, 1), (0, 1)]);
+        assert_eq!(layers[0][1].node_types.len(), 2);
+        assert_eq!(inputs[0].len(), 4);
+    }
+
+    #[test]
+    fn test_width_limit() {
+        // x_i * x_{i+1} for 30 constraints
+        let packed = |width_limit| {
+            let mut arena = NodeArena::new();
+            let mut nodes = vec![];
+            for i in 0..30 {
+                let (x, y) = (arena.variable(i), arena.variable(i + 1));
+                nodes.push(vec![arena.gate(NodeType::Mult, x, y)]);
+            }
+            let options = ConversionOptions {
+                width_limit,
+                ..Default::default()
+            };
+            compile::<Fr>(arena, nodes, &options).unwrap()
+        };
+        assert_eq!(packed(4).0.len(), 4);
+        let (layers, inputs) = packed(ConversionOptions::single_circuit().width_limit);
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0][0].node_types.len(), 32);
+        assert_eq!(inputs[0].len(), 32);
+    }
+
+    #[test]
+    fn test_inline_signals() {
+        let fe = |x: Fr| FieldElement(x.to_repr());
+        let one = fe(Fr::one());
+        // out public, x and y private, t1 = x * x, t2 = t1 * y and t2 * x = out - 3
+        let mut constraints = vec![
+            Constraint(vec![(one, 2)], vec![(one, 2)], vec![(one, 4)]),
+            Constraint(vec![(one, 4)], vec![(one, 3)], vec![(one, 5)]),
+            Constraint(
+                vec![(one, 5)],
+                vec![(one, 2)],
+                vec![(one, 1), (fe(-Fr::from(3)), 0)],
+            ),
+        ];
+        let witness: Vec<Fr> = [1, 43, 2, 5, 4, 20].iter().map(|v| Fr::from(*v)).collect();
+        let options = ConversionOptions::default();
+        // t1 and t2 are inlined into the last constraint
+        let compiled = compile_constraints::<Fr>(&constraints, 1, &options).unwrap();
+        assert_eq!(compiled.circuits.len(), 1);
+        assert!(compiled.assign(&witness).is_ok());
+        let mut wrong = witness.clone();
+        wrong[1] = Fr::from(44);
+        assert!(compiled.assign(&wrong).is_err());
+
+        // t1 = 4 keeps the definition of t1, which is still read as itself
+        constraints.push(Constraint(
+            vec![],
+            vec![],
+            vec![(one, 4), (fe(-Fr::from(4)), 0)],
+        ));
+        let compiled = compile_constraints::<Fr>(&constraints, 1, &options).unwrap();
+        assert_eq!(compiled.circuits.len(), 3);
+        assert!(compiled.assign(&witness).is_ok());
+        let mut wrong = witness.clone();
+        wrong[4] = Fr::from(5);
+        assert!(compiled.assign(&wrong).is_err());
+    }
+
+    #[test]
+    fn test_zero_height() {
+        let one = FieldElement(Fr::one().to_repr());
+        let minus_one = FieldElement((-Fr::one()).to_repr());
+        // x * x = y and -out = 0, whose root is the leaf out
+        let constraints = vec![
+            Constraint(vec![(one, 2)], vec![(one, 2)], vec![(one, 3)]),
+            Constraint(vec![], vec![], vec![(minus_one, 1)]),
+        ];
+        let witness: Vec<Fr> = [1, 0, 3, 9].iter().map(|v| Fr::from(*v)).collect();
+        let compiled = compile_constraints::<Fr>(&constraints, 1, &Default::default()).unwrap();
+        assert_eq!(compiled.circuits.len(), 2);
+        assert!(compiled.assign(&witness).is_ok());
+        for (wire, value) in [(1, 5), (3, 8)] {
+            let mut wrong = witness.clone();
+            wrong[wire] = Fr::from(value);
+            assert!(compiled.assign(&wrong).is_err());
+        }
+
+        // and packed into one circuit with the other constraint
+        let options = ConversionOptions::single_circuit();
+        let compiled = compile_constraints::<Fr>(&constraints, 1, &options).unwrap();
+        assert_eq!(compiled.circuits.len(), 1);
+        assert!(compiled.assign(&witness).is_ok());
+    }
+
+    #[test]
+    fn test_empty_c() {
+        let fe = |x: Fr| FieldElement(x.to_repr());
+        let one = fe(Fr::one());
+        // IsZero from circomlib with out public: -in * inv = out - 1 and in * out = 0
+        let constraints = vec![
+            Constraint(
+                vec![(fe(-Fr::one()), 2)],
+                vec![(one, 3)],
+                vec![(one, 1), (fe(-Fr::one()), 0)],
+            ),
+            Constraint(vec![(one, 2)], vec![(one, 1)], vec![]),
+        ];
+        let compiled = compile_constraints::<Fr>(&constraints, 1, &Default::default()).unwrap();
+        let three = Fr::from(3);
+        let zero = vec![Fr::one(), Fr::one(), Fr::zero(), Fr::from(5)];
+        let nonzero = vec![Fr::one(), Fr::zero(), three, three.invert().unwrap()];
+        assert!(compiled.assign(&zero).is_ok());
+        assert!(compiled.assign(&nonzero).is_ok());
+        let mut wrong = nonzero.clone();
+        wrong[1] = Fr::one();
+        assert!(compiled.assign(&wrong).is_err());
+    }
 }
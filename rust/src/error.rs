@@ -0,0 +1,84 @@
+use crate::convert::ConversionOptions;
+use crate::gkr::{encoding::DecodeError, prover::ProveError};
+use std::{fmt, io};
+
+/// Reason converting, proving or aggregating failed.
+#[derive(Debug)]
+pub enum AggregatorError {
+    /// Reading or writing `path` failed.
+    Io { path: String, error: io::Error },
+    /// circom, the witness calculator or snarkjs could not be run, or failed, or the tera
+    /// template of the verifier could not be rendered.
+    Tool { tool: String, reason: String },
+    /// The r1cs file cannot be parsed or is over another field.
+    R1cs(String),
+    /// The wtns file cannot be parsed or is over another field.
+    Wtns(String),
+    /// The sym file does not name the public signals.
+    Sym(String),
+    /// A constraint or setting that cannot be turned into a GKR circuit or proof.
+    Unsupported(String),
+    /// The witness does not fit the circuit, or does not satisfy it.
+    WitnessMismatch(String),
+    /// A stored proof or compiled circuit cannot be decoded.
+    Decode(DecodeError),
+    /// The commitment cannot prove the circuit as asked.
+    Prove(ProveError),
+    /// The compiled circuit was converted with other options than the ones given.
+    OptionsMismatch {
+        compiled: ConversionOptions,
+        given: ConversionOptions,
+    },
+}
+
+impl fmt::Display for AggregatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregatorError::Io { path, error } => write!(f, "{}: {}", path, error),
+            AggregatorError::Tool { tool, reason } => write!(f, "{} failed: {}", tool, reason),
+            AggregatorError::R1cs(reason) => write!(f, "bad r1cs: {}", reason),
+            AggregatorError::Wtns(reason) => write!(f, "bad witness file: {}", reason),
+            AggregatorError::Sym(reason) => write!(f, "bad sym file: {}", reason),
+            AggregatorError::Unsupported(reason) => write!(f, "unsupported: {}", reason),
+            AggregatorError::WitnessMismatch(reason) => write!(f, "witness mismatch: {}", reason),
+            AggregatorError::Decode(e) => write!(f, "{}", e),
+            AggregatorError::Prove(e) => write!(f, "unsupported: {}", e),
+            AggregatorError::OptionsMismatch { compiled, given } => write!(
+                f,
+                "the circuit was compiled with {}, but {} was given",
+                compiled, given
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AggregatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AggregatorError::Io { error, .. } => Some(error),
+            AggregatorError::Decode(e) => Some(e),
+            AggregatorError::Prove(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for AggregatorError {
+    fn from(e: DecodeError) -> Self {
+        AggregatorError::Decode(e)
+    }
+}
+
+impl From<ProveError> for AggregatorError {
+    fn from(e: ProveError) -> Self {
+        AggregatorError::Prove(e)
+    }
+}
+
+/// Maps an I/O error on `path`, as in `fs::read(&path).map_err(io_error(&path))`.
+pub fn io_error<P: fmt::Display>(path: P) -> impl FnOnce(io::Error) -> AggregatorError {
+    move |error| AggregatorError::Io {
+        path: path.to_string(),
+        error,
+    }
+}
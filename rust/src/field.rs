@@ -0,0 +1,115 @@
+pub mod goldilocks;
+
+use ff::PrimeField;
+use halo2curves::bn256;
+use std::{fmt, str::FromStr};
+
+/// Field a circom circuit is compiled for and proven over.
+pub trait CircuitField: PrimeField<Repr = [u8; 32]> {
+    /// Name used on the command line.
+    const NAME: &'static str;
+    /// Value of `circom --prime` that compiles circuits over this field.
+    const CIRCOM_PRIME: &'static str;
+}
+
+impl CircuitField for bn256::Fr {
+    const NAME: &'static str = "bn254";
+    const CIRCOM_PRIME: &'static str = "bn128";
+}
+
+impl CircuitField for bls12_381::Scalar {
+    const NAME: &'static str = "bls12-381";
+    const CIRCOM_PRIME: &'static str = "bls12381";
+}
+
+impl CircuitField for pasta_curves::Fp {
+    const NAME: &'static str = "pallas";
+    const CIRCOM_PRIME: &'static str = "pallas";
+}
+
+impl CircuitField for pasta_curves::Fq {
+    const NAME: &'static str = "vesta";
+    const CIRCOM_PRIME: &'static str = "vesta";
+}
+
+/// The modulus in little-endian bytes, as in the headers of r1cs and wtns files.
+pub fn modulus_le<F: PrimeField<Repr = [u8; 32]>>() -> [u8; 32] {
+    // p - 1 is the largest element, so add one to its representation
+    let mut bytes = (F::zero() - F::one()).to_repr();
+    for b in bytes.iter_mut() {
+        let (sum, carry) = b.overflowing_add(1);
+        *b = sum;
+        if !carry {
+            break;
+        }
+    }
+    bytes
+}
+
+/// Runtime choice of a `CircuitField`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    #[default]
+    Bn254,
+    Bls12_381,
+    Pallas,
+    Vesta,
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::Bn254 => bn256::Fr::NAME,
+            FieldType::Bls12_381 => bls12_381::Scalar::NAME,
+            FieldType::Pallas => pasta_curves::Fp::NAME,
+            FieldType::Vesta => pasta_curves::Fq::NAME,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bn254" | "bn128" => Ok(FieldType::Bn254),
+            "bls12-381" | "bls12381" => Ok(FieldType::Bls12_381),
+            "pallas" => Ok(FieldType::Pallas),
+            "vesta" => Ok(FieldType::Vesta),
+            _ => Err(format!(
+                "unknown field {}, expected bn254, bls12-381, pallas or vesta",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modulus_le, CircuitField};
+    use num_bigint::BigUint;
+    use num_traits::Num;
+
+    fn check_modulus<F: CircuitField>(hex: &str) {
+        let mut expected = BigUint::from_str_radix(hex, 16).unwrap().to_bytes_le();
+        expected.resize(32, 0);
+        assert_eq!(modulus_le::<F>().to_vec(), expected, "{}", F::NAME);
+    }
+
+    #[test]
+    fn test_modulus() {
+        check_modulus::<halo2curves::bn256::Fr>(
+            "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001",
+        );
+        check_modulus::<bls12_381::Scalar>(
+            "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001",
+        );
+        check_modulus::<pasta_curves::Fp>(
+            "40000000000000000000000000000000224698fc094cf91b992d30ed00000001",
+        );
+        check_modulus::<pasta_curves::Fq>(
+            "40000000000000000000000000000000224698fc0994a8dd8c46eb2100000001",
+        );
+    }
+}
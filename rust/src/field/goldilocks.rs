@@ -0,0 +1,396 @@
+use ff::{Field, PrimeField};
+use rand_core::RngCore;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
+
+/// The Goldilocks prime 2^64 - 2^32 + 1.
+pub const MODULUS: u64 = 0xffff_ffff_0000_0001;
+
+// 7 is not a square mod p, so u^2 = 7 defines the quadratic extension
+const NON_RESIDUE: u64 = 7;
+
+/// Element of the Goldilocks field, kept reduced below `MODULUS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Goldilocks(u64);
+
+/// c0 + c1 * u in the quadratic extension of Goldilocks with u^2 = 7.
+/// A random challenge from the base field alone would give about 64 bits of soundness,
+/// so the protocol draws challenges from here instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GoldilocksExt2 {
+    pub c0: Goldilocks,
+    pub c1: Goldilocks,
+}
+
+macro_rules! impl_binops {
+    ($t:ty) => {
+        impl Add for $t {
+            type Output = $t;
+            fn add(self, rhs: $t) -> $t {
+                self.add_inner(&rhs)
+            }
+        }
+        impl<'a> Add<&'a $t> for $t {
+            type Output = $t;
+            fn add(self, rhs: &'a $t) -> $t {
+                self.add_inner(rhs)
+            }
+        }
+        impl AddAssign for $t {
+            fn add_assign(&mut self, rhs: $t) {
+                *self = self.add_inner(&rhs);
+            }
+        }
+        impl<'a> AddAssign<&'a $t> for $t {
+            fn add_assign(&mut self, rhs: &'a $t) {
+                *self = self.add_inner(rhs);
+            }
+        }
+        impl Sub for $t {
+            type Output = $t;
+            fn sub(self, rhs: $t) -> $t {
+                self.sub_inner(&rhs)
+            }
+        }
+        impl<'a> Sub<&'a $t> for $t {
+            type Output = $t;
+            fn sub(self, rhs: &'a $t) -> $t {
+                self.sub_inner(rhs)
+            }
+        }
+        impl SubAssign for $t {
+            fn sub_assign(&mut self, rhs: $t) {
+                *self = self.sub_inner(&rhs);
+            }
+        }
+        impl<'a> SubAssign<&'a $t> for $t {
+            fn sub_assign(&mut self, rhs: &'a $t) {
+                *self = self.sub_inner(rhs);
+            }
+        }
+        impl Mul for $t {
+            type Output = $t;
+            fn mul(self, rhs: $t) -> $t {
+                self.mul_inner(&rhs)
+            }
+        }
+        impl<'a> Mul<&'a $t> for $t {
+            type Output = $t;
+            fn mul(self, rhs: &'a $t) -> $t {
+                self.mul_inner(rhs)
+            }
+        }
+        impl MulAssign for $t {
+            fn mul_assign(&mut self, rhs: $t) {
+                *self = self.mul_inner(&rhs);
+            }
+        }
+        impl<'a> MulAssign<&'a $t> for $t {
+            fn mul_assign(&mut self, rhs: &'a $t) {
+                *self = self.mul_inner(rhs);
+            }
+        }
+        impl Neg for $t {
+            type Output = $t;
+            fn neg(self) -> $t {
+                <$t>::zero().sub_inner(&self)
+            }
+        }
+    };
+}
+
+impl_binops!(Goldilocks);
+impl_binops!(GoldilocksExt2);
+
+// x^e for an exponent of up to 128 bits
+fn pow_u128<F: Field>(x: &F, e: u128) -> F {
+    x.pow_vartime([e as u64, (e >> 64) as u64])
+}
+
+// Tonelli-Shanks in a field of order 2^s * t + 1, where z is a non-square
+fn sqrt_tonelli_shanks<F: Field>(a: &F, s: u32, t: u128, z: &F) -> CtOption<F> {
+    if a.is_zero_vartime() {
+        return CtOption::new(F::zero(), Choice::from(1));
+    }
+    let mut m = s;
+    let mut c = pow_u128(z, t);
+    let mut x = pow_u128(a, (t + 1) / 2);
+    let mut b = pow_u128(a, t);
+    while b != F::one() {
+        // least i with b^(2^i) = 1
+        let mut i = 0;
+        let mut b_pow = b;
+        while b_pow != F::one() {
+            b_pow = b_pow.square();
+            i += 1;
+            if i == m {
+                return CtOption::new(F::zero(), Choice::from(0));
+            }
+        }
+        let mut d = c;
+        for _ in 0..(m - i - 1) {
+            d = d.square();
+        }
+        x *= d;
+        c = d.square();
+        b *= c;
+        m = i;
+    }
+    CtOption::new(x, Choice::from(1))
+}
+
+impl Goldilocks {
+    pub const fn new(value: u64) -> Self {
+        Goldilocks(value % MODULUS)
+    }
+
+    /// The canonical representative, below `MODULUS`.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    fn add_inner(&self, rhs: &Self) -> Self {
+        let (sum, carry) = self.0.overflowing_add(rhs.0);
+        if carry || sum >= MODULUS {
+            // adding 2^64 - p = 2^32 - 1 undoes the wrap around
+            Goldilocks(sum.wrapping_sub(MODULUS))
+        } else {
+            Goldilocks(sum)
+        }
+    }
+
+    fn sub_inner(&self, rhs: &Self) -> Self {
+        if self.0 >= rhs.0 {
+            Goldilocks(self.0 - rhs.0)
+        } else {
+            Goldilocks(MODULUS - rhs.0 + self.0)
+        }
+    }
+
+    fn mul_inner(&self, rhs: &Self) -> Self {
+        Goldilocks(((self.0 as u128 * rhs.0 as u128) % MODULUS as u128) as u64)
+    }
+
+    fn is_square(&self) -> bool {
+        // Euler's criterion
+        self.pow_vartime([(MODULUS - 1) / 2]) != -Goldilocks::one()
+    }
+}
+
+impl From<u64> for Goldilocks {
+    fn from(value: u64) -> Self {
+        Goldilocks::new(value)
+    }
+}
+
+impl ConditionallySelectable for Goldilocks {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Goldilocks(u64::conditional_select(&a.0, &b.0, choice))
+    }
+}
+
+impl ConstantTimeEq for Goldilocks {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl Field for Goldilocks {
+    fn random(mut rng: impl RngCore) -> Self {
+        loop {
+            let value = rng.next_u64();
+            if value < MODULUS {
+                return Goldilocks(value);
+            }
+        }
+    }
+
+    fn zero() -> Self {
+        Goldilocks(0)
+    }
+
+    fn one() -> Self {
+        Goldilocks(1)
+    }
+
+    fn square(&self) -> Self {
+        self.mul_inner(self)
+    }
+
+    fn double(&self) -> Self {
+        self.add_inner(self)
+    }
+
+    fn invert(&self) -> CtOption<Self> {
+        CtOption::new(self.pow_vartime([MODULUS - 2]), !self.is_zero())
+    }
+
+    fn sqrt(&self) -> CtOption<Self> {
+        sqrt_tonelli_shanks(
+            self,
+            Self::S,
+            ((MODULUS - 1) >> Self::S) as u128,
+            &Self::multiplicative_generator(),
+        )
+    }
+}
+
+impl PrimeField for Goldilocks {
+    type Repr = [u8; 8];
+
+    const NUM_BITS: u32 = 64;
+    const CAPACITY: u32 = 63;
+    const S: u32 = 32;
+
+    fn from_repr(repr: Self::Repr) -> CtOption<Self> {
+        let value = u64::from_le_bytes(repr);
+        CtOption::new(Goldilocks(value), Choice::from((value < MODULUS) as u8))
+    }
+
+    fn to_repr(&self) -> Self::Repr {
+        self.0.to_le_bytes()
+    }
+
+    fn is_odd(&self) -> Choice {
+        Choice::from((self.0 & 1) as u8)
+    }
+
+    fn multiplicative_generator() -> Self {
+        Goldilocks(7)
+    }
+
+    fn root_of_unity() -> Self {
+        Self::multiplicative_generator().pow_vartime([(MODULUS - 1) >> Self::S])
+    }
+}
+
+impl GoldilocksExt2 {
+    pub fn new(c0: Goldilocks, c1: Goldilocks) -> Self {
+        GoldilocksExt2 { c0, c1 }
+    }
+
+    fn add_inner(&self, rhs: &Self) -> Self {
+        GoldilocksExt2::new(self.c0 + rhs.c0, self.c1 + rhs.c1)
+    }
+
+    fn sub_inner(&self, rhs: &Self) -> Self {
+        GoldilocksExt2::new(self.c0 - rhs.c0, self.c1 - rhs.c1)
+    }
+
+    fn mul_inner(&self, rhs: &Self) -> Self {
+        let w = Goldilocks(NON_RESIDUE);
+        GoldilocksExt2::new(
+            self.c0 * rhs.c0 + w * self.c1 * rhs.c1,
+            self.c0 * rhs.c1 + self.c1 * rhs.c0,
+        )
+    }
+
+    /// (c0 + c1 * u) * (c0 - c1 * u), which lies in the base field.
+    pub fn norm(&self) -> Goldilocks {
+        self.c0.square() - Goldilocks(NON_RESIDUE) * self.c1.square()
+    }
+}
+
+impl From<Goldilocks> for GoldilocksExt2 {
+    fn from(value: Goldilocks) -> Self {
+        GoldilocksExt2::new(value, Goldilocks::zero())
+    }
+}
+
+impl From<u64> for GoldilocksExt2 {
+    fn from(value: u64) -> Self {
+        GoldilocksExt2::from(Goldilocks::from(value))
+    }
+}
+
+impl ConditionallySelectable for GoldilocksExt2 {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        GoldilocksExt2::new(
+            Goldilocks::conditional_select(&a.c0, &b.c0, choice),
+            Goldilocks::conditional_select(&a.c1, &b.c1, choice),
+        )
+    }
+}
+
+impl ConstantTimeEq for GoldilocksExt2 {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.c0.ct_eq(&other.c0) & self.c1.ct_eq(&other.c1)
+    }
+}
+
+impl Field for GoldilocksExt2 {
+    fn random(mut rng: impl RngCore) -> Self {
+        let c0 = Goldilocks::random(&mut rng);
+        let c1 = Goldilocks::random(&mut rng);
+        GoldilocksExt2::new(c0, c1)
+    }
+
+    fn zero() -> Self {
+        GoldilocksExt2::from(Goldilocks::zero())
+    }
+
+    fn one() -> Self {
+        GoldilocksExt2::from(Goldilocks::one())
+    }
+
+    fn square(&self) -> Self {
+        self.mul_inner(self)
+    }
+
+    fn double(&self) -> Self {
+        self.add_inner(self)
+    }
+
+    fn invert(&self) -> CtOption<Self> {
+        self.norm()
+            .invert()
+            .map(|n| GoldilocksExt2::new(self.c0 * n, -self.c1 * n))
+    }
+
+    fn sqrt(&self) -> CtOption<Self> {
+        // an element is a square iff its norm is, so c0 + u is a non-square for the
+        // first c0 with c0^2 - 7 a non-square
+        let mut z = GoldilocksExt2::new(Goldilocks::zero(), Goldilocks::one());
+        while z.norm().is_square() {
+            z.c0 += Goldilocks::one();
+        }
+        // p^2 - 1 = (p - 1)(p + 1) = 2^33 * t with t odd
+        let order = MODULUS as u128 * MODULUS as u128 - 1;
+        sqrt_tonelli_shanks(self, 33, order >> 33, &z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goldilocks_arithmetic() {
+        let a = Goldilocks::from(MODULUS - 1);
+        assert_eq!(a + Goldilocks::one(), Goldilocks::zero());
+        assert_eq!(a * a, Goldilocks::one());
+        assert_eq!(
+            Goldilocks::from(3) * Goldilocks::from(3).invert().unwrap(),
+            Goldilocks::one()
+        );
+        assert_eq!(
+            Goldilocks::root_of_unity().pow_vartime([1u64 << 32]),
+            Goldilocks::one()
+        );
+        assert_ne!(
+            Goldilocks::root_of_unity().pow_vartime([1u64 << 31]),
+            Goldilocks::one()
+        );
+        let s = Goldilocks::from(5).square().sqrt().unwrap();
+        assert!(s == Goldilocks::from(5) || s == -Goldilocks::from(5));
+
+        let x = GoldilocksExt2::new(Goldilocks::from(3), Goldilocks::from(MODULUS - 4));
+        let u = GoldilocksExt2::new(Goldilocks::zero(), Goldilocks::one());
+        assert_eq!(u.square(), GoldilocksExt2::from(NON_RESIDUE));
+        assert_eq!(x * x.invert().unwrap(), GoldilocksExt2::one());
+        let s = x.square().sqrt().unwrap();
+        assert!(s == x || s == -x);
+        // the norm of u is -7, which is not a square
+        assert!(bool::from(u.sqrt().is_none()));
+    }
+}
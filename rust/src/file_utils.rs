@@ -1,23 +1,33 @@
 use ff::PrimeField;
-use halo2curves::bn256::Fr;
 use num_bigint::BigInt;
 use num_traits::Num;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_reader, from_str, Value};
 use std::collections::HashMap;
-use std::env::current_dir;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::aggregator::CircomInputProof;
 use crate::convert::Output;
+use crate::error::{io_error, AggregatorError};
+use crate::field::CircuitField;
+use crate::gkr::{
+    encoding::{DecodeError, PcsEncoding},
+    pcs::kzg::Kzg,
+    Proof,
+};
+use crate::witness::WitnessCalculator;
+use crate::workspace::Workspace;
+use r1cs_file::R1csFile;
+use wtns_file::WtnsFile;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Data {
     value_map: HashMap<String, String>,
 }
 
-pub fn stringify_fr(f: &Fr) -> String {
+pub fn stringify_fr<F: PrimeField<Repr = [u8; 32]>>(f: &F) -> String {
     let r = f.to_repr();
     let mut s = String::from("");
     for &b in r.iter().rev() {
@@ -27,7 +37,7 @@ pub fn stringify_fr(f: &Fr) -> String {
     decimal
 }
 
-fn make_output_value_map(output: Output<Fr>) -> Data {
+fn make_output_value_map<F: CircuitField>(output: Output<F>) -> Data {
     let mut value_map = HashMap::new();
     for (k, i) in output.wire_map.iter() {
         let name = output
@@ -39,16 +49,82 @@ fn make_output_value_map(output: Output<Fr>) -> Data {
     Data { value_map }
 }
 
-pub fn write_output(path: String, output: Output<Fr>) {
+pub fn write_output<F: CircuitField>(
+    path: String,
+    output: Output<F>,
+) -> Result<(), AggregatorError> {
     let data = make_output_value_map(output);
     let json_string = serde_json::to_string(&data.value_map).unwrap();
 
-    fs::write(path, json_string).expect("Unable to write file");
+    fs::write(&path, json_string).map_err(io_error(&path))
 }
 
-pub fn write_aggregated_input(path: String, inputs: Vec<CircomInputProof>) -> String {
-    let file = fs::File::open(path).unwrap();
-    let mut input_json: HashMap<String, Value> = from_reader(file).unwrap();
+/// Writes every proof as `{root_path}{name}_proof{i}.json`, in the order of the circuits.
+pub fn write_proofs<F: CircuitField, P: PcsEncoding<F, F>>(
+    root_path: &String,
+    name: &String,
+    proofs: &Vec<Proof<F, F, P>>,
+) -> Result<(), AggregatorError> {
+    for (i, proof) in proofs.iter().enumerate() {
+        let path = format!("{}{}_proof{}.json", root_path, name, i);
+        fs::write(&path, proof.to_json()).map_err(io_error(&path))?;
+    }
+    Ok(())
+}
+
+pub fn read_r1cs(path: &String) -> Result<R1csFile<32>, AggregatorError> {
+    let file = fs::File::open(path).map_err(io_error(path))?;
+    R1csFile::<32>::read(file).map_err(|e| AggregatorError::R1cs(format!("{}: {}", path, e)))
+}
+
+/// Reads a proof in either encoding, telling them apart by the magic bytes of the binary one.
+pub fn read_proof<F: CircuitField, P: PcsEncoding<F, F>>(
+    path: &String,
+) -> Result<Proof<F, F, P>, AggregatorError> {
+    let bytes = fs::read(path).map_err(io_error(path))?;
+    if bytes.starts_with(b"GKRP") {
+        Ok(Proof::from_bytes(&bytes)?)
+    } else {
+        let json = String::from_utf8(bytes).map_err(|e| DecodeError::Format(e.to_string()))?;
+        Ok(Proof::from_json(&json)?)
+    }
+}
+
+/// Writes the keys of a KZG setup as `kzg_prover.key` and `kzg_verifier.key` in `dir`,
+/// returning their paths.
+pub fn write_kzg_keys(dir: &Path, kzg: &Kzg) -> Result<(PathBuf, PathBuf), AggregatorError> {
+    fs::create_dir_all(dir).map_err(io_error(dir.display()))?;
+    let prover = dir.join("kzg_prover.key");
+    let verifier = dir.join("kzg_verifier.key");
+    fs::write(&prover, kzg.to_bytes()).map_err(io_error(prover.display()))?;
+    fs::write(&verifier, kzg.verifier_key().to_bytes()).map_err(io_error(verifier.display()))?;
+    Ok((prover, verifier))
+}
+
+/// Reads a KZG key of `write_kzg_keys`. Proving needs the prover key, verifying takes either.
+pub fn read_kzg_key(path: &String, prover: bool) -> Result<Kzg, AggregatorError> {
+    let bytes = fs::read(path).map_err(io_error(path))?;
+    let kzg = Kzg::from_bytes(&bytes)?;
+    if prover && !kzg.can_commit() {
+        return Err(AggregatorError::Unsupported(format!(
+            "{} is a verifier key, proving needs the prover key",
+            path
+        )));
+    }
+    Ok(kzg)
+}
+
+pub fn write_aggregated_input(
+    path: String,
+    inputs: Vec<CircomInputProof>,
+    workspace: &Workspace,
+) -> Result<String, AggregatorError> {
+    let file = fs::File::open(&path).map_err(io_error(&path))?;
+    let mut input_json: HashMap<String, Value> =
+        from_reader(file).map_err(|e| AggregatorError::Io {
+            path: path.clone(),
+            error: e.into(),
+        })?;
     for (i, input) in inputs.iter().enumerate() {
         let proof_string = serde_json::to_string(&input).unwrap();
         let proof_data: HashMap<String, Value> = from_str(&proof_string).unwrap();
@@ -60,10 +136,19 @@ pub fn write_aggregated_input(path: String, inputs: Vec<CircomInputProof>) -> St
     }
     let json_string = serde_json::to_string_pretty(&input_json).unwrap();
 
-    let root = current_dir().unwrap();
-    let new_path = root.join("aggregated.json");
-    fs::write(&new_path, json_string).unwrap();
-    new_path.into_os_string().into_string().unwrap()
+    let new_path = workspace.aggregated_input();
+    fs::write(&new_path, json_string).map_err(io_error(new_path.display()))?;
+    Ok(new_path.to_string_lossy().into_owned())
+}
+
+/// Directory of a path with a trailing `/`, or an empty string for a bare file name.
+pub fn get_dir(path: &String) -> String {
+    let path_str: Vec<&str> = path.as_str().split('/').collect();
+    let mut root_path = String::new();
+    for slice in path_str[..path_str.len() - 1].iter() {
+        root_path = format!("{}{}/", root_path, slice);
+    }
+    root_path
 }
 
 pub fn get_name(path: &String) -> String {
@@ -73,42 +158,89 @@ pub fn get_name(path: &String) -> String {
     String::from(name_tuple[0])
 }
 
-pub fn execute_circom(path: String, input_path: &String) -> (String, String) {
-    let _ = Command::new("circom")
-        .arg(path.clone())
+// runs an external tool, failing unless it exits successfully
+fn run_tool(command: &mut Command, tool: &str) -> Result<(), AggregatorError> {
+    let output = command.output().map_err(|e| AggregatorError::Tool {
+        tool: String::from(tool),
+        reason: e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(AggregatorError::Tool {
+            tool: String::from(tool),
+            reason: format!(
+                "{}, {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Compiles a circuit into the run directory of `workspace`. `include_dir` is searched for the
+/// includes of the circuit. Returns the circuit name and the run directory, where the r1cs, sym
+/// and wasm files are.
+pub fn compile_circom<F: CircuitField>(
+    path: &String,
+    include_dir: &String,
+    workspace: &Workspace,
+) -> Result<(String, String), AggregatorError> {
+    let mut circom = Command::new("circom");
+    circom
+        .arg(path)
+        .arg("--prime")
+        .arg(F::CIRCOM_PRIME)
         .arg("--r1cs")
         .arg("--sym")
         .arg("--wasm")
-        .output()
-        .expect("circom command failed");
-    print!("");
-    let path_str: Vec<&str> = path.as_str().split('/').collect();
-    let mut path_cloned = path_str.clone();
-    path_cloned.pop();
-    let mut root_path = String::new();
-    for slice in path_cloned {
-        root_path = format!("{}{}/", root_path, slice);
+        .arg("-o")
+        .arg(workspace.run_dir());
+    // a bare file name is in the working directory
+    if include_dir.is_empty() {
+        circom.arg("-l").arg(".");
+    } else {
+        circom.arg("-l").arg(include_dir);
     }
-    let circom_name: Vec<&str> = path_str[path_str.len() - 1].split('.').collect();
-    let name = circom_name[0];
-
-    let witness_gen_name = format!("{}_js/", name);
-    let witness_gen_file = current_dir()
-        .unwrap()
-        .join(witness_gen_name.clone())
-        .join("generate_witness.js");
-    let wasm = current_dir()
-        .unwrap()
-        .join(witness_gen_name)
+    run_tool(&mut circom, "circom")?;
+    Ok((get_name(path), workspace.root_path()))
+}
+
+/// The witness calculator of the circuit `name` compiled by `compile_circom`.
+pub fn witness_calculator(
+    name: &String,
+    workspace: &Workspace,
+) -> Result<WitnessCalculator, AggregatorError> {
+    let wasm = workspace
+        .run_dir()
+        .join(format!("{}_js", name))
         .join(format!("{}.wasm", name));
+    WitnessCalculator::from_file(&wasm)
+}
+
+/// The witness of a compiled circuit on the input JSON at `input_path`.
+pub fn calculate_witness(
+    calculator: &mut WitnessCalculator,
+    input_path: &String,
+) -> Result<WtnsFile<32>, AggregatorError> {
+    let input = fs::read_to_string(input_path).map_err(io_error(input_path))?;
+    calculator.calculate(&input)
+}
+
+/// Compiles a circuit as `compile_circom` and computes its witness on `input_path` in process.
+/// Returns the circuit name, the run directory and the witness, which is also written to the
+/// run directory.
+pub fn execute_circom<F: CircuitField>(
+    path: String,
+    input_path: &String,
+    include_dir: &String,
+    workspace: &Workspace,
+) -> Result<(String, String, WtnsFile<32>), AggregatorError> {
+    let (name, root_path) = compile_circom::<F>(&path, include_dir, workspace)?;
+    let wtns = calculate_witness(&mut witness_calculator(&name, workspace)?, input_path)?;
 
-    let _ = Command::new("node")
-        .arg(witness_gen_file.clone())
-        .arg(wasm)
-        .arg(input_path.clone())
-        .arg("witness.wtns")
-        .status()
-        .expect("witness calculator generation failed");
-    print!("");
-    (String::from(name), root_path)
+    // snarkjs proves the last round from the witness file
+    let witness_path = workspace.witness();
+    let file = fs::File::create(&witness_path).map_err(io_error(witness_path.display()))?;
+    wtns.write(file).map_err(io_error(witness_path.display()))?;
+    Ok((name, root_path, wtns))
 }
@@ -1,70 +1,172 @@
+pub mod encoding;
+pub mod pcs;
 pub mod poly;
 pub mod prover;
 pub mod sumcheck;
-
-use ff::PrimeField;
-
+pub mod transcript;
+pub mod verifier;
+pub mod zk;
+
+use ff::{Field, PrimeField};
+use pcs::{Clear, Pcs};
+use poly::{BinaryFactor, BinaryPoly, BinaryTerm, MultilinearPoly, SparsePoly, Term};
+use transcript::{HashType, TranscriptField};
+
+/// Proof for a circuit over B. Challenges and everything derived from them are in E,
+/// which is B itself unless B is too small for sound challenges. The input layer is given
+/// through the commitment P, by default in the clear.
 #[derive(Clone, Debug)]
-pub struct Proof<S: PrimeField> {
-    pub sumcheck_proofs: Vec<Vec<Vec<S>>>,
-    pub sumcheck_r: Vec<Vec<S>>,
-    pub d: Vec<Vec<S>>,
-    pub q: Vec<Vec<S>>,
-    pub z: Vec<Vec<S>>,
-    pub r: Vec<S>,
+pub struct Proof<B: PrimeField, E: TranscriptField + From<B> = B, P: Pcs<B, E> = Clear> {
+    pub sumcheck_proofs: Vec<Vec<Vec<E>>>,
+    pub sumcheck_r: Vec<Vec<E>>,
+    pub q: Vec<Vec<E>>,
+    pub z: Vec<Vec<E>>,
+    pub r: Vec<E>,
 
     pub depth: usize,
-    pub input_func: Vec<Vec<S>>,
+    /// Commitment to the input layer with the public gates set to zero.
+    pub commitment: P::Commitment,
+    /// Opening of the commitment at z[d - 1], and at every public gate, where it is zero.
+    /// The gates of a batch are opened once, at a random combination of the copies.
+    pub opening: P::Opening,
+    /// Claimed values of the public gates of every copy, as (position in the input layer, value).
+    pub public: Vec<(usize, B)>,
+    /// Sums of the sumcheck masks of every layer if the proof is zero-knowledge, else empty.
+    /// Such a proof commits to the padded input layer together with the masks, z holds only
+    /// the output point, and q[i] is the line through the masked values at b* and c*.
+    pub masks: Vec<E>,
     pub k: Vec<usize>,
+    pub hash: HashType,
+}
+
+impl<B: PrimeField, E: TranscriptField + From<B>> Proof<B, E> {
+    /// Input layer with the public gates set to zero, which a clear proof carries as it is.
+    pub fn input_func(&self) -> &MultilinearPoly<B> {
+        &self.commitment
+    }
+
+    /// The whole input layer, input_func with the public values put back.
+    pub fn input_layer(&self) -> MultilinearPoly<B> {
+        let mut layer = self.commitment.clone();
+        for (pos, value) in self.public.iter() {
+            layer.evals[*pos] = *value;
+        }
+        layer
+    }
 }
 
 pub struct Input<S: PrimeField> {
     // w[i] is function that gets index and returns value of each gate.
-    // polynomial form
-    pub w: Vec<Vec<Vec<S>>>,
-    // d is output of circuit
-    pub d: Vec<Vec<S>>,
+    // evaluation form
+    // w[0] is output of circuit, which should be zero everywhere
+    pub w: Vec<MultilinearPoly<S>>,
 }
 
 impl<S: PrimeField> Input<S> {
-    pub fn w(&self, i: usize) -> Vec<Vec<S>> {
-        self.w[i].clone()
+    pub fn w(&self, i: usize) -> &MultilinearPoly<S> {
+        &self.w[i]
     }
 }
 
 pub struct Layer<S: PrimeField> {
     pub k: usize,
-    pub add: Vec<Vec<S>>,
-    pub mult: Vec<Vec<S>>,
+    pub add: BinaryPoly<S>,
+    pub mult: BinaryPoly<S>,
     pub wire: (Vec<Vec<S>>, Vec<Vec<S>>),
 }
 
 impl<S: PrimeField> Layer<S> {
     pub fn new(
         k: usize,
-        add: Vec<Vec<S>>,
-        mult: Vec<Vec<S>>,
+        add: BinaryPoly<S>,
+        mult: BinaryPoly<S>,
         wire: (Vec<Vec<S>>, Vec<Vec<S>>),
     ) -> Self {
         Layer { k, add, mult, wire }
     }
+
+    /// Layer with add_i and mult_i read off the wiring, one term per gate.
+    pub fn from_wires(k: usize, k_next: usize, wire: (Vec<Vec<S>>, Vec<Vec<S>>)) -> Self {
+        let v = k + 2 * k_next;
+        let predicate = |wires: &Vec<Vec<S>>| {
+            let terms = wires
+                .iter()
+                .map(|w| {
+                    let factors = w
+                        .iter()
+                        .map(|b| {
+                            if *b == S::one() {
+                                BinaryFactor::X
+                            } else {
+                                BinaryFactor::OneMinusX
+                            }
+                        })
+                        .collect();
+                    BinaryTerm::new(S::one(), factors)
+                })
+                .collect();
+            SparsePoly::new(v, terms)
+        };
+        let add = predicate(&wire.0);
+        let mult = predicate(&wire.1);
+        Layer { k, add, mult, wire }
+    }
 }
 
 pub struct GKRCircuit<S: PrimeField> {
     pub layer: Vec<Layer<S>>,
     input_k: usize,
+    public: Vec<usize>,
 }
 
 impl<S: PrimeField> GKRCircuit<S> {
     pub fn new(layer: Vec<Layer<S>>, input_k: usize) -> Self {
-        GKRCircuit { layer, input_k }
+        GKRCircuit::with_public(layer, input_k, vec![])
+    }
+
+    /// Circuit whose input gates at `public` hold public inputs and outputs. Their values are
+    /// part of the statement: the verifier evaluates their share of the input layer itself.
+    pub fn with_public(layer: Vec<Layer<S>>, input_k: usize, public: Vec<usize>) -> Self {
+        GKRCircuit {
+            layer,
+            input_k,
+            public,
+        }
+    }
+
+    pub fn public(&self) -> &Vec<usize> {
+        &self.public
+    }
+
+    /// Positions of the public gates in the input layer of 2^n copies, copy by copy.
+    pub fn public_positions(&self, n: usize) -> Vec<usize> {
+        let mut res = vec![];
+        for j in 0..(1 << n) {
+            for p in self.public.iter() {
+                res.push((j << self.input_k) + p);
+            }
+        }
+        res
+    }
+
+    /// Points of the input layer of 2^n copies at the public gates of the copy `rho`, a random
+    /// combination of the copies, so that one opening per gate shows it is zero in every copy.
+    pub fn public_points<E: Field>(&self, rho: &[E]) -> Vec<Vec<E>> {
+        self.public
+            .iter()
+            .map(|p| {
+                let mut point = rho.to_vec();
+                point.extend(poly::index_to_bits::<E>(*p, self.input_k));
+                point
+            })
+            .collect()
     }
 
     pub fn depth(&self) -> usize {
         self.layer.len()
     }
 
-    pub fn add(&self, i: usize) -> Vec<Vec<S>> {
+    pub fn add(&self, i: usize) -> BinaryPoly<S> {
         self.layer[i].add.clone()
     }
 
@@ -72,7 +174,7 @@ impl<S: PrimeField> GKRCircuit<S> {
         self.layer[i].wire.0.clone()
     }
 
-    pub fn mult(&self, i: usize) -> Vec<Vec<S>> {
+    pub fn mult(&self, i: usize) -> BinaryPoly<S> {
         self.layer[i].mult.clone()
     }
 
@@ -96,7 +198,7 @@ impl<S: PrimeField> GKRCircuit<S> {
         ks
     }
 
-    pub fn get_add_list(&self) -> Vec<Vec<Vec<S>>> {
+    pub fn get_add_list(&self) -> Vec<BinaryPoly<S>> {
         let mut adds = vec![];
         for i in 0..self.depth() {
             adds.push(self.add(i));
@@ -104,7 +206,7 @@ impl<S: PrimeField> GKRCircuit<S> {
         adds
     }
 
-    pub fn get_mult_list(&self) -> Vec<Vec<Vec<S>>> {
+    pub fn get_mult_list(&self) -> Vec<BinaryPoly<S>> {
         let mut mults = vec![];
         for i in 0..self.depth() {
             mults.push(self.mult(i));
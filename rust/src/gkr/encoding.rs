@@ -0,0 +1,999 @@
+use super::{
+    pcs::{
+        kzg::{Kzg, KzgOpening},
+        ligero::{Ligero, LigeroOpening},
+        Clear, Pcs,
+    },
+    poly::MultilinearPoly,
+    transcript::{HashType, TranscriptField},
+    zk::MaskLayout,
+    Proof,
+};
+use crate::field::{goldilocks::*, CircuitField};
+use ff::{Field, PrimeField};
+use halo2curves::{
+    bn256::{Fr, G1Affine},
+    group::GroupEncoding,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// Version of the proof encodings, written in the header of every proof.
+/// Bump it whenever the proof or transcript layout changes.
+pub const PROOF_VERSION: u32 = 6;
+
+const MAGIC: &[u8; 4] = b"GKRP";
+
+// bounds on the layer sizes and the depth a proof may claim, far beyond any provable circuit,
+// so that nothing sized by them overflows
+const MAX_VARS: usize = 40;
+const MAX_DEPTH: usize = 1 << 16;
+
+/// Field element with a canonical fixed-width encoding in serialized proofs.
+pub trait ProofField: Field {
+    /// Name written in the proof header.
+    const NAME: &'static str;
+    const BYTES: usize;
+    fn to_bytes(&self) -> Vec<u8>;
+    /// None unless `bytes` is the canonical encoding of an element.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl<F: CircuitField> ProofField for F {
+    const NAME: &'static str = F::NAME;
+    const BYTES: usize = 32;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_repr().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        F::from_repr(bytes.try_into().ok()?).into()
+    }
+}
+
+impl ProofField for Goldilocks {
+    const NAME: &'static str = "goldilocks";
+    const BYTES: usize = 8;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_repr().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Goldilocks::from_repr(bytes.try_into().ok()?).into()
+    }
+}
+
+impl ProofField for GoldilocksExt2 {
+    const NAME: &'static str = "goldilocks-ext2";
+    const BYTES: usize = 16;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.c0.to_bytes();
+        bytes.extend(self.c1.to_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::BYTES {
+            return None;
+        }
+        Some(GoldilocksExt2::new(
+            Goldilocks::from_bytes(&bytes[..8])?,
+            Goldilocks::from_bytes(&bytes[8..])?,
+        ))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not a proof: bad magic bytes, invalid JSON or missing entries.
+    Format(String),
+    Version {
+        expected: u32,
+        got: u32,
+    },
+    /// The header names other fields than the ones decoded into.
+    Field {
+        expected: String,
+        got: String,
+    },
+    Hash(String),
+    /// The proof commits to its input layer with another scheme than the one decoded into.
+    Pcs {
+        expected: String,
+        got: String,
+    },
+    /// Bytes that are not the canonical encoding of a field element.
+    Element,
+    /// A vector whose length does not match the layer sizes of the proof.
+    Length(String),
+    /// The input ends in the middle of the proof.
+    Truncated,
+    /// The input goes on after the proof.
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Format(reason) => write!(f, "not a proof: {}", reason),
+            DecodeError::Version { expected, got } => {
+                write!(
+                    f,
+                    "proof version {} is not supported, expected {}",
+                    got, expected
+                )
+            }
+            DecodeError::Field { expected, got } => {
+                write!(f, "proof is over {}, expected {}", got, expected)
+            }
+            DecodeError::Hash(hash) => write!(f, "unknown hash {}", hash),
+            DecodeError::Pcs { expected, got } => {
+                write!(f, "proof commits with {}, expected {}", got, expected)
+            }
+            DecodeError::Element => write!(f, "non-canonical field element"),
+            DecodeError::Length(reason) => write!(f, "bad length: {}", reason),
+            DecodeError::Truncated => write!(f, "proof is truncated"),
+            DecodeError::TrailingBytes => write!(f, "trailing bytes after the proof"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn bad_length<T>(reason: String) -> Result<T, DecodeError> {
+    Err(DecodeError::Length(reason))
+}
+
+// "bn254" for a proof over bn254 with bn254 challenges, "goldilocks/goldilocks-ext2" otherwise
+fn field_name<B: ProofField, E: ProofField>() -> String {
+    if B::NAME == E::NAME {
+        String::from(B::NAME)
+    } else {
+        format!("{}/{}", B::NAME, E::NAME)
+    }
+}
+
+fn check_header<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>, P>(
+    version: u32,
+    field: &str,
+    hash: &str,
+    pcs: &str,
+) -> Result<HashType, DecodeError>
+where
+    P: PcsEncoding<B, E>,
+{
+    if version != PROOF_VERSION {
+        return Err(DecodeError::Version {
+            expected: PROOF_VERSION,
+            got: version,
+        });
+    }
+    if field != field_name::<B, E>() {
+        return Err(DecodeError::Field {
+            expected: field_name::<B, E>(),
+            got: String::from(field),
+        });
+    }
+    if pcs != P::NAME {
+        return Err(DecodeError::Pcs {
+            expected: String::from(P::NAME),
+            got: String::from(pcs),
+        });
+    }
+    // only the canonical name, so that encoding a decoded proof gives the same bytes
+    match hash.parse::<HashType>() {
+        Ok(h) if h.to_string() == hash => Ok(h),
+        _ => Err(DecodeError::Hash(String::from(hash))),
+    }
+}
+
+// the lengths every vector must have given the layer sizes k
+fn check_shape<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>, P>(
+    proof: &Proof<B, E, P>,
+) -> Result<(), DecodeError>
+where
+    P: PcsEncoding<B, E>,
+{
+    let k = &proof.k;
+    if k.is_empty() {
+        return bad_length(String::from("proof has no layers"));
+    }
+    let depth = k.len();
+    if depth > MAX_DEPTH || k.iter().any(|k| *k > MAX_VARS) {
+        return bad_length(String::from("proof claims a circuit too large to prove"));
+    }
+    // a zero-knowledge proof keeps only the output point, and has a mask sum per layer
+    let zk = !proof.masks.is_empty();
+    if proof.depth != depth
+        || proof.sumcheck_proofs.len() != depth - 1
+        || proof.sumcheck_r.len() != depth - 1
+        || proof.q.len() != depth - 1
+        || proof.r.len() != depth - 1
+        || proof.z.len() != if zk { 1 } else { depth }
+        || (zk && proof.masks.len() != depth - 1)
+    {
+        return bad_length(format!("expected {} layers", depth));
+    }
+    for i in 0..depth - 1 {
+        // the sumcheck of the last layer reads the padded input layer of a zero-knowledge proof
+        let padded = zk && i + 2 == depth;
+        let v = 2 * (k[i + 1] + padded as usize);
+        if proof.sumcheck_proofs[i].len() != v || proof.sumcheck_r[i].len() != v {
+            return bad_length(format!("layer {} should have {} sumcheck rounds", i, v));
+        }
+        // round polynomials are at most cubic, or quartic with the masks
+        let degree = if zk { 4 } else { 3 };
+        if proof.sumcheck_proofs[i]
+            .iter()
+            .any(|g| g.is_empty() || g.len() > degree + 1)
+        {
+            return bad_length(format!("layer {} has a round polynomial of bad degree", i));
+        }
+        let q_degree = if zk { 1 } else { k[i + 1] };
+        if proof.q[i].is_empty() || proof.q[i].len() > q_degree + 1 {
+            return bad_length(format!("q[{}] has a bad degree", i));
+        }
+    }
+    for i in 0..proof.z.len() {
+        if proof.z[i].len() != k[i] {
+            return bad_length(format!("z[{}] should have {} variables", i, k[i]));
+        }
+    }
+    let k_input = k[depth - 1];
+    let committed = if zk {
+        MaskLayout::new(k).num_vars
+    } else {
+        k_input
+    };
+    if !P::fits(&proof.commitment, committed) {
+        return bad_length(String::from(
+            "input layer has a wrong number of evaluations",
+        ));
+    }
+    if proof.public.iter().any(|(pos, _)| *pos >> k_input != 0) {
+        return bad_length(String::from("a public gate is outside the input layer"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonProof {
+    version: u32,
+    field: String,
+    hash: String,
+    k: Vec<usize>,
+    sumcheck_proofs: Vec<Vec<Vec<String>>>,
+    sumcheck_r: Vec<Vec<String>>,
+    q: Vec<Vec<String>>,
+    z: Vec<Vec<String>>,
+    r: Vec<String>,
+    /// [position, value] of every public gate
+    public: Vec<(usize, String)>,
+    masks: Vec<String>,
+    pcs: String,
+    commitment: Value,
+    opening: Value,
+}
+
+fn to_hex<S: ProofField>(v: &Vec<S>) -> Vec<String> {
+    v.iter().map(|s| hex::encode(s.to_bytes())).collect()
+}
+
+fn from_hex<S: ProofField>(v: &Vec<String>) -> Result<Vec<S>, DecodeError> {
+    v.iter()
+        .map(|s| {
+            let bytes = hex::decode(s).map_err(|_| DecodeError::Element)?;
+            // lowercase only, like the encoder
+            if hex::encode(&bytes) != *s {
+                return Err(DecodeError::Element);
+            }
+            S::from_bytes(&bytes).ok_or(DecodeError::Element)
+        })
+        .collect()
+}
+
+fn to_hex_point<G: GroupEncoding>(p: &G) -> String {
+    hex::encode(p.to_bytes().as_ref())
+}
+
+fn point_from_bytes<G: GroupEncoding>(bytes: &[u8]) -> Result<G, DecodeError> {
+    let mut repr = G::Repr::default();
+    if repr.as_ref().len() != bytes.len() {
+        return Err(DecodeError::Element);
+    }
+    repr.as_mut().copy_from_slice(bytes);
+    Option::from(G::from_bytes(&repr)).ok_or(DecodeError::Element)
+}
+
+fn from_hex_point<G: GroupEncoding>(s: &String) -> Result<G, DecodeError> {
+    let bytes = hex::decode(s).map_err(|_| DecodeError::Element)?;
+    if hex::encode(&bytes) != *s {
+        return Err(DecodeError::Element);
+    }
+    point_from_bytes(&bytes)
+}
+
+fn from_json_value<T: DeserializeOwned>(json: &Value) -> Result<T, DecodeError> {
+    serde_json::from_value(json.clone()).map_err(|e| DecodeError::Format(e.to_string()))
+}
+
+pub(crate) fn put_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend((value as u32).to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_u32(out, s.len());
+    out.extend(s.as_bytes());
+}
+
+fn put_vec<S: ProofField>(out: &mut Vec<u8>, v: &Vec<S>) {
+    put_u32(out, v.len());
+    for s in v.iter() {
+        out.extend(s.to_bytes());
+    }
+}
+
+fn put_vec2<S: ProofField>(out: &mut Vec<u8>, v: &Vec<Vec<S>>) {
+    put_u32(out, v.len());
+    for inner in v.iter() {
+        put_vec(out, inner);
+    }
+}
+
+/// Appends the compressed encoding of a curve point.
+pub(crate) fn put_point<G: GroupEncoding>(out: &mut Vec<u8>, p: &G) {
+    out.extend(p.to_bytes().as_ref());
+}
+
+/// Cursor over a binary proof or key.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(DecodeError::Truncated);
+        }
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+
+    /// Fails unless the input ends here.
+    pub(crate) fn finish(&self) -> Result<(), DecodeError> {
+        if self.pos != self.bytes.len() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn point<G: GroupEncoding>(&mut self) -> Result<G, DecodeError> {
+        let len = G::Repr::default().as_ref().len();
+        point_from_bytes(self.take(len)?)
+    }
+
+    pub(crate) fn points<G: GroupEncoding>(&mut self, n: usize) -> Result<Vec<G>, DecodeError> {
+        // read one by one, so a forged count fails on the bytes rather than on the allocation
+        let mut res = vec![];
+        for _ in 0..n {
+            res.push(self.point()?);
+        }
+        Ok(res)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<usize, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| DecodeError::Format(String::from("bad header")))
+    }
+
+    fn vec<S: ProofField>(&mut self) -> Result<Vec<S>, DecodeError> {
+        let len = self.u32()?;
+        // checked before allocating, so a forged length cannot exhaust memory
+        let bytes = self.take(len.checked_mul(S::BYTES).ok_or(DecodeError::Truncated)?)?;
+        bytes
+            .chunks(S::BYTES)
+            .map(|b| S::from_bytes(b).ok_or(DecodeError::Element))
+            .collect()
+    }
+
+    fn vec2<S: ProofField>(&mut self) -> Result<Vec<Vec<S>>, DecodeError> {
+        let len = self.u32()?;
+        let mut res = vec![];
+        for _ in 0..len {
+            res.push(self.vec()?);
+        }
+        Ok(res)
+    }
+}
+
+/// A `Pcs` whose commitments and openings can be written in a proof, in JSON and in the
+/// binary encoding.
+pub trait PcsEncoding<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>>:
+    Pcs<B, E>
+{
+    /// Name written in the proof header.
+    const NAME: &'static str;
+
+    fn commitment_to_json(commitment: &Self::Commitment) -> Value;
+    fn commitment_from_json(json: &Value) -> Result<Self::Commitment, DecodeError>;
+    fn opening_to_json(opening: &Self::Opening) -> Value;
+    fn opening_from_json(json: &Value) -> Result<Self::Opening, DecodeError>;
+    fn put_commitment(out: &mut Vec<u8>, commitment: &Self::Commitment);
+    fn read_commitment(reader: &mut Reader) -> Result<Self::Commitment, DecodeError>;
+    fn put_opening(out: &mut Vec<u8>, opening: &Self::Opening);
+    fn read_opening(reader: &mut Reader) -> Result<Self::Opening, DecodeError>;
+
+    /// Whether the commitment can be to a polynomial in `num_vars` variables, as far as it
+    /// tells.
+    fn fits(_commitment: &Self::Commitment, _num_vars: usize) -> bool {
+        true
+    }
+}
+
+// a clear input layer is its evaluations, and has no opening
+impl<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>> PcsEncoding<B, E>
+    for Clear
+{
+    const NAME: &'static str = "clear";
+
+    fn commitment_to_json(commitment: &MultilinearPoly<B>) -> Value {
+        Value::from(to_hex(&commitment.evals))
+    }
+
+    fn commitment_from_json(json: &Value) -> Result<MultilinearPoly<B>, DecodeError> {
+        input_layer(from_hex(&from_json_value(json)?)?)
+    }
+
+    fn opening_to_json(_: &()) -> Value {
+        Value::Null
+    }
+
+    fn opening_from_json(json: &Value) -> Result<(), DecodeError> {
+        from_json_value(json)
+    }
+
+    fn put_commitment(out: &mut Vec<u8>, commitment: &MultilinearPoly<B>) {
+        put_vec(out, &commitment.evals);
+    }
+
+    fn read_commitment(reader: &mut Reader) -> Result<MultilinearPoly<B>, DecodeError> {
+        input_layer(reader.vec()?)
+    }
+
+    fn put_opening(_: &mut Vec<u8>, _: &()) {}
+
+    fn read_opening(_: &mut Reader) -> Result<(), DecodeError> {
+        Ok(())
+    }
+
+    fn fits(commitment: &MultilinearPoly<B>, num_vars: usize) -> bool {
+        commitment.num_vars == num_vars
+    }
+}
+
+fn input_layer<B: PrimeField>(evals: Vec<B>) -> Result<MultilinearPoly<B>, DecodeError> {
+    if !evals.len().is_power_of_two() {
+        return bad_length(String::from(
+            "input layer has a wrong number of evaluations",
+        ));
+    }
+    Ok(MultilinearPoly::new(evals))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonKzgOpening {
+    value: String,
+    quotients: Vec<String>,
+    blinding: String,
+}
+
+// a point is its compressed encoding, and an opening is one value and m + 1 points per point
+// opened at
+impl PcsEncoding<Fr, Fr> for Kzg {
+    const NAME: &'static str = "kzg";
+
+    fn commitment_to_json(commitment: &G1Affine) -> Value {
+        Value::from(to_hex_point(commitment))
+    }
+
+    fn commitment_from_json(json: &Value) -> Result<G1Affine, DecodeError> {
+        from_hex_point(&from_json_value(json)?)
+    }
+
+    fn opening_to_json(opening: &Vec<KzgOpening>) -> Value {
+        let json: Vec<JsonKzgOpening> = opening
+            .iter()
+            .map(|o| JsonKzgOpening {
+                value: hex::encode(ProofField::to_bytes(&o.value)),
+                quotients: o.quotients.iter().map(to_hex_point).collect(),
+                blinding: to_hex_point(&o.blinding),
+            })
+            .collect();
+        serde_json::to_value(json).unwrap()
+    }
+
+    fn opening_from_json(json: &Value) -> Result<Vec<KzgOpening>, DecodeError> {
+        let json: Vec<JsonKzgOpening> = from_json_value(json)?;
+        json.iter()
+            .map(|o| {
+                Ok(KzgOpening {
+                    value: from_hex(&vec![o.value.clone()])?[0],
+                    quotients: o
+                        .quotients
+                        .iter()
+                        .map(from_hex_point)
+                        .collect::<Result<_, _>>()?,
+                    blinding: from_hex_point(&o.blinding)?,
+                })
+            })
+            .collect()
+    }
+
+    fn put_commitment(out: &mut Vec<u8>, commitment: &G1Affine) {
+        put_point(out, commitment);
+    }
+
+    fn read_commitment(reader: &mut Reader) -> Result<G1Affine, DecodeError> {
+        reader.point()
+    }
+
+    fn put_opening(out: &mut Vec<u8>, opening: &Vec<KzgOpening>) {
+        put_u32(out, opening.len());
+        for o in opening.iter() {
+            out.extend(ProofField::to_bytes(&o.value));
+            put_u32(out, o.quotients.len());
+            for q in o.quotients.iter() {
+                put_point(out, q);
+            }
+            put_point(out, &o.blinding);
+        }
+    }
+
+    fn read_opening(reader: &mut Reader) -> Result<Vec<KzgOpening>, DecodeError> {
+        let len = reader.u32()?;
+        let mut opening = vec![];
+        for _ in 0..len {
+            let bytes = reader.take(<Fr as ProofField>::BYTES)?;
+            let value = <Fr as ProofField>::from_bytes(bytes).ok_or(DecodeError::Element)?;
+            let num_quotients = reader.u32()?;
+            opening.push(KzgOpening {
+                value,
+                quotients: reader.points(num_quotients)?,
+                blinding: reader.point()?,
+            });
+        }
+        Ok(opening)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonLigeroOpening {
+    combinations: Vec<Vec<String>>,
+    columns: Vec<Vec<String>>,
+    paths: Vec<Vec<String>>,
+}
+
+fn digest_from_bytes(bytes: &[u8]) -> Result<[u8; 32], DecodeError> {
+    bytes.try_into().map_err(|_| DecodeError::Element)
+}
+
+fn digest_from_hex(s: &String) -> Result<[u8; 32], DecodeError> {
+    let bytes = hex::decode(s).map_err(|_| DecodeError::Element)?;
+    if hex::encode(&bytes) != *s {
+        return Err(DecodeError::Element);
+    }
+    digest_from_bytes(&bytes)
+}
+
+// the commitment is a Merkle root, and an opening the row combinations, over E, and the
+// opened columns, over B, with their Merkle paths
+impl<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>> PcsEncoding<B, E>
+    for Ligero
+{
+    const NAME: &'static str = "ligero";
+
+    fn commitment_to_json(commitment: &[u8; 32]) -> Value {
+        Value::from(hex::encode(commitment))
+    }
+
+    fn commitment_from_json(json: &Value) -> Result<[u8; 32], DecodeError> {
+        digest_from_hex(&from_json_value(json)?)
+    }
+
+    fn opening_to_json(opening: &LigeroOpening<B, E>) -> Value {
+        let json = JsonLigeroOpening {
+            combinations: opening.combinations.iter().map(to_hex).collect(),
+            columns: opening.columns.iter().map(to_hex).collect(),
+            paths: opening
+                .paths
+                .iter()
+                .map(|path| path.iter().map(hex::encode).collect())
+                .collect(),
+        };
+        serde_json::to_value(json).unwrap()
+    }
+
+    fn opening_from_json(json: &Value) -> Result<LigeroOpening<B, E>, DecodeError> {
+        let json: JsonLigeroOpening = from_json_value(json)?;
+        let mut paths = vec![];
+        for path in json.paths.iter() {
+            paths.push(path.iter().map(digest_from_hex).collect::<Result<_, _>>()?);
+        }
+        Ok(LigeroOpening {
+            combinations: json
+                .combinations
+                .iter()
+                .map(from_hex)
+                .collect::<Result<_, _>>()?,
+            columns: json
+                .columns
+                .iter()
+                .map(from_hex)
+                .collect::<Result<_, _>>()?,
+            paths,
+        })
+    }
+
+    fn put_commitment(out: &mut Vec<u8>, commitment: &[u8; 32]) {
+        out.extend(commitment);
+    }
+
+    fn read_commitment(reader: &mut Reader) -> Result<[u8; 32], DecodeError> {
+        digest_from_bytes(reader.take(32)?)
+    }
+
+    fn put_opening(out: &mut Vec<u8>, opening: &LigeroOpening<B, E>) {
+        put_vec2(out, &opening.combinations);
+        put_vec2(out, &opening.columns);
+        put_u32(out, opening.paths.len());
+        for path in opening.paths.iter() {
+            put_u32(out, path.len());
+            for node in path.iter() {
+                out.extend(node);
+            }
+        }
+    }
+
+    fn read_opening(reader: &mut Reader) -> Result<LigeroOpening<B, E>, DecodeError> {
+        let combinations = reader.vec2()?;
+        let columns = reader.vec2()?;
+        let num_paths = reader.u32()?;
+        let mut paths = vec![];
+        for _ in 0..num_paths {
+            let len = reader.u32()?;
+            let bytes = reader.take(len.checked_mul(32).ok_or(DecodeError::Truncated)?)?;
+            paths.push(
+                bytes
+                    .chunks(32)
+                    .map(digest_from_bytes)
+                    .collect::<Result<_, _>>()?,
+            );
+        }
+        Ok(LigeroOpening {
+            combinations,
+            columns,
+            paths,
+        })
+    }
+}
+
+impl<B: PrimeField + ProofField, E: ProofField + TranscriptField + From<B>, P> Proof<B, E, P>
+where
+    P: PcsEncoding<B, E>,
+{
+    /// JSON encoding with a header of the version, field, hash and commitment scheme.
+    /// Elements are hex strings of their canonical little-endian bytes, and curve points of
+    /// their compressed encoding.
+    pub fn to_json(&self) -> String {
+        let json = JsonProof {
+            version: PROOF_VERSION,
+            field: field_name::<B, E>(),
+            hash: self.hash.to_string(),
+            k: self.k.clone(),
+            sumcheck_proofs: self
+                .sumcheck_proofs
+                .iter()
+                .map(|p| p.iter().map(to_hex).collect())
+                .collect(),
+            sumcheck_r: self.sumcheck_r.iter().map(to_hex).collect(),
+            q: self.q.iter().map(to_hex).collect(),
+            z: self.z.iter().map(to_hex).collect(),
+            r: to_hex(&self.r),
+            public: self
+                .public
+                .iter()
+                .map(|(pos, v)| (*pos, hex::encode(v.to_bytes())))
+                .collect(),
+            masks: to_hex(&self.masks),
+            pcs: String::from(P::NAME),
+            commitment: P::commitment_to_json(&self.commitment),
+            opening: P::opening_to_json(&self.opening),
+        };
+        serde_json::to_string(&json).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DecodeError> {
+        let json: JsonProof =
+            serde_json::from_str(json).map_err(|e| DecodeError::Format(e.to_string()))?;
+        let hash = check_header::<B, E, P>(json.version, &json.field, &json.hash, &json.pcs)?;
+        let mut sumcheck_proofs = vec![];
+        for p in json.sumcheck_proofs.iter() {
+            sumcheck_proofs.push(p.iter().map(from_hex).collect::<Result<_, _>>()?);
+        }
+        let mut public = vec![];
+        for (pos, v) in json.public.iter() {
+            public.push((*pos, from_hex(&vec![v.clone()])?[0]));
+        }
+        let proof = Proof {
+            sumcheck_proofs,
+            sumcheck_r: json
+                .sumcheck_r
+                .iter()
+                .map(from_hex)
+                .collect::<Result<_, _>>()?,
+            q: json.q.iter().map(from_hex).collect::<Result<_, _>>()?,
+            z: json.z.iter().map(from_hex).collect::<Result<_, _>>()?,
+            r: from_hex(&json.r)?,
+            depth: json.k.len(),
+            commitment: P::commitment_from_json(&json.commitment)?,
+            opening: P::opening_from_json(&json.opening)?,
+            public,
+            masks: from_hex(&json.masks)?,
+            k: json.k,
+            hash,
+        };
+        check_shape(&proof)?;
+        Ok(proof)
+    }
+
+    /// Compact binary encoding: the magic bytes "GKRP", the same header as the JSON
+    /// encoding, then the vectors of the proof, each prefixed by its length, and the
+    /// commitment and its opening. Integers are little-endian u32.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        put_u32(&mut out, PROOF_VERSION as usize);
+        put_str(&mut out, &field_name::<B, E>());
+        put_str(&mut out, &self.hash.to_string());
+        put_str(&mut out, P::NAME);
+        put_u32(&mut out, self.k.len());
+        for k in self.k.iter() {
+            put_u32(&mut out, *k);
+        }
+        put_u32(&mut out, self.sumcheck_proofs.len());
+        for p in self.sumcheck_proofs.iter() {
+            put_vec2(&mut out, p);
+        }
+        put_vec2(&mut out, &self.sumcheck_r);
+        put_vec2(&mut out, &self.q);
+        put_vec2(&mut out, &self.z);
+        put_vec(&mut out, &self.r);
+        put_u32(&mut out, self.public.len());
+        for (pos, v) in self.public.iter() {
+            put_u32(&mut out, *pos);
+            out.extend(v.to_bytes());
+        }
+        put_vec(&mut out, &self.masks);
+        P::put_commitment(&mut out, &self.commitment);
+        P::put_opening(&mut out, &self.opening);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len()) != Ok(MAGIC.as_slice()) {
+            return Err(DecodeError::Format(String::from("bad magic bytes")));
+        }
+        let version = reader.u32()? as u32;
+        let field = reader.string()?;
+        let hash = reader.string()?;
+        let pcs = reader.string()?;
+        let hash = check_header::<B, E, P>(version, &field, &hash, &pcs)?;
+
+        let depth = reader.u32()?;
+        let mut k = vec![];
+        for _ in 0..depth {
+            k.push(reader.u32()?);
+        }
+        let num_layers = reader.u32()?;
+        let mut sumcheck_proofs = vec![];
+        for _ in 0..num_layers {
+            sumcheck_proofs.push(reader.vec2()?);
+        }
+        let sumcheck_r = reader.vec2()?;
+        let q = reader.vec2()?;
+        let z = reader.vec2()?;
+        let r = reader.vec()?;
+        let num_public = reader.u32()?;
+        let mut public = vec![];
+        for _ in 0..num_public {
+            let pos = reader.u32()?;
+            let bytes = reader.take(B::BYTES)?;
+            public.push((pos, B::from_bytes(bytes).ok_or(DecodeError::Element)?));
+        }
+        let masks = reader.vec()?;
+        let commitment = P::read_commitment(&mut reader)?;
+        let opening = P::read_opening(&mut reader)?;
+        reader.finish()?;
+
+        let proof = Proof {
+            sumcheck_proofs,
+            sumcheck_r,
+            q,
+            z,
+            r,
+            depth,
+            commitment,
+            opening,
+            public,
+            masks,
+            k,
+            hash,
+        };
+        check_shape(&proof)?;
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gkr::{prover, verifier, GKRCircuit};
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_round_trip() {
+        let (circuit, input) = verifier::tests::example(Fr::zero() - Fr::from(6));
+        let proof = prover::prove(&circuit, &input, HashType::Poseidon).unwrap();
+        let from_json = Proof::<Fr>::from_json(&proof.to_json()).unwrap();
+        assert_eq!(from_json.to_bytes(), proof.to_bytes());
+        let from_bytes = Proof::<Fr>::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(from_bytes.to_json(), proof.to_json());
+        assert_eq!(verifier::verify(&circuit, &from_bytes), Ok(()));
+
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![1, 2]);
+        let proof = prover::prove(&circuit, &input, HashType::Poseidon).unwrap();
+        let from_bytes = Proof::<Fr>::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(from_bytes.public, proof.public);
+        let from_json = Proof::<Fr>::from_json(&proof.to_json()).unwrap();
+        assert_eq!(from_json.to_bytes(), proof.to_bytes());
+        assert_eq!(verifier::verify(&circuit, &from_json), Ok(()));
+
+        let (circuit, input) = verifier::tests::example(Goldilocks::zero() - Goldilocks::from(6));
+        let proof =
+            prover::prove_ext::<_, GoldilocksExt2>(&circuit, &input, HashType::Keccak256).unwrap();
+        let decoded = Proof::<Goldilocks, GoldilocksExt2>::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(verifier::verify(&circuit, &decoded), Ok(()));
+        let decoded = Proof::<Goldilocks, GoldilocksExt2>::from_json(&proof.to_json()).unwrap();
+        assert_eq!(decoded.to_json(), proof.to_json());
+    }
+
+    #[test]
+    fn test_committed_round_trip() {
+        let (circuit, input) = verifier::tests::example(Fr::zero() - Fr::from(6));
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![1]);
+        let kzg = Kzg::setup(4, OsRng);
+        let proof = prover::prove_with(&circuit, &input, &kzg, HashType::Keccak256).unwrap();
+        let from_json = Proof::<Fr, Fr, Kzg>::from_json(&proof.to_json()).unwrap();
+        assert_eq!(from_json.to_bytes(), proof.to_bytes());
+        let from_bytes = Proof::<Fr, Fr, Kzg>::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(from_bytes.to_json(), proof.to_json());
+        let vk = kzg.verifier_key();
+        assert_eq!(verifier::verify_with(&circuit, &vk, &from_bytes), Ok(()));
+
+        // in zero knowledge, with the mask sums and the openings of the masks
+        let options = prover::ProverOptions { zk: true };
+        let zk_kzg = Kzg::setup(prover::committed_vars(&circuit, &options), OsRng);
+        let zk_proof = prover::prove_with_options(
+            &circuit,
+            &input,
+            &zk_kzg,
+            HashType::Keccak256,
+            &options,
+            &mut OsRng,
+        )
+        .unwrap();
+        let from_json = Proof::<Fr, Fr, Kzg>::from_json(&zk_proof.to_json()).unwrap();
+        assert_eq!(from_json.to_bytes(), zk_proof.to_bytes());
+        let from_bytes = Proof::<Fr, Fr, Kzg>::from_bytes(&zk_proof.to_bytes()).unwrap();
+        assert_eq!(from_bytes.masks, zk_proof.masks);
+        let zk_vk = zk_kzg.verifier_key();
+        assert_eq!(verifier::verify_with(&circuit, &zk_vk, &from_bytes), Ok(()));
+
+        // decoding into another commitment scheme fails on the header
+        assert!(matches!(
+            Proof::<Fr>::from_bytes(&proof.to_bytes()),
+            Err(DecodeError::Pcs { .. })
+        ));
+        assert!(matches!(
+            Proof::<Fr>::from_json(&proof.to_json()),
+            Err(DecodeError::Pcs { .. })
+        ));
+
+        // over goldilocks, with the row combinations in the extension
+        let (circuit, input) = verifier::tests::example(Goldilocks::zero() - Goldilocks::from(6));
+        let ligero = Ligero::default();
+        let proof = prover::prove_with::<_, GoldilocksExt2, _>(
+            &circuit,
+            &input,
+            &ligero,
+            HashType::Keccak256,
+        )
+        .unwrap();
+        type LigeroProof = Proof<Goldilocks, GoldilocksExt2, Ligero>;
+        let from_json = LigeroProof::from_json(&proof.to_json()).unwrap();
+        assert_eq!(from_json.to_bytes(), proof.to_bytes());
+        let from_bytes = LigeroProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(from_bytes.to_json(), proof.to_json());
+        assert_eq!(
+            verifier::verify_with(&circuit, &ligero, &from_bytes),
+            Ok(())
+        );
+        let bytes = proof.to_bytes();
+        assert_eq!(
+            LigeroProof::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::Truncated
+        );
+    }
+
+    #[test]
+    fn test_strict_decoding() {
+        let (circuit, input) = verifier::tests::example(Fr::zero() - Fr::from(6));
+        let proof = prover::prove(&circuit, &input, HashType::Mimc7).unwrap();
+        let bytes = proof.to_bytes();
+
+        assert!(matches!(
+            Proof::<pasta_curves::Fp>::from_bytes(&bytes),
+            Err(DecodeError::Field { .. })
+        ));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(
+            Proof::<Fr>::from_bytes(&extra).unwrap_err(),
+            DecodeError::TrailingBytes
+        );
+        assert_eq!(
+            Proof::<Fr>::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::Truncated
+        );
+        let mut version = bytes.clone();
+        version[4] = 7;
+        assert!(matches!(
+            Proof::<Fr>::from_bytes(&version),
+            Err(DecodeError::Version { got: 7, .. })
+        ));
+        // the last input evaluation replaced by the modulus itself
+        let mut non_canonical = bytes.clone();
+        let n = non_canonical.len();
+        non_canonical[n - 32..].copy_from_slice(&crate::field::modulus_le::<Fr>());
+        assert_eq!(
+            Proof::<Fr>::from_bytes(&non_canonical).unwrap_err(),
+            DecodeError::Element
+        );
+
+        let mut short_r = proof.clone();
+        short_r.r.pop();
+        assert!(matches!(
+            Proof::<Fr>::from_json(&short_r.to_json()),
+            Err(DecodeError::Length(_))
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+pub mod kzg;
+pub mod ligero;
+
+use super::{
+    poly::{bits_to_index, MultilinearPoly},
+    transcript::{Transcript, TranscriptField},
+};
+use ff::PrimeField;
+use rand_core::RngCore;
+use std::{fmt, fmt::Debug, str::FromStr};
+
+/// Runtime choice of the commitment to the input layer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PcsType {
+    #[default]
+    Clear,
+    Kzg,
+    Ligero,
+}
+
+impl fmt::Display for PcsType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcsType::Clear => write!(f, "clear"),
+            PcsType::Kzg => write!(f, "kzg"),
+            PcsType::Ligero => write!(f, "ligero"),
+        }
+    }
+}
+
+impl FromStr for PcsType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clear" => Ok(PcsType::Clear),
+            "kzg" => Ok(PcsType::Kzg),
+            "ligero" => Ok(PcsType::Ligero),
+            _ => Err(format!(
+                "unknown commitment {}, expected clear, kzg or ligero",
+                s
+            )),
+        }
+    }
+}
+
+/// Multilinear polynomial commitment the input layer is given to the verifier through.
+/// The commitment is absorbed before the first challenge, and after the last layer the
+/// prover opens it at the points the verifier asks for.
+pub trait Pcs<B: PrimeField, E: TranscriptField + From<B>> {
+    type Commitment: Clone + Debug;
+    type Opening: Clone + Debug;
+    /// What the prover keeps from committing for the opening.
+    type ProverData;
+
+    /// Commits to `poly`, blinding it with `rng` if the commitment hides.
+    fn commit(
+        &self,
+        poly: &MultilinearPoly<B>,
+        rng: &mut impl RngCore,
+    ) -> (Self::Commitment, Self::ProverData);
+
+    fn append_commitment(&self, transcript: &mut Transcript<E>, commitment: &Self::Commitment);
+
+    /// Opens `poly` at every point of `points`, blinding the openings with `rng` if the
+    /// commitment hides.
+    fn open(
+        &self,
+        poly: &MultilinearPoly<B>,
+        data: &Self::ProverData,
+        points: &Vec<Vec<E>>,
+        transcript: &mut Transcript<E>,
+        rng: &mut impl RngCore,
+    ) -> Self::Opening;
+
+    /// The values of the committed polynomial at `points`, or None if the opening is invalid.
+    fn verify(
+        &self,
+        commitment: &Self::Commitment,
+        points: &Vec<Vec<E>>,
+        opening: &Self::Opening,
+        transcript: &mut Transcript<E>,
+    ) -> Option<Vec<E>>;
+
+    /// Whether the commitment and its openings tell nothing about the polynomial beyond the
+    /// opened values, which a zero-knowledge proof needs.
+    fn hiding(&self) -> bool {
+        false
+    }
+
+    /// The most variables a committed polynomial may have, if the parameters bound it.
+    fn max_vars(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// No commitment: the input layer is sent as it is and the verifier evaluates it.
+/// Proofs grow with the input layer and do not hide it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clear;
+
+impl<B: PrimeField, E: TranscriptField + From<B>> Pcs<B, E> for Clear {
+    type Commitment = MultilinearPoly<B>;
+    type Opening = ();
+    type ProverData = ();
+
+    fn commit(&self, poly: &MultilinearPoly<B>, _: &mut impl RngCore) -> (Self::Commitment, ()) {
+        (poly.clone(), ())
+    }
+
+    fn append_commitment(&self, transcript: &mut Transcript<E>, commitment: &Self::Commitment) {
+        let evals = commitment.evals.iter().map(|v| E::from(*v)).collect();
+        transcript.append_vec(b"input layer", &evals);
+    }
+
+    fn open(
+        &self,
+        _: &MultilinearPoly<B>,
+        _: &(),
+        _: &Vec<Vec<E>>,
+        _: &mut Transcript<E>,
+        _: &mut impl RngCore,
+    ) {
+    }
+
+    fn verify(
+        &self,
+        commitment: &Self::Commitment,
+        points: &Vec<Vec<E>>,
+        _: &(),
+        _: &mut Transcript<E>,
+    ) -> Option<Vec<E>> {
+        let mut values = vec![];
+        for point in points.iter() {
+            if point.len() != commitment.num_vars {
+                return None;
+            }
+            // on the hypercube the polynomial is its table
+            let value = if point.iter().all(|x| *x == E::zero() || *x == E::one()) {
+                E::from(commitment.evals[bits_to_index(point)])
+            } else {
+                commitment.evaluate(point)
+            };
+            values.push(value);
+        }
+        Some(values)
+    }
+}
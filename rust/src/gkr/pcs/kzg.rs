@@ -0,0 +1,373 @@
+use super::Pcs;
+use crate::gkr::{
+    encoding::{put_point, put_u32, DecodeError, Reader},
+    poly::{eq_table, MultilinearPoly},
+    transcript::Transcript,
+};
+use ff::Field;
+use halo2curves::{
+    bn256::{Bn256, Fr, G1Affine, G2Affine, G2Prepared, Gt, G1, G2},
+    group::{Curve, Group, GroupEncoding},
+    pairing::{MillerLoopResult, MultiMillerLoop},
+};
+use rand_core::RngCore;
+
+/// Hiding multilinear KZG (PST13) on BN254 from a trusted setup, see the README.
+#[derive(Clone, Debug)]
+pub struct Kzg {
+    // lagrange[i][y] = g^eq(τ[i..], y), the basis for polynomials in the last l - i variables
+    lagrange: Vec<Vec<G1Affine>>,
+    g: G1Affine,
+    h: G2Affine,
+    h_tau: Vec<G2Affine>,
+    g_tau: Vec<G1Affine>,
+    g_gamma: G1Affine,
+    h_gamma: G2Affine,
+}
+
+/// Value at one point, the commitments to the quotients q_1, ..., q_m, and what makes up
+/// for their blinding and that of the commitment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KzgOpening {
+    pub value: Fr,
+    pub quotients: Vec<G1Affine>,
+    pub blinding: G1Affine,
+}
+
+// Σ scalars[i] * bases[i], skipping the zero scalars the padding of a layer is made of
+fn msm(bases: &[G1Affine], scalars: &[Fr]) -> G1 {
+    let mut acc = G1::identity();
+    for (base, scalar) in bases.iter().zip(scalars.iter()) {
+        if *scalar != Fr::zero() {
+            acc += G1::from(*base) * scalar;
+        }
+    }
+    acc
+}
+
+// lagrange[i + 1][y] = lagrange[i][y] + lagrange[i][half + y], as eq(τ[i..], (b, y)) summed
+// over the first variable b is eq(τ[i + 1..], y)
+fn lagrange_levels(basis: Vec<G1Affine>) -> Vec<Vec<G1Affine>> {
+    let mut levels = vec![basis];
+    while levels[levels.len() - 1].len() > 1 {
+        let prev = &levels[levels.len() - 1];
+        let half = prev.len() / 2;
+        let sums: Vec<G1> = (0..half)
+            .map(|y| G1::from(prev[y]) + prev[half + y])
+            .collect();
+        let mut next = prev[..half].to_vec();
+        G1::batch_normalize(&sums, &mut next);
+        levels.push(next);
+    }
+    levels
+}
+
+const KEY_MAGIC: &[u8; 4] = b"GKRK";
+const KEY_VERSION: u32 = 1;
+
+impl Kzg {
+    /// Setup from a τ drawn from `rng` and dropped when it returns. Anyone who knows τ can
+    /// open a commitment to any value, so the prover must not run it, see `Kzg`.
+    pub fn setup(max_vars: usize, mut rng: impl RngCore) -> Self {
+        let tau: Vec<Fr> = (0..max_vars).map(|_| Fr::random(&mut rng)).collect();
+        let gamma = Fr::random(&mut rng);
+        let g = G1::generator();
+        let h = G2::generator();
+        let basis = eq_table(&tau).iter().map(|e| g * e).collect::<Vec<G1>>();
+        let mut affine = vec![g.to_affine(); basis.len()];
+        G1::batch_normalize(&basis, &mut affine);
+        Kzg {
+            lagrange: lagrange_levels(affine),
+            g: g.to_affine(),
+            h: h.to_affine(),
+            h_tau: tau.iter().map(|t| (h * t).to_affine()).collect(),
+            g_tau: tau.iter().map(|t| (g * t).to_affine()).collect(),
+            g_gamma: (g * gamma).to_affine(),
+            h_gamma: (h * gamma).to_affine(),
+        }
+    }
+
+    /// The parameters without the commitment key, which is all the verifier needs.
+    pub fn verifier_key(&self) -> Self {
+        Kzg {
+            lagrange: vec![],
+            g: self.g,
+            h: self.h,
+            h_tau: self.h_tau.clone(),
+            g_tau: vec![],
+            g_gamma: self.g_gamma,
+            h_gamma: self.h_gamma,
+        }
+    }
+
+    /// Whether these are the prover's parameters, which can commit, or only the verifier key.
+    pub fn can_commit(&self) -> bool {
+        !self.lagrange.is_empty()
+    }
+
+    /// The key as "GKRK", the version, the number of variables, whether it can commit, the
+    /// points of the verifier key, then g^τ_i and the Lagrange basis of every variable for
+    /// the prover. Integers are little-endian u32 and points are compressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = KEY_MAGIC.to_vec();
+        put_u32(&mut out, KEY_VERSION as usize);
+        put_u32(&mut out, self.vars());
+        put_u32(&mut out, self.can_commit() as usize);
+        put_point(&mut out, &self.g);
+        put_point(&mut out, &self.h);
+        put_point(&mut out, &self.g_gamma);
+        put_point(&mut out, &self.h_gamma);
+        for p in self.h_tau.iter() {
+            put_point(&mut out, p);
+        }
+        if self.can_commit() {
+            for p in self.g_tau.iter().chain(self.lagrange[0].iter()) {
+                put_point(&mut out, p);
+            }
+        }
+        out
+    }
+
+    /// Reads a key of `to_bytes`, deriving the bases of fewer variables.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(KEY_MAGIC.len()) != Ok(KEY_MAGIC.as_slice()) {
+            return Err(DecodeError::Format(String::from("not a kzg key")));
+        }
+        let version = reader.u32()? as u32;
+        if version != KEY_VERSION {
+            return Err(DecodeError::Version {
+                expected: KEY_VERSION,
+                got: version,
+            });
+        }
+        let max_vars = reader.u32()?;
+        let can_commit = match reader.u32()? {
+            0 => false,
+            1 => true,
+            _ => return Err(DecodeError::Format(String::from("bad kzg key kind"))),
+        };
+        if max_vars >= usize::BITS as usize {
+            return Err(DecodeError::Length(format!(
+                "kzg key for {} variables",
+                max_vars
+            )));
+        }
+        let mut kzg = Kzg {
+            lagrange: vec![],
+            g: reader.point()?,
+            h: reader.point()?,
+            h_tau: vec![],
+            g_tau: vec![],
+            g_gamma: reader.point()?,
+            h_gamma: reader.point()?,
+        };
+        kzg.h_tau = reader.points(max_vars)?;
+        if can_commit {
+            kzg.g_tau = reader.points(max_vars)?;
+            kzg.lagrange = lagrange_levels(reader.points(1 << max_vars)?);
+        }
+        reader.finish()?;
+        Ok(kzg)
+    }
+
+    fn vars(&self) -> usize {
+        self.h_tau.len()
+    }
+
+    fn basis(&self, num_vars: usize) -> &Vec<G1Affine> {
+        assert!(
+            num_vars <= self.vars(),
+            "setup is for at most {} variables",
+            self.vars()
+        );
+        assert!(self.can_commit(), "verifier key cannot commit");
+        &self.lagrange[self.vars() - num_vars]
+    }
+
+    // with the commitment blinded by g^(γ r), the quotients are blinded by g^(γ s_i) and the
+    // blinding is g^(r - Σ s_i (τ_i - z_i))
+    fn open_at(
+        &self,
+        poly: &MultilinearPoly<Fr>,
+        r: &Fr,
+        z: &Vec<Fr>,
+        rng: &mut impl RngCore,
+    ) -> KzgOpening {
+        let offset = self.vars() - z.len();
+        let mut f = poly.clone();
+        let mut quotients = vec![];
+        let mut blinding = G1::from(self.g) * r;
+        for (i, z_i) in z.iter().enumerate() {
+            // f = f(0, X') + X_i * (f(1, X') - f(0, X'))
+            let half = f.evals.len() / 2;
+            let q: Vec<Fr> = (0..half).map(|j| f.evals[half + j] - f.evals[j]).collect();
+            let s = Fr::random(&mut *rng);
+            let quotient = msm(self.basis(z.len() - i - 1), &q) + G1::from(self.g_gamma) * s;
+            quotients.push(quotient.to_affine());
+            blinding -= (G1::from(self.g_tau[offset + i]) - G1::from(self.g) * z_i) * s;
+            f = f.fix_variable(z_i);
+        }
+        KzgOpening {
+            value: f.evals[0],
+            quotients,
+            blinding: blinding.to_affine(),
+        }
+    }
+
+    // e(C - g^v, h) = Π e(π_i, h^(τ_i - z_i)) e(blinding, h^γ)
+    fn verify_at(&self, commitment: &G1Affine, z: &Vec<Fr>, opening: &KzgOpening) -> bool {
+        if z.len() > self.vars() || opening.quotients.len() != z.len() {
+            return false;
+        }
+        let offset = self.vars() - z.len();
+        let lhs = (G1::from(*commitment) - G1::from(self.g) * opening.value).to_affine();
+        let mut g1 = vec![lhs];
+        let mut g2 = vec![G2Prepared::from(self.h)];
+        for (i, (pi, z_i)) in opening.quotients.iter().zip(z.iter()).enumerate() {
+            g1.push((-G1::from(*pi)).to_affine());
+            let shifted = G2::from(self.h_tau[offset + i]) - G2::from(self.h) * z_i;
+            g2.push(G2Prepared::from(shifted.to_affine()));
+        }
+        g1.push((-G1::from(opening.blinding)).to_affine());
+        g2.push(G2Prepared::from(self.h_gamma));
+        let terms: Vec<(&G1Affine, &G2Prepared)> = g1.iter().zip(g2.iter()).collect();
+        Bn256::multi_miller_loop(&terms).final_exponentiation() == Gt::identity()
+    }
+}
+
+impl Pcs<Fr, Fr> for Kzg {
+    type Commitment = G1Affine;
+    type Opening = Vec<KzgOpening>;
+    /// The blinding r of the commitment.
+    type ProverData = Fr;
+
+    fn commit(&self, poly: &MultilinearPoly<Fr>, rng: &mut impl RngCore) -> (G1Affine, Fr) {
+        let r = Fr::random(rng);
+        let commitment = msm(self.basis(poly.num_vars), &poly.evals) + G1::from(self.g_gamma) * r;
+        (commitment.to_affine(), r)
+    }
+
+    fn append_commitment(&self, transcript: &mut Transcript<Fr>, commitment: &G1Affine) {
+        transcript.append_bytes(b"kzg commitment", commitment.to_bytes().as_ref());
+    }
+
+    fn open(
+        &self,
+        poly: &MultilinearPoly<Fr>,
+        r: &Fr,
+        points: &Vec<Vec<Fr>>,
+        _: &mut Transcript<Fr>,
+        rng: &mut impl RngCore,
+    ) -> Vec<KzgOpening> {
+        points
+            .iter()
+            .map(|z| self.open_at(poly, r, z, &mut *rng))
+            .collect()
+    }
+
+    fn verify(
+        &self,
+        commitment: &G1Affine,
+        points: &Vec<Vec<Fr>>,
+        opening: &Vec<KzgOpening>,
+        _: &mut Transcript<Fr>,
+    ) -> Option<Vec<Fr>> {
+        if opening.len() != points.len() {
+            return None;
+        }
+        let mut values = vec![];
+        for (z, o) in points.iter().zip(opening.iter()) {
+            if !self.verify_at(commitment, z, o) {
+                return None;
+            }
+            values.push(o.value);
+        }
+        Some(values)
+    }
+
+    fn hiding(&self) -> bool {
+        true
+    }
+
+    fn max_vars(&self) -> Option<usize> {
+        Some(self.vars())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kzg;
+    use crate::gkr::{
+        pcs::Pcs,
+        poly::MultilinearPoly,
+        transcript::{HashType, Transcript},
+    };
+    use halo2curves::bn256::Fr;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_open() {
+        let kzg = Kzg::setup(4, OsRng);
+        let vk = kzg.verifier_key();
+        let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+        // fewer variables than the setup, so the last coordinates of τ are used
+        let poly = MultilinearPoly::new((0..8).map(|i| Fr::from(i * i + 1)).collect());
+        let (commitment, data) = kzg.commit(&poly, &mut OsRng);
+        let points = vec![
+            vec![Fr::from(3), Fr::from(5), Fr::from(7)],
+            vec![Fr::one(), Fr::zero(), Fr::one()],
+        ];
+        let opening = kzg.open(&poly, &data, &points, &mut transcript, &mut OsRng);
+        let values = vk.verify(&commitment, &points, &opening, &mut transcript);
+        assert_eq!(values, Some(vec![poly.evaluate(&points[0]), Fr::from(26)]));
+
+        let mut wrong_value = opening.clone();
+        wrong_value[0].value += Fr::one();
+        assert_eq!(
+            vk.verify(&commitment, &points, &wrong_value, &mut transcript),
+            None
+        );
+        let (other, _) = kzg.commit(&MultilinearPoly::zero(3), &mut OsRng);
+        assert_eq!(vk.verify(&other, &points, &opening, &mut transcript), None);
+
+        // a second commitment to the same polynomial looks unrelated, and opens as well
+        let (again, data) = kzg.commit(&poly, &mut OsRng);
+        assert_ne!(again, commitment);
+        let opening = kzg.open(&poly, &data, &points, &mut transcript, &mut OsRng);
+        assert!(vk
+            .verify(&again, &points, &opening, &mut transcript)
+            .is_some());
+        assert_eq!(
+            vk.verify(&commitment, &points, &opening, &mut transcript),
+            None
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let kzg = Kzg::setup(3, OsRng);
+        let pk = Kzg::from_bytes(&kzg.to_bytes()).unwrap();
+        // the bases of fewer variables are derived from the one of all variables
+        assert_eq!(pk.lagrange, kzg.lagrange);
+        assert_eq!(pk.to_bytes(), kzg.to_bytes());
+        let vk = Kzg::from_bytes(&kzg.verifier_key().to_bytes()).unwrap();
+        assert!(!vk.can_commit());
+
+        let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+        let poly = MultilinearPoly::new((0..4).map(|i| Fr::from(i + 2)).collect());
+        let (commitment, data) = pk.commit(&poly, &mut OsRng);
+        let points = vec![vec![Fr::from(4), Fr::from(9)]];
+        let opening = pk.open(&poly, &data, &points, &mut transcript, &mut OsRng);
+        assert_eq!(
+            vk.verify(&commitment, &points, &opening, &mut transcript),
+            Some(vec![poly.evaluate(&points[0])])
+        );
+
+        let bytes = kzg.to_bytes();
+        assert!(Kzg::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(Kzg::from_bytes(&extra).is_err());
+    }
+}
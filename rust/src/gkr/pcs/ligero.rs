@@ -0,0 +1,412 @@
+use super::Pcs;
+use crate::gkr::{
+    poly::{eq_table, MultilinearPoly},
+    transcript::{Transcript, TranscriptField},
+};
+use ethers_core::utils::keccak256;
+use ff::{Field, PrimeField};
+use rand_core::RngCore;
+use rayon::prelude::*;
+
+type Digest = [u8; 32];
+
+/// Transparent multilinear commitment in the style of Ligero and Brakedown, with no setup and
+/// no pairings. The evaluations are laid out as a matrix M whose rows are Reed–Solomon encoded,
+/// and the commitment is the Merkle root over the columns of the encoded matrix.
+/// f(z) = eq(z_row)ᵀ M eq(z_col) is opened by sending the row combination eq(z_row)ᵀ M, which
+/// the verifier checks on random columns: the encoding of a combination must agree with the
+/// same combination of every opened column. One more combination with random coefficients
+/// tests that the committed rows are codewords. Works over any field with 2-adic roots of unity.
+#[derive(Clone, Copy, Debug)]
+pub struct Ligero {
+    /// log2 of the inverse rate of the code.
+    pub rate_bits: usize,
+    /// Number of columns opened, or all of them if there are fewer.
+    pub queries: usize,
+}
+
+impl Default for Ligero {
+    // rate 1/4 with the queries for 128 bits, whose count the README derives
+    fn default() -> Self {
+        Ligero::new(2, 128)
+    }
+}
+
+/// The encoded rows and the Merkle tree over their columns, from leaves to root.
+pub struct LigeroData<B: PrimeField> {
+    encoded: Vec<Vec<B>>,
+    tree: Vec<Vec<Digest>>,
+}
+
+/// Row combinations, the random one first and then one per point, and the opened columns
+/// of the encoded matrix with their Merkle paths.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LigeroOpening<B: PrimeField, E: Field> {
+    pub combinations: Vec<Vec<E>>,
+    pub columns: Vec<Vec<B>>,
+    pub paths: Vec<Vec<Digest>>,
+}
+
+// the first half of the variables, rounded down, picks the row and the rest the column
+fn shape(num_vars: usize) -> (usize, usize) {
+    let row_vars = num_vars / 2;
+    (row_vars, num_vars - row_vars)
+}
+
+// generator of the 2^log_n-th roots of unity
+fn omega<B: PrimeField>(log_n: usize) -> Option<B> {
+    if log_n > B::S as usize {
+        return None;
+    }
+    Some(B::root_of_unity().pow_vartime([1u64 << (B::S as usize - log_n)]))
+}
+
+// the polynomial with coefficients `values` at every power of omega, in place
+fn fft<S: Field>(values: &mut Vec<S>, omega: S) {
+    let n = values.len();
+    if n <= 1 {
+        return;
+    }
+    let log_n = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - log_n);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let w_len = omega.pow_vartime([(n / len) as u64]);
+        for start in (0..n).step_by(len) {
+            let mut w = S::one();
+            for j in 0..(len / 2) {
+                let u = values[start + j];
+                let v = values[start + j + len / 2] * w;
+                values[start + j] = u + v;
+                values[start + j + len / 2] = u - v;
+                w *= w_len;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// Reed–Solomon codeword of a row, read as the coefficients of a polynomial
+fn encode<S: Field>(row: &[S], rate_bits: usize, omega: S) -> Vec<S> {
+    let mut values = row.to_vec();
+    values.resize(row.len() << rate_bits, S::zero());
+    fft(&mut values, omega);
+    values
+}
+
+fn hash_column<B: PrimeField>(column: &[B]) -> Digest {
+    let mut bytes = vec![];
+    for v in column.iter() {
+        bytes.extend_from_slice(v.to_repr().as_ref());
+    }
+    keccak256(bytes)
+}
+
+fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+    keccak256([left.as_slice(), right.as_slice()].concat())
+}
+
+fn merkle_tree(leaves: Vec<Digest>) -> Vec<Vec<Digest>> {
+    let mut tree = vec![leaves];
+    while tree[tree.len() - 1].len() > 1 {
+        let level = tree[tree.len() - 1]
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+        tree.push(level);
+    }
+    tree
+}
+
+fn verify_path(root: &Digest, mut index: usize, leaf: Digest, path: &Vec<Digest>) -> bool {
+    let mut node = leaf;
+    for sibling in path.iter() {
+        node = if index & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+        index >>= 1;
+    }
+    node == *root
+}
+
+impl Ligero {
+    /// Code of rate 2^-rate_bits, with as many queries as `security_bits` take when each one
+    /// lets a bad opening through with probability 1 - δ/3, for the distance δ of the code.
+    pub fn new(rate_bits: usize, security_bits: usize) -> Self {
+        assert!(rate_bits > 0, "a code of rate 1 has no distance");
+        let distance = 1.0 - 0.5f64.powi(rate_bits as i32);
+        let escape = 1.0 - distance / 3.0;
+        Ligero {
+            rate_bits,
+            queries: (security_bits as f64 / -escape.log2()).ceil() as usize,
+        }
+    }
+
+    // random coefficients for the proximity test, then eq(z_row) for every point
+    fn row_coefficients<E: TranscriptField>(
+        &self,
+        row_vars: usize,
+        points: &Vec<Vec<E>>,
+        transcript: &mut Transcript<E>,
+    ) -> Vec<Vec<E>> {
+        let mut res = vec![(0..(1 << row_vars))
+            .map(|_| transcript.challenge(b"ligero row"))
+            .collect()];
+        for point in points.iter() {
+            res.push(eq_table(&point[..row_vars].to_vec()));
+        }
+        res
+    }
+
+    fn column_indices<E: TranscriptField>(
+        &self,
+        n: usize,
+        transcript: &mut Transcript<E>,
+    ) -> Vec<usize> {
+        if self.queries >= n {
+            return (0..n).collect();
+        }
+        (0..self.queries)
+            .map(|_| {
+                let c: E = transcript.challenge(b"ligero column");
+                let limb = c.to_limbs()[0];
+                u64::from_le_bytes(limb[..8].try_into().unwrap()) as usize & (n - 1)
+            })
+            .collect()
+    }
+}
+
+impl<B: PrimeField, E: TranscriptField + From<B>> Pcs<B, E> for Ligero {
+    type Commitment = Digest;
+    type Opening = LigeroOpening<B, E>;
+    type ProverData = LigeroData<B>;
+
+    fn commit(&self, poly: &MultilinearPoly<B>, _: &mut impl RngCore) -> (Digest, LigeroData<B>) {
+        let (_, col_vars) = shape(poly.num_vars);
+        let omega = omega::<B>(col_vars + self.rate_bits)
+            .expect("field has no roots of unity of the codeword length");
+        let encoded: Vec<Vec<B>> = poly
+            .evals
+            .par_chunks(1 << col_vars)
+            .map(|row| encode(row, self.rate_bits, omega))
+            .collect();
+        let leaves = (0..(1 << (col_vars + self.rate_bits)))
+            .into_par_iter()
+            .map(|j| hash_column(&encoded.iter().map(|row| row[j]).collect::<Vec<_>>()))
+            .collect();
+        let tree = merkle_tree(leaves);
+        (tree[tree.len() - 1][0], LigeroData { encoded, tree })
+    }
+
+    fn append_commitment(&self, transcript: &mut Transcript<E>, commitment: &Digest) {
+        transcript.append_bytes(b"merkle root", commitment);
+    }
+
+    fn open(
+        &self,
+        poly: &MultilinearPoly<B>,
+        data: &LigeroData<B>,
+        points: &Vec<Vec<E>>,
+        transcript: &mut Transcript<E>,
+        _: &mut impl RngCore,
+    ) -> LigeroOpening<B, E> {
+        let (row_vars, col_vars) = shape(poly.num_vars);
+        let cols = 1 << col_vars;
+        let mut combinations = vec![];
+        for a in self.row_coefficients(row_vars, points, transcript).iter() {
+            let mut combination = vec![E::zero(); cols];
+            for (a_i, row) in a.iter().zip(poly.evals.chunks(cols)) {
+                for (c, v) in combination.iter_mut().zip(row.iter()) {
+                    *c += *a_i * E::from(*v);
+                }
+            }
+            transcript.append_vec(b"ligero combination", &combination);
+            combinations.push(combination);
+        }
+
+        let mut columns = vec![];
+        let mut paths = vec![];
+        for j in self.column_indices(cols << self.rate_bits, transcript) {
+            columns.push(data.encoded.iter().map(|row| row[j]).collect());
+            let path = data.tree[..data.tree.len() - 1]
+                .iter()
+                .enumerate()
+                .map(|(level, nodes)| nodes[(j >> level) ^ 1])
+                .collect();
+            paths.push(path);
+        }
+        LigeroOpening {
+            combinations,
+            columns,
+            paths,
+        }
+    }
+
+    fn verify(
+        &self,
+        commitment: &Digest,
+        points: &Vec<Vec<E>>,
+        opening: &LigeroOpening<B, E>,
+        transcript: &mut Transcript<E>,
+    ) -> Option<Vec<E>> {
+        let num_vars = points.first()?.len();
+        if points.iter().any(|p| p.len() != num_vars) {
+            return None;
+        }
+        let (row_vars, col_vars) = shape(num_vars);
+        let log_n = col_vars + self.rate_bits;
+        let omega = E::from(omega::<B>(log_n)?);
+
+        let coefficients = self.row_coefficients(row_vars, points, transcript);
+        if opening.combinations.len() != coefficients.len()
+            || opening
+                .combinations
+                .iter()
+                .any(|c| c.len() != 1 << col_vars)
+        {
+            return None;
+        }
+        for combination in opening.combinations.iter() {
+            transcript.append_vec(b"ligero combination", combination);
+        }
+        let encoded: Vec<Vec<E>> = opening
+            .combinations
+            .iter()
+            .map(|c| encode(c, self.rate_bits, omega))
+            .collect();
+
+        let indices = self.column_indices(1 << log_n, transcript);
+        if opening.columns.len() != indices.len() || opening.paths.len() != indices.len() {
+            return None;
+        }
+        for (j, (column, path)) in indices
+            .iter()
+            .zip(opening.columns.iter().zip(opening.paths.iter()))
+        {
+            if column.len() != 1 << row_vars
+                || path.len() != log_n
+                || !verify_path(commitment, *j, hash_column(column), path)
+            {
+                return None;
+            }
+            for (a, codeword) in coefficients.iter().zip(encoded.iter()) {
+                let mut expected = E::zero();
+                for (a_i, v) in a.iter().zip(column.iter()) {
+                    expected += *a_i * E::from(*v);
+                }
+                if codeword[*j] != expected {
+                    return None;
+                }
+            }
+        }
+
+        let mut values = vec![];
+        for (point, combination) in points.iter().zip(opening.combinations[1..].iter()) {
+            let eq = eq_table(&point[row_vars..].to_vec());
+            let mut value = E::zero();
+            for (e, c) in eq.iter().zip(combination.iter()) {
+                value += *e * c;
+            }
+            values.push(value);
+        }
+        Some(values)
+    }
+
+    // the codewords of the columns need roots of unity of their length
+    fn max_vars(&self) -> Option<usize> {
+        Some(2 * (B::S as usize).saturating_sub(self.rate_bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, omega, Ligero};
+    use crate::field::goldilocks::*;
+    use crate::gkr::{
+        pcs::Pcs,
+        poly::MultilinearPoly,
+        transcript::{HashType, Transcript},
+    };
+    use ff::Field;
+    use halo2curves::bn256::Fr;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_encode() {
+        // 1 + 2x + 3x^2 at 1, w, w^2, ..., w^7
+        let w = omega::<Fr>(3).unwrap();
+        let row = vec![Fr::from(1), Fr::from(2), Fr::from(3), Fr::zero()];
+        let codeword = encode(&row, 1, w);
+        for (i, c) in codeword.iter().enumerate() {
+            let x = w.pow_vartime([i as u64]);
+            assert_eq!(*c, Fr::from(1) + x * Fr::from(2) + x * x * Fr::from(3));
+        }
+    }
+
+    #[test]
+    fn test_queries() {
+        assert_eq!(Ligero::default().queries, 309);
+        // rate 1/2 lets a query through with probability 5/6
+        assert_eq!(Ligero::new(1, 100).queries, 381);
+    }
+
+    #[test]
+    fn test_open() {
+        let ligero = Ligero {
+            rate_bits: 2,
+            queries: 5,
+        };
+        let poly = MultilinearPoly::new((0..32).map(|i| Fr::from(i * i + 1)).collect());
+        let points = vec![
+            (1..6).map(Fr::from).collect(),
+            vec![Fr::one(), Fr::zero(), Fr::zero(), Fr::one(), Fr::one()],
+        ];
+        let (root, data) = Pcs::<_, Fr>::commit(&ligero, &poly, &mut OsRng);
+        let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+        let opening = ligero.open(&poly, &data, &points, &mut transcript, &mut OsRng);
+        assert_eq!(opening.columns.len(), 5);
+        let verify = |opening| {
+            let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+            ligero.verify(&root, &points, &opening, &mut transcript)
+        };
+        assert_eq!(
+            verify(opening.clone()),
+            Some(vec![poly.evaluate(&points[0]), Fr::from(362)])
+        );
+
+        let mut bad_combination = opening.clone();
+        bad_combination.combinations[1][0] += Fr::one();
+        assert_eq!(verify(bad_combination), None);
+        let mut bad_column = opening.clone();
+        bad_column.columns[0][0] += Fr::one();
+        assert_eq!(verify(bad_column), None);
+
+        // challenges from the extension, columns over the base field
+        let poly = MultilinearPoly::new((0..8).map(|i| Goldilocks::from(i + 7)).collect());
+        let point: Vec<GoldilocksExt2> = (0..3)
+            .map(|i| GoldilocksExt2::new(Goldilocks::from(i), Goldilocks::from(5)))
+            .collect();
+        let ligero = Ligero::default();
+        let (root, data) = Pcs::<_, GoldilocksExt2>::commit(&ligero, &poly, &mut OsRng);
+        let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+        let opening = ligero.open(
+            &poly,
+            &data,
+            &vec![point.clone()],
+            &mut transcript,
+            &mut OsRng,
+        );
+        let mut transcript = Transcript::new(b"test", HashType::Keccak256);
+        assert_eq!(
+            ligero.verify(&root, &vec![point.clone()], &opening, &mut transcript),
+            Some(vec![poly.evaluate(&point)])
+        );
+    }
+}
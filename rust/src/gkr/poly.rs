@@ -1,115 +1,369 @@
-use ethers_core::types::U256;
-use ff::PrimeField;
-use std::{collections::HashMap, vec};
+use ff::Field;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    marker::PhantomData,
+    ops::{Add, Mul},
+    vec,
+};
+
+/// coeff * x_1^exps[0] * ... * x_n^exps[n - 1]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseTerm<S: Field> {
+    pub coeff: S,
+    pub exps: Vec<u32>,
+}
 
-fn fe_to_u256<F>(f: F) -> U256
-where
-    F: PrimeField<Repr = [u8; 32]>,
-{
-    U256::from_little_endian(f.to_repr().as_ref())
+/// How a variable appears in a `BinaryTerm`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryFactor {
+    Absent,
+    /// 1 - x_i
+    OneMinusX,
+    /// x_i
+    X,
 }
 
-pub fn get_empty<S: PrimeField>(l: usize) -> Vec<Vec<S>> {
-    vec![vec![S::zero(); l + 1]; 1]
+/// coeff times a product of x_i and 1 - x_i factors.
+/// add_i and mult_i are sums of these, one per gate, so they never need expanding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinaryTerm<S: Field> {
+    pub coeff: S,
+    pub factors: Vec<BinaryFactor>,
 }
 
-fn minus_one<S: PrimeField>() -> S {
-    S::zero() - S::one()
+pub trait Term<S: Field>: Clone + Mul<Output = Self> {
+    /// What the term records for each variable.
+    type Var: Clone + Eq + Hash;
+
+    fn new(coeff: S, vars: Vec<Self::Var>) -> Self;
+    fn coeff(&self) -> S;
+    fn vars(&self) -> &Vec<Self::Var>;
 }
 
-fn constant_one<S: PrimeField>(l: usize) -> Vec<S> {
-    let mut vec = vec![S::zero(); l + 1];
-    vec[0] = S::one();
-    vec
+impl<S: Field> Term<S> for SparseTerm<S> {
+    type Var = u32;
+
+    fn new(coeff: S, exps: Vec<u32>) -> Self {
+        SparseTerm { coeff, exps }
+    }
+
+    fn coeff(&self) -> S {
+        self.coeff
+    }
+
+    fn vars(&self) -> &Vec<u32> {
+        &self.exps
+    }
 }
 
-/// For add_i and mult_i, they have only two types for term, x or 1 - x.
-/// Represent (1 - x) as 1, x as 2.
-pub fn chi_w_for_binary<S: PrimeField>(w: &String) -> Vec<Vec<S>> {
-    let l = w.len();
-    let mut prod = constant_one::<S>(l);
-    for (i, w_i) in w.chars().enumerate() {
-        if w_i == '0' {
-            // 1 - x_i
-            prod[i + 1] = S::one();
-        } else if w_i == '1' {
-            // x_i
-            prod[i + 1] = S::one() + S::one();
+impl<S: Field> Term<S> for BinaryTerm<S> {
+    type Var = BinaryFactor;
+
+    fn new(coeff: S, factors: Vec<BinaryFactor>) -> Self {
+        BinaryTerm { coeff, factors }
+    }
+
+    fn coeff(&self) -> S {
+        self.coeff
+    }
+
+    fn vars(&self) -> &Vec<BinaryFactor> {
+        &self.factors
+    }
+}
+
+impl<S: Field> Mul for SparseTerm<S> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        assert_eq!(self.exps.len(), rhs.exps.len());
+        let exps = self
+            .exps
+            .iter()
+            .zip(rhs.exps.iter())
+            .map(|(e1, e2)| e1 + e2)
+            .collect();
+        SparseTerm {
+            coeff: self.coeff * rhs.coeff,
+            exps,
+        }
+    }
+}
+
+impl<S: Field> Mul for BinaryTerm<S> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        assert_eq!(self.factors.len(), rhs.factors.len());
+        let factors = self
+            .factors
+            .iter()
+            .zip(rhs.factors.iter())
+            .map(|(f1, f2)| match (f1, f2) {
+                (BinaryFactor::Absent, f) | (f, BinaryFactor::Absent) => *f,
+                _ => panic!("product of binary terms sharing a variable is not multilinear"),
+            })
+            .collect();
+        BinaryTerm {
+            coeff: self.coeff * rhs.coeff,
+            factors,
+        }
+    }
+}
+
+impl<S: Field> SparseTerm<S> {
+    fn fix(&mut self, i: usize, x: &S) {
+        self.coeff *= x.pow_vartime([self.exps[i] as u64]);
+        self.exps[i] = 0;
+    }
+}
+
+impl<S: Field> BinaryTerm<S> {
+    fn fix(&mut self, i: usize, x: &S) {
+        match self.factors[i] {
+            BinaryFactor::OneMinusX => self.coeff *= S::one() - x,
+            BinaryFactor::X => self.coeff *= x,
+            BinaryFactor::Absent => {}
         }
+        self.factors[i] = BinaryFactor::Absent;
     }
-    vec![prod]
-}
-
-pub fn partial_eval_binary_form<S: PrimeField>(f: &Vec<Vec<S>>, x: &Vec<S>) -> Vec<Vec<S>> {
-    let l = x.len();
-    let mut new_f = vec![];
-    for term in f {
-        let mut new_term = vec![];
-        let mut new_const = term[0];
-        for i in 0..l {
-            let idx = i + 1;
-            if term[idx] == S::one() {
-                new_const *= S::one() - x[i];
-            } else if term[idx] == S::one() + S::one() {
-                new_const *= x[i];
+}
+
+/// Sum of terms in `num_vars` variables. Variables are numbered from 0 in method arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparsePoly<S: Field, T: Term<S> = SparseTerm<S>> {
+    pub num_vars: usize,
+    pub terms: Vec<T>,
+    _field: PhantomData<S>,
+}
+
+pub type BinaryPoly<S> = SparsePoly<S, BinaryTerm<S>>;
+
+impl<S: Field, T: Term<S>> SparsePoly<S, T> {
+    pub fn new(num_vars: usize, terms: Vec<T>) -> Self {
+        for t in terms.iter() {
+            assert_eq!(
+                t.vars().len(),
+                num_vars,
+                "term has a wrong number of variables"
+            );
+        }
+        SparsePoly {
+            num_vars,
+            terms,
+            _field: PhantomData,
+        }
+    }
+
+    pub fn zero(num_vars: usize) -> Self {
+        Self::new(num_vars, vec![])
+    }
+
+    /// Merges the terms over the same monomial and drops the zero ones.
+    pub fn simplify(&self) -> Self {
+        let mut index: HashMap<&Vec<T::Var>, usize> = HashMap::new();
+        let mut merged: Vec<(S, &Vec<T::Var>)> = vec![];
+        for t in self.terms.iter() {
+            if let Some(i) = index.get(t.vars()) {
+                merged[*i].0 += t.coeff();
+            } else {
+                index.insert(t.vars(), merged.len());
+                merged.push((t.coeff(), t.vars()));
             }
         }
-        new_term.push(new_const);
-        new_term.extend_from_slice(&term[l + 1..]);
-        new_f.push(new_term);
-    }
-    new_f
-}
-
-pub fn partial_eval_i_binary_form<S: PrimeField<Repr = [u8; 32]>>(
-    f: &Vec<Vec<S>>,
-    x: &S,
-    i: usize,
-) -> Vec<Vec<S>> {
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = t.clone();
-        let mut constant = t[0];
-        if t[i] == S::one() {
-            constant *= S::one() - x;
-        } else if t[i] == S::one() + S::one() {
-            constant *= x;
+        let terms = merged
+            .into_iter()
+            .filter(|(coeff, _)| *coeff != S::zero())
+            .map(|(coeff, vars)| T::new(coeff, vars.clone()))
+            .collect();
+        Self::new(self.num_vars, terms)
+    }
+}
+
+impl<'a, S: Field, T: Term<S>> Add for &'a SparsePoly<S, T> {
+    type Output = SparsePoly<S, T>;
+
+    fn add(self, rhs: Self) -> SparsePoly<S, T> {
+        assert_eq!(self.num_vars, rhs.num_vars);
+        let mut terms = self.terms.clone();
+        terms.extend_from_slice(&rhs.terms);
+        SparsePoly::new(self.num_vars, terms).simplify()
+    }
+}
+
+impl<'a, S: Field, T: Term<S>> Mul for &'a SparsePoly<S, T> {
+    type Output = SparsePoly<S, T>;
+
+    fn mul(self, rhs: Self) -> SparsePoly<S, T> {
+        assert_eq!(self.num_vars, rhs.num_vars);
+        let mut terms = vec![];
+        for t1 in self.terms.iter() {
+            for t2 in rhs.terms.iter() {
+                terms.push(t1.clone() * t2.clone());
+            }
         }
-        new_t[0] = constant;
-        new_t[i] = S::zero();
-        res_f.push(new_t);
+        SparsePoly::new(self.num_vars, terms).simplify()
     }
-    res_f
 }
 
-pub fn chi_w<S: PrimeField>(w: &String) -> Vec<Vec<S>> {
-    let l = w.len();
-    let mut prod_single = constant_one::<S>(l);
-    let mut prod_double = Vec::new();
-    for (i, w_i) in w.chars().enumerate() {
-        let idx = i + 1;
-        if w_i == '0' {
-            let mut subres = vec![];
-            let mut term = constant_one::<S>(l);
-            term[0] = minus_one();
-            term[idx] = S::one();
-            let one = constant_one::<S>(l);
-            subres.push(term);
-            subres.push(one);
-            prod_double.push(subres);
-        } else if w_i == '1' {
-            prod_single[idx] = S::one();
+impl<S: Field> SparsePoly<S, SparseTerm<S>> {
+    /// Fixes variable `i` to `x`, leaving it with exponent 0.
+    pub fn fix_var(&self, i: usize, x: &S) -> Self {
+        let mut res = self.clone();
+        for t in res.terms.iter_mut() {
+            t.fix(i, x);
         }
+        res
+    }
+
+    /// Fixes the first `r.len()` variables to `r` and drops them.
+    pub fn partial_eval(&self, r: &Vec<S>) -> Self {
+        assert!(self.num_vars >= r.len());
+        let l = r.len();
+        let mut terms = vec![];
+        for t in self.terms.iter() {
+            let mut new_t = t.clone();
+            for (i, r_i) in r.iter().enumerate() {
+                new_t.fix(i, r_i);
+            }
+            new_t.exps.drain(..l);
+            terms.push(new_t);
+        }
+        Self::new(self.num_vars - l, terms)
     }
-    let mut res = vec![prod_single];
-    for poly in prod_double {
-        let mut new_res = vec![];
-        for term in poly.iter() {
-            for res_term in res.iter() {
-                new_res.push(mult_mono(term, res_term));
+
+    /// Coefficients of variable `i`, highest degree first, when every other variable is fixed.
+    pub fn univariate_coeffs(&self, i: usize) -> Vec<S> {
+        let mut coeffs = vec![S::zero()];
+        for t in self.terms.iter() {
+            let deg = t.exps[i] as usize;
+            if coeffs.len() <= deg {
+                coeffs.resize(deg + 1, S::zero());
             }
+            coeffs[deg] += t.coeff;
         }
-        res = new_res;
+        coeffs.reverse();
+        coeffs
+    }
+
+    pub fn evaluate(&self, x: &Vec<S>) -> S {
+        assert_eq!(self.num_vars, x.len());
+        let mut res = S::zero();
+        for t in self.terms.iter() {
+            let mut term = t.coeff;
+            for (x_i, e) in x.iter().zip(t.exps.iter()) {
+                term *= x_i.pow_vartime([*e as u64]);
+            }
+            res += term;
+        }
+        res
+    }
+}
+
+impl<S: Field> SparsePoly<S, BinaryTerm<S>> {
+    /// Fixes variable `i` to `x`, leaving it absent.
+    pub fn fix_var(&self, i: usize, x: &S) -> Self {
+        let mut res = self.clone();
+        for t in res.terms.iter_mut() {
+            t.fix(i, x);
+        }
+        res
+    }
+
+    /// Fixes variables `i`, ..., `i + x.len() - 1` to `x`, leaving them absent.
+    pub fn fix_vars_from(&self, x: &[S], i: usize) -> Self {
+        let mut res = self.clone();
+        for t in res.terms.iter_mut() {
+            for (j, x_j) in x.iter().enumerate() {
+                t.fix(i + j, x_j);
+            }
+        }
+        res
+    }
+
+    /// Fixes the first `x.len()` variables to `x` and drops them.
+    pub fn partial_eval(&self, x: &Vec<S>) -> Self {
+        assert!(self.num_vars >= x.len());
+        let l = x.len();
+        let mut terms = vec![];
+        for t in self.terms.iter() {
+            let mut new_t = t.clone();
+            for (i, x_i) in x.iter().enumerate() {
+                new_t.fix(i, x_i);
+            }
+            new_t.factors.drain(..l);
+            terms.push(new_t);
+        }
+        Self::new(self.num_vars - l, terms)
+    }
+
+    /// Coefficients of variable `i`, highest degree first, when every other variable is fixed.
+    pub fn univariate_coeffs(&self, i: usize) -> Vec<S> {
+        let mut coeffs = vec![S::zero(); 2];
+        for t in self.terms.iter() {
+            match t.factors[i] {
+                BinaryFactor::OneMinusX => {
+                    coeffs[0] -= t.coeff;
+                    coeffs[1] += t.coeff;
+                }
+                BinaryFactor::X => coeffs[0] += t.coeff,
+                BinaryFactor::Absent => coeffs[1] += t.coeff,
+            }
+        }
+        coeffs
+    }
+
+    pub fn evaluate<E: Field + From<S>>(&self, x: &Vec<E>) -> E {
+        assert_eq!(self.num_vars, x.len());
+        let mut res = E::zero();
+        for t in self.terms.iter() {
+            let mut term = E::from(t.coeff);
+            for (i, x_i) in x.iter().enumerate() {
+                match t.factors[i] {
+                    BinaryFactor::OneMinusX => term *= E::one() - x_i,
+                    BinaryFactor::X => term *= x_i,
+                    BinaryFactor::Absent => {}
+                }
+            }
+            res += term;
+        }
+        res
+    }
+}
+
+/// eq(w, x) for a gate label `w` in binary.
+pub fn chi_w_for_binary<S: Field>(w: &String) -> BinaryPoly<S> {
+    let factors = w
+        .chars()
+        .map(|w_i| match w_i {
+            '0' => BinaryFactor::OneMinusX,
+            '1' => BinaryFactor::X,
+            _ => BinaryFactor::Absent,
+        })
+        .collect();
+    SparsePoly::new(w.len(), vec![BinaryTerm::new(S::one(), factors)])
+}
+
+/// eq(w, x) expanded into monomials.
+pub fn chi_w<S: Field>(w: &String) -> SparsePoly<S> {
+    let l = w.len();
+    let mut res = SparsePoly::new(l, vec![SparseTerm::new(S::one(), vec![0; l])]);
+    for (i, w_i) in w.chars().enumerate() {
+        let mut x_i = vec![0; l];
+        x_i[i] = 1;
+        let factor = if w_i == '1' {
+            vec![SparseTerm::new(S::one(), x_i)]
+        } else if w_i == '0' {
+            // 1 - x_i
+            vec![
+                SparseTerm::new(S::zero() - S::one(), x_i),
+                SparseTerm::new(S::one(), vec![0; l]),
+            ]
+        } else {
+            continue;
+        };
+        res = &res * &SparsePoly::new(l, factor);
     }
     res
 }
@@ -130,8 +384,8 @@ pub fn generate_binary_string(l: usize) -> Vec<String> {
     }
 }
 
-pub fn generate_binary<S: PrimeField>(l: usize) -> Vec<Vec<S>> {
-    fn genbin<S: PrimeField>(n: usize, current: usize, acc: Vec<Vec<S>>) -> Vec<Vec<S>> {
+pub fn generate_binary<S: Field>(l: usize) -> Vec<Vec<S>> {
+    fn genbin<S: Field>(n: usize, current: usize, acc: Vec<Vec<S>>) -> Vec<Vec<S>> {
         if current == n {
             acc
         } else {
@@ -157,107 +411,7 @@ pub fn generate_binary<S: PrimeField>(l: usize) -> Vec<Vec<S>> {
     genbin(l, 0, vec![])
 }
 
-pub fn partial_eval_i<S: PrimeField<Repr = [u8; 32]>>(
-    f: &Vec<Vec<S>>,
-    x: &S,
-    i: usize,
-) -> Vec<Vec<S>> {
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = t.clone();
-        let exp = fe_to_u256(t[i]).as_usize();
-        let mut x_pow = S::one();
-        for _ in 0..exp {
-            x_pow *= x;
-        }
-        let constant = t[0] * x_pow;
-        new_t[0] = constant;
-        new_t[i] = S::zero();
-        res_f.push(new_t);
-    }
-    res_f
-}
-
-pub fn partial_eval_from<S: PrimeField<Repr = [u8; 32]>>(
-    f: &Vec<Vec<S>>,
-    r: &Vec<S>,
-    idx: usize,
-) -> Vec<Vec<S>> {
-    assert!(f[0].len() > r.len());
-    if r.len() == 0 {
-        return f.clone();
-    }
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = t.clone();
-        let mut constant = t[0];
-        for i in 0..r.len() {
-            if t[idx + i] == S::zero() {
-                continue;
-            }
-            let x = fe_to_u256(t[idx + i]).as_usize();
-            for _ in 0..x {
-                constant *= r[i];
-            }
-            new_t[idx + i] = S::zero();
-        }
-        new_t[0] = constant;
-        res_f.push(new_t);
-    }
-    res_f
-}
-
-pub fn partial_eval_from_binary_form<S: PrimeField<Repr = [u8; 32]>>(
-    f: &Vec<Vec<S>>,
-    x: &Vec<S>,
-    idx: usize,
-) -> Vec<Vec<S>> {
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = t.clone();
-        let mut constant = t[0];
-        for i in 0..x.len() {
-            if t[idx + i] == S::one() {
-                constant *= S::one() - x[i];
-                new_t[idx + i] = S::zero();
-            } else if t[idx + i] == S::one() + S::one() {
-                constant *= x[i];
-                new_t[idx + i] = S::zero();
-            }
-        }
-
-        new_t[0] = constant;
-        res_f.push(new_t);
-    }
-    res_f
-}
-
-pub fn partial_eval<S: PrimeField<Repr = [u8; 32]>>(f: &Vec<Vec<S>>, r: &Vec<S>) -> Vec<Vec<S>> {
-    assert!(f[0].len() > r.len());
-    if r.len() == 0 {
-        return f.clone();
-    }
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = vec![];
-        let mut constant = t[0];
-        for i in 0..r.len() {
-            let x = fe_to_u256(t[i + 1]).as_usize();
-            if x == 0 {
-                continue;
-            }
-            for _ in 0..x {
-                constant *= r[i];
-            }
-        }
-        new_t.push(constant);
-        new_t.extend_from_slice(&t[r.len() + 1..]);
-        res_f.push(new_t);
-    }
-    res_f
-}
-
-pub fn eval_univariate<S: PrimeField<Repr = [u8; 32]>>(f: &Vec<S>, x: &S) -> S {
+pub fn eval_univariate<S: Field>(f: &Vec<S>, x: &S) -> S {
     let mut res = f[0];
     for i in f.iter().skip(1) {
         res *= x;
@@ -266,160 +420,7 @@ pub fn eval_univariate<S: PrimeField<Repr = [u8; 32]>>(f: &Vec<S>, x: &S) -> S {
     res
 }
 
-pub fn modify_poly_from_k<S: PrimeField>(f: &Vec<Vec<S>>, k: usize) -> Vec<Vec<S>> {
-    let mut res_f = vec![];
-    for t in f.iter() {
-        let mut new_t = vec![t[0]];
-        let mut zeros = vec![S::zero(); k];
-        new_t.append(&mut zeros);
-        new_t.extend_from_slice(&t[1..]);
-
-        res_f.push(new_t);
-    }
-    res_f
-}
-
-pub fn extend_length<S: PrimeField>(f: &Vec<S>, l: usize) -> Vec<S> {
-    if f.len() == l {
-        f.clone()
-    } else {
-        let mut new_f = f.clone();
-        let mut zeros = vec![S::zero(); l - f.len()];
-        new_f.append(&mut zeros);
-        new_f
-    }
-}
-
-pub fn add_poly<S: PrimeField + std::hash::Hash>(
-    f1: &Vec<Vec<S>>,
-    f2: &Vec<Vec<S>>,
-) -> Vec<Vec<S>> {
-    let mut map: HashMap<Vec<S>, S> = HashMap::new();
-    let mut len1 = 0;
-    let mut len2 = 0;
-    if f1.len() != 0 {
-        len1 = f1[0].len();
-    }
-    if f2.len() != 0 {
-        len2 = f2[0].len();
-    }
-    let len = if len1 > len2 { len1 } else { len2 };
-    let mut res = vec![];
-    for t in f1 {
-        let t_ext = extend_length(t, len);
-        if map.contains_key(&t_ext[1..]) {
-            *map.get_mut(&t_ext[1..]).unwrap() += t_ext[0];
-        } else {
-            map.insert(t_ext[1..].to_vec(), t_ext[0]);
-        }
-    }
-    for t in f2 {
-        let t_ext = extend_length(t, len);
-        if map.contains_key(&t_ext[1..]) {
-            *map.get_mut(&t_ext[1..]).unwrap() += t_ext[0];
-        } else {
-            map.insert(t_ext[1..].to_vec(), t_ext[0]);
-        }
-    }
-    for (poly, constant) in map.iter() {
-        if constant.clone() == S::zero() {
-            continue;
-        }
-        let mut new_poly = vec![constant.clone()];
-        let mut p_cloned = poly.clone();
-        new_poly.append(&mut p_cloned);
-        res.push(new_poly)
-    }
-    res
-}
-
-fn mult_mono<S: PrimeField>(t1: &Vec<S>, t2: &Vec<S>) -> Vec<S> {
-    assert_eq!(t1.len(), t2.len());
-    let mut res = vec![];
-    for i in 0..t1.len() {
-        if i == 0 {
-            res.push(t1[0] * t2[0]);
-        } else {
-            res.push(t1[i] + t2[i]);
-        }
-    }
-    res
-}
-
-pub fn mult_poly<S: PrimeField + std::hash::Hash>(
-    f1: &Vec<Vec<S>>,
-    f2: &Vec<Vec<S>>,
-) -> Vec<Vec<S>> {
-    let mut map: HashMap<Vec<S>, S> = HashMap::new();
-    let mut len1 = 0;
-    let mut len2 = 0;
-    if f1.len() != 0 {
-        len1 = f1[0].len();
-    }
-    if f2.len() != 0 {
-        len2 = f2[0].len();
-    }
-    let len = if len1 > len2 { len1 } else { len2 };
-
-    let mut res = vec![];
-
-    for t1 in f1 {
-        for t2 in f2 {
-            let t = mult_mono(&extend_length(t1, len), &extend_length(t2, len));
-            if map.contains_key(&t[1..]) {
-                *map.get_mut(&t[1..]).unwrap() += t[0];
-            } else {
-                map.insert(t[1..].to_vec(), t[0]);
-            }
-        }
-    }
-    for (poly, constant) in map.iter() {
-        if constant.clone() == S::zero() {
-            continue;
-        }
-        let mut new_poly = vec![constant.clone()];
-        let mut p_cloned = poly.clone();
-        new_poly.append(&mut p_cloned);
-        res.push(new_poly)
-    }
-    res
-}
-
-pub fn get_univariate_coeff<S: PrimeField<Repr = [u8; 32]>>(
-    f: &Vec<Vec<S>>,
-    i: usize,
-    is_binary_form: bool,
-) -> Vec<S> {
-    if is_binary_form {
-        let mut coeffs = vec![S::zero(); 2];
-        for t in f {
-            let constant = t[0];
-            if t[i] == S::one() {
-                coeffs[0] += constant;
-                coeffs[1] += minus_one::<S>() * constant;
-            } else if t[i] == S::one() + S::one() {
-                coeffs[1] += constant;
-            }
-        }
-        coeffs.reverse();
-        coeffs
-    } else {
-        let mut coeffs = vec![S::zero()];
-        for t in f {
-            let deg_u256 = fe_to_u256(t[i]);
-            let deg = deg_u256.as_usize();
-            if coeffs.len() - 1 < deg {
-                let mut acc = vec![S::zero(); deg - coeffs.len() + 1];
-                coeffs.append(&mut acc);
-            }
-            coeffs[deg] += t[0];
-        }
-        coeffs.reverse();
-        coeffs
-    }
-}
-
-pub fn mult_univariate<S: PrimeField<Repr = [u8; 32]>>(p: &Vec<S>, q: &Vec<S>) -> Vec<S> {
+pub fn mult_univariate<S: Field>(p: &Vec<S>, q: &Vec<S>) -> Vec<S> {
     let h_deg_p = p.len() - 1;
     let h_deg_q = q.len() - 1;
     let mut p_rev = p.clone();
@@ -441,7 +442,7 @@ pub fn mult_univariate<S: PrimeField<Repr = [u8; 32]>>(p: &Vec<S>, q: &Vec<S>) -
     res
 }
 
-pub fn add_univariate<S: PrimeField<Repr = [u8; 32]>>(p: &Vec<S>, q: &Vec<S>) -> Vec<S> {
+pub fn add_univariate<S: Field>(p: &Vec<S>, q: &Vec<S>) -> Vec<S> {
     if p.len() == 0 {
         return q.clone();
     } else if q.len() == 0 {
@@ -466,76 +467,39 @@ pub fn add_univariate<S: PrimeField<Repr = [u8; 32]>>(p: &Vec<S>, q: &Vec<S>) ->
     res
 }
 
-pub fn reduce_multiple_polynomial<S: PrimeField<Repr = [u8; 32]>>(
-    b: &Vec<S>,
-    c: &Vec<S>,
-    w: &Vec<Vec<S>>,
-) -> Vec<S> {
+/// q(t) = w(l(t)) where l is the line with l(0) = b and l(1) = c.
+pub fn reduce_multiple_polynomial<B: Field, E: Field + From<u64> + From<B>>(
+    b: &Vec<E>,
+    c: &Vec<E>,
+    w: &MultilinearPoly<B>,
+) -> Vec<E> {
     assert_eq!(b.len(), c.len());
-    let mut res = vec![S::zero()];
-    let mut t = vec![];
-    let iterator = b.iter().zip(c.iter());
-    for (b_i, c_i) in iterator {
-        let gradient = *c_i - *b_i;
-        let new_const = *b_i;
-        t.push((new_const, gradient));
-    }
-    for terms in w {
-        let mut new_poly = vec![S::one()];
-        for (i, d) in terms.iter().enumerate() {
-            if i == 0 {
-                new_poly[0] = d.clone();
-                continue;
-            }
-            let idx = i - 1;
-            let deg = fe_to_u256(*d).as_usize();
-            for _ in 0..deg {
-                let term = vec![t[idx].1, t[idx].0];
-                new_poly = mult_univariate(&new_poly, &term);
-            }
-        }
-        res = add_univariate(&res, &new_poly);
+    // q has degree at most k, so k + 1 evaluations determine it
+    let mut values = vec![];
+    for t in 0..(b.len() + 1) {
+        let point = l_function(b, c, &E::from(t as u64));
+        values.push(w.evaluate(&point));
     }
-    res
+    interpolate_univariate(&values)
 }
 
-pub fn get_multi_ext<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(value: &Vec<S>, v: usize) -> Vec<Vec<S>> {
-    let mut map: HashMap<Vec<S>, S> = HashMap::new();
-    let binary = generate_binary_string(v);
-    let mut polynomial: Vec<Vec<S>> = vec![];
-    for b in binary {
+pub fn get_multi_ext<S: Field>(value: &Vec<S>, v: usize) -> SparsePoly<S> {
+    let mut terms = vec![];
+    for b in generate_binary_string(v) {
         let idx = usize::from_str_radix(&b, 2).unwrap();
         let val = value[idx];
         if val == S::zero() {
             continue;
         }
-        let mut res = chi_w::<S>(&b);
-        for i in 0..res.len() {
-            res[i][0] *= val
+        for mut t in chi_w::<S>(&b).terms {
+            t.coeff *= val;
+            terms.push(t);
         }
-        polynomial.append(&mut res);
     }
-    let mut res = vec![];
-    for t in polynomial {
-        if map.contains_key(&t[1..]) {
-            *map.get_mut(&t[1..]).unwrap() += t[0];
-        } else {
-            map.insert(t[1..].to_vec(), t[0]);
-        }
-    }
-    for (poly, constant) in map.iter() {
-        if constant.clone() == S::zero() {
-            continue;
-        }
-        let mut new_poly = vec![constant.clone()];
-        let mut p_cloned = poly.clone();
-        new_poly.append(&mut p_cloned);
-        res.push(new_poly)
-    }
-    res
+    SparsePoly::new(v, terms).simplify()
 }
 
-pub fn l_function<S: PrimeField<Repr = [u8; 32]>>(b: &Vec<S>, c: &Vec<S>, r: &S) -> Vec<S> {
+pub fn l_function<S: Field>(b: &Vec<S>, c: &Vec<S>, r: &S) -> Vec<S> {
     let mut res = vec![];
     let mut t = vec![];
     let iterator = b.iter().zip(c.iter());
@@ -549,3 +513,167 @@ pub fn l_function<S: PrimeField<Repr = [u8; 32]>>(b: &Vec<S>, c: &Vec<S>, r: &S)
     }
     res
 }
+
+/// Multilinear polynomial in evaluation form. `evals[i]` is the value at the binary
+/// expansion of `i`, with x_1 as the most significant bit like `get_multi_ext`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultilinearPoly<S: Field> {
+    pub num_vars: usize,
+    pub evals: Vec<S>,
+}
+
+impl<S: Field> MultilinearPoly<S> {
+    pub fn new(evals: Vec<S>) -> Self {
+        assert!(
+            evals.len().is_power_of_two(),
+            "number of evaluations should be a power of 2"
+        );
+        let num_vars = evals.len().trailing_zeros() as usize;
+        MultilinearPoly { num_vars, evals }
+    }
+
+    pub fn zero(num_vars: usize) -> Self {
+        MultilinearPoly {
+            num_vars,
+            evals: vec![S::zero(); 1 << num_vars],
+        }
+    }
+
+    /// Fixes x_1 to `r`.
+    pub fn fix_variable(&self, r: &S) -> Self {
+        assert!(self.num_vars > 0);
+        let half = self.evals.len() / 2;
+        let evals = (0..half)
+            .map(|i| self.evals[i] + *r * (self.evals[half + i] - self.evals[i]))
+            .collect();
+        MultilinearPoly {
+            num_vars: self.num_vars - 1,
+            evals,
+        }
+    }
+
+    /// Fixes x_1, ..., x_l to `r`.
+    pub fn partial_eval(&self, r: &Vec<S>) -> Self {
+        let mut res = self.clone();
+        for r_i in r.iter() {
+            res = res.fix_variable(r_i);
+        }
+        res
+    }
+
+    /// Evaluates at `x`, which may lie in an extension of the field of the evaluations.
+    pub fn evaluate<E: Field + From<S>>(&self, x: &Vec<E>) -> E {
+        assert_eq!(self.num_vars, x.len());
+        let eq = eq_table(x);
+        self.evals
+            .iter()
+            .zip(eq.iter())
+            .fold(E::zero(), |acc, (e, t)| acc + E::from(*e) * t)
+    }
+
+    pub fn lift<E: Field + From<S>>(&self) -> MultilinearPoly<E> {
+        MultilinearPoly {
+            num_vars: self.num_vars,
+            evals: self.evals.iter().map(|e| E::from(*e)).collect(),
+        }
+    }
+
+    /// Coefficients of x_1 -> f(x_1, rest) for a boolean `rest`, highest degree first.
+    pub fn linear_in_first(&self, rest: &[S]) -> Vec<S> {
+        assert_eq!(self.num_vars, rest.len() + 1);
+        let idx = bits_to_index(rest);
+        let half = self.evals.len() / 2;
+        vec![self.evals[half + idx] - self.evals[idx], self.evals[idx]]
+    }
+}
+
+pub fn bits_to_index<S: Field>(bits: &[S]) -> usize {
+    bits.iter()
+        .fold(0, |acc, b| (acc << 1) | if *b == S::one() { 1 } else { 0 })
+}
+
+/// The `num_vars` bits of `index`, most significant first.
+pub fn index_to_bits<S: Field>(index: usize, num_vars: usize) -> Vec<S> {
+    (0..num_vars)
+        .rev()
+        .map(|i| {
+            if (index >> i) & 1 == 1 {
+                S::one()
+            } else {
+                S::zero()
+            }
+        })
+        .collect()
+}
+
+/// eq(r, x) for every x in the hypercube, in the order of `MultilinearPoly::evals`.
+pub fn eq_table<S: Field>(r: &Vec<S>) -> Vec<S> {
+    let mut table = vec![S::one()];
+    for r_i in r.iter() {
+        let mut next = Vec::with_capacity(table.len() * 2);
+        for t in table.iter() {
+            next.push(*t * (S::one() - r_i));
+            next.push(*t * r_i);
+        }
+        table = next;
+    }
+    table
+}
+
+pub fn eq_eval<S: Field>(x: &Vec<S>, y: &Vec<S>) -> S {
+    assert_eq!(x.len(), y.len());
+    x.iter().zip(y.iter()).fold(S::one(), |acc, (x_i, y_i)| {
+        acc * (*x_i * y_i + (S::one() - x_i) * (S::one() - y_i))
+    })
+}
+
+/// Coefficients, highest degree first, of the polynomial of degree < ys.len() through (i, ys[i]).
+pub fn interpolate_univariate<S: Field + From<u64>>(ys: &Vec<S>) -> Vec<S> {
+    let n = ys.len();
+    let mut res = vec![S::zero(); n];
+    for i in 0..n {
+        let mut basis = vec![S::one()];
+        let mut denom = S::one();
+        for j in 0..n {
+            if i == j {
+                continue;
+            }
+            basis = mult_univariate(&basis, &vec![S::one(), S::zero() - S::from(j as u64)]);
+            denom *= S::from(i as u64) - S::from(j as u64);
+        }
+        let scale = ys[i] * denom.invert().unwrap();
+        for (res_c, basis_c) in res.iter_mut().zip(basis.iter()) {
+            *res_c += *basis_c * scale;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2curves::bn256::Fr;
+
+    #[test]
+    fn test_sparse_forms_agree() {
+        let w = MultilinearPoly::new((0..8).map(|i| Fr::from(3 * i + 1)).collect());
+        let x = vec![Fr::from(4), Fr::from(7), Fr::from(9)];
+        assert_eq!(get_multi_ext(&w.evals, 3).evaluate(&x), w.evaluate(&x));
+
+        let label = String::from("101");
+        let expected = eq_eval(&vec![Fr::one(), Fr::zero(), Fr::one()], &x);
+        assert_eq!(chi_w::<Fr>(&label).evaluate(&x), expected);
+        assert_eq!(chi_w_for_binary::<Fr>(&label).evaluate(&x), expected);
+
+        // like terms are merged: x_1 * (1 + 1) = 2 * x_1
+        let a = SparsePoly::new(1, vec![SparseTerm::new(Fr::one(), vec![1])]);
+        let b = SparsePoly::new(
+            1,
+            vec![
+                SparseTerm::new(Fr::one(), vec![0]),
+                SparseTerm::new(Fr::one(), vec![0]),
+            ],
+        );
+        assert_eq!((&a * &b).terms, vec![SparseTerm::new(Fr::from(2), vec![1])]);
+    }
+}
@@ -1,96 +1,486 @@
-use super::{poly::*, sumcheck::*, GKRCircuit, Input, Proof};
+use super::{
+    pcs::{Clear, Pcs},
+    poly::*,
+    sumcheck::*,
+    transcript::{HashType, Transcript, TranscriptField},
+    zk::{mask_at, mask_sum, vanishing, MaskLayout, MASK_DEGREE},
+    GKRCircuit, Input, Proof,
+};
 use ff::PrimeField;
-use mimc_rs::{Fr, Mimc7};
-use std::vec;
+use rand_core::{OsRng, RngCore};
+use std::{fmt, vec};
 
-pub fn prove<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(
+/// How `prove_with_options` proves a circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProverOptions {
+    /// Zero knowledge, as in Libra: every sumcheck is masked by ρ Σ_j g_j(x_j) for random
+    /// univariates g_j, the values of the inner layers are sent masked by polynomials that
+    /// vanish on the hypercube, and the input layer is padded with random values. Needs a
+    /// hiding `Pcs`. Only single inputs are proven in zero knowledge, not batches.
+    pub zk: bool,
+}
+
+/// Reason `prove_with_options` cannot prove a circuit with the given commitment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProveError {
+    /// The parameters of the `Pcs` commit to at most `max` variables, `needed` are committed.
+    TooManyVars { needed: usize, max: usize },
+    /// Zero knowledge was asked for, but the `Pcs` does not hide.
+    NotHiding,
+    /// The hash is not defined over the challenge field.
+    UnsupportedHash(HashType),
+    /// A batch of no inputs.
+    EmptyBatch,
+    /// Zero knowledge masks the values of the inner layers by polynomials of their variables,
+    /// so `layer` of a single gate cannot be masked.
+    SingleGateLayer { layer: usize },
+}
+
+impl fmt::Display for ProveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProveError::TooManyVars { needed, max } => write!(
+                f,
+                "the input layer needs a commitment to {} variables, the parameters allow {}",
+                needed, max
+            ),
+            ProveError::NotHiding => write!(f, "zero knowledge needs a hiding commitment"),
+            ProveError::UnsupportedHash(hash) => {
+                write!(f, "{} transcript needs limbs below the BN254 modulus", hash)
+            }
+            ProveError::EmptyBatch => write!(f, "a batch needs an input"),
+            ProveError::SingleGateLayer { layer } => write!(
+                f,
+                "zero knowledge needs two gates or more in layer {}",
+                layer
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProveError {}
+
+pub fn prove<S: PrimeField + TranscriptField>(
     circuit: &GKRCircuit<S>,
     input: &Input<S>,
-) -> Proof<S> {
-    let mimc = Mimc7::new(91);
+    hash: HashType,
+) -> Result<Proof<S>, ProveError> {
+    prove_ext(circuit, input, hash)
+}
+
+/// Proves a circuit over B with the challenges drawn from an extension E of B.
+pub fn prove_ext<B: PrimeField, E: TranscriptField + From<B>>(
+    circuit: &GKRCircuit<B>,
+    input: &Input<B>,
+    hash: HashType,
+) -> Result<Proof<B, E>, ProveError> {
+    prove_with(circuit, input, &Clear, hash)
+}
+
+/// Proves a circuit with the input layer committed to by `pcs` instead of sent in the clear.
+pub fn prove_with<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    input: &Input<B>,
+    pcs: &P,
+    hash: HashType,
+) -> Result<Proof<B, E, P>, ProveError> {
+    prove_with_options(
+        circuit,
+        input,
+        pcs,
+        hash,
+        &ProverOptions::default(),
+        &mut OsRng,
+    )
+}
+
+/// `prove_with` as `options` asks, with the commitment blinded by `rng`. Fails if `hash` is
+/// not defined over E, if the parameters of `pcs` are too small for the committed polynomial,
+/// or if zero knowledge is asked for and `pcs` does not hide.
+pub fn prove_with_options<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    input: &Input<B>,
+    pcs: &P,
+    hash: HashType,
+    options: &ProverOptions,
+    rng: &mut impl RngCore,
+) -> Result<Proof<B, E, P>, ProveError> {
+    if !hash.supports::<E>() {
+        return Err(ProveError::UnsupportedHash(hash));
+    }
+    if options.zk && !pcs.hiding() {
+        return Err(ProveError::NotHiding);
+    }
+    if options.zk {
+        if let Some(layer) = (1..circuit.depth()).find(|i| circuit.k(*i) == 0) {
+            return Err(ProveError::SingleGateLayer { layer });
+        }
+    }
+    let needed = committed_vars(circuit, options);
+    if let Some(max) = pcs.max_vars() {
+        if needed > max {
+            return Err(ProveError::TooManyVars { needed, max });
+        }
+    }
+    let wires = circuit.layer.iter().map(|layer| &layer.wire).collect();
+    if options.zk {
+        return Ok(prove_copies_zk(circuit, &wires, &input.w, pcs, hash, rng));
+    }
+    Ok(prove_copies(circuit, 0, &wires, &input.w, pcs, hash, rng))
+}
+
+/// Number of variables of the polynomial `prove_with_options` commits to, which the
+/// parameters of the `Pcs` must allow: the input layer, or the table of `MaskLayout` in zero
+/// knowledge.
+pub fn committed_vars<B: PrimeField>(circuit: &GKRCircuit<B>, options: &ProverOptions) -> usize {
+    if options.zk {
+        let k = (0..(circuit.depth() + 1)).map(|i| circuit.k(i)).collect();
+        MaskLayout::new(&k).num_vars
+    } else {
+        circuit.k(circuit.depth())
+    }
+}
+
+/// Proves many inputs of one circuit at once, as a single data-parallel circuit of
+/// 2^n >= inputs.len() copies that share the wiring of `circuit`. The proof is that of one
+/// copy with n more variables per layer, so the sumchecks grow by n rounds per layer, but the
+/// input layer is sent in the clear and grows linearly with the batch; `prove_batch_with`
+/// commits to it instead. Missing copies repeat the last input.
+pub fn prove_batch<S: PrimeField + TranscriptField>(
+    circuit: &GKRCircuit<S>,
+    inputs: &[Input<S>],
+    hash: HashType,
+) -> Result<Proof<S>, ProveError> {
+    prove_batch_with(circuit, inputs, &Clear, hash)
+}
+
+/// `prove_batch` with the input layers of all copies committed to by `pcs` as one table,
+/// opened as that of a single copy, so that only the public values grow linearly.
+pub fn prove_batch_with<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    inputs: &[Input<B>],
+    pcs: &P,
+    hash: HashType,
+) -> Result<Proof<B, E, P>, ProveError> {
+    if !hash.supports::<E>() {
+        return Err(ProveError::UnsupportedHash(hash));
+    }
+    if inputs.is_empty() {
+        return Err(ProveError::EmptyBatch);
+    }
+    let n = inputs.len().next_power_of_two().trailing_zeros() as usize;
+    let needed = circuit.k(circuit.depth()) + n;
+    if let Some(max) = pcs.max_vars() {
+        if needed > max {
+            return Err(ProveError::TooManyVars { needed, max });
+        }
+    }
+
+    // copy j of a gate is at j * 2^k + gate, so its label is prefixed by j in n bits
+    let replicate = |wires: &Vec<Vec<B>>, k: usize, k_next: usize| -> Vec<Vec<B>> {
+        let mut res = vec![];
+        for j in 0..(1 << n) {
+            let prefix: Vec<B> = index_to_bits(j, n);
+            for w in wires.iter() {
+                let mut wire = prefix.clone();
+                wire.extend_from_slice(&w[..k]);
+                wire.extend_from_slice(&prefix);
+                wire.extend_from_slice(&w[k..k + k_next]);
+                wire.extend_from_slice(&prefix);
+                wire.extend_from_slice(&w[k + k_next..]);
+                res.push(wire);
+            }
+        }
+        res
+    };
+    let batch_wires: Vec<(Vec<Vec<B>>, Vec<Vec<B>>)> = circuit
+        .layer
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| {
+            let k_next = circuit.k(i + 1);
+            (
+                replicate(&layer.wire.0, layer.k, k_next),
+                replicate(&layer.wire.1, layer.k, k_next),
+            )
+        })
+        .collect();
+    let wires = batch_wires.iter().collect();
+
+    let mut w = vec![];
+    for i in 0..(circuit.depth() + 1) {
+        let mut evals = vec![];
+        for j in 0..(1 << n) {
+            let input = &inputs[j.min(inputs.len() - 1)];
+            evals.extend_from_slice(&input.w(i).evals);
+        }
+        w.push(MultilinearPoly::new(evals));
+    }
+
+    Ok(prove_copies(circuit, n, &wires, &w, pcs, hash, &mut OsRng))
+}
+
+// starts the transcript of 2^n copies of `circuit` and binds the public values, which are sent
+// in the clear, before any challenge. Returns the input layer with the public gates set to zero.
+fn bind_statement<B: PrimeField, E: TranscriptField + From<B>>(
+    circuit: &GKRCircuit<B>,
+    n: usize,
+    input: &MultilinearPoly<B>,
+    hash: HashType,
+) -> (Transcript<E>, MultilinearPoly<B>, Vec<(usize, B)>) {
+    let mut transcript = Transcript::new(b"gkr", hash);
+    transcript.append_circuit(circuit);
+    if n > 0 {
+        transcript.append(b"batch", &E::from(n as u64));
+    }
+    let mut input_func = input.clone();
+    let mut public = vec![];
+    for pos in circuit.public_positions(n) {
+        public.push((pos, input_func.evals[pos]));
+        input_func.evals[pos] = B::zero();
+    }
+    let values = public.iter().map(|(_, v)| E::from(*v)).collect();
+    transcript.append_vec(b"public", &values);
+    (transcript, input_func, public)
+}
+
+// GKR over 2^n copies of `circuit`, given the wires of all copies and the values of all layers
+fn prove_copies<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    n: usize,
+    wires: &Vec<&(Vec<Vec<B>>, Vec<Vec<B>>)>,
+    w: &Vec<MultilinearPoly<B>>,
+    pcs: &P,
+    hash: HashType,
+    rng: &mut impl RngCore,
+) -> Proof<B, E, P> {
+    let k = |i: usize| circuit.k(i) + n;
+    let (mut transcript, input_func, public) =
+        bind_statement(circuit, n, &w[circuit.depth()], hash);
+    let (commitment, data) = pcs.commit(&input_func, rng);
+    pcs.append_commitment(&mut transcript, &commitment);
 
     let mut sumcheck_proofs = vec![];
     let mut sumcheck_r = vec![];
     let mut q = vec![];
     let mut r_stars = vec![];
+    // every output gate should be zero, so the claim is W_0(z[0]) = 0 at a random z[0]
     let mut z_zero = vec![];
-    for _ in 0..circuit.layer[0].k {
-        z_zero.push(S::zero());
+    for _ in 0..k(0) {
+        z_zero.push(transcript.challenge(b"output point"));
     }
     let mut z = vec![];
     z.push(z_zero);
 
     for i in 0..circuit.depth() {
-        let add = circuit.add(i);
-        let mut add_res = vec![];
-        if z[i].len() == 0 {
-            add_res = add.clone();
-        } else {
-            add_res = partial_eval_binary_form(&add, &z[i]);
-        }
-        let mult = circuit.mult(i);
-        let mut mult_res = vec![];
-        if z[i].len() == 0 {
-            mult_res = mult.clone();
-        } else {
-            mult_res = partial_eval_binary_form(&mult, &z[i]);
-        }
-        let w_i = input.w(i + 1).clone();
-        let mut w_i_ext_b = vec![];
-        for t in w_i.iter() {
-            w_i_ext_b.push(extend_length(t, 2 * circuit.k(i + 1) + 1));
-        }
-        let mut w_i_ext_c = modify_poly_from_k(&input.w(i + 1), circuit.k(i + 1));
-
-        if w_i_ext_b.len() == 0 {
-            w_i_ext_b = vec![vec![S::zero(); 2 * circuit.k(i + 1) + 1]];
-        }
-        if w_i_ext_c.len() == 0 {
-            w_i_ext_c = vec![vec![S::zero(); 2 * circuit.k(i + 1) + 1]];
-        }
-
-        let (sumcheck_proof, r) = prove_sumcheck_opt(
-            &circuit.add_wire(i),
-            &circuit.mult_wire(i),
-            &add_res,
-            &mult_res,
-            &w_i_ext_b,
-            &w_i_ext_c,
-            2 * circuit.k(i + 1),
+        transcript.append(b"layer", &E::from(i as u64));
+        let (sumcheck_proof, r) = prove_sumcheck_libra(
+            &wires[i].0,
+            &wires[i].1,
+            &z[i],
+            &w[i + 1].lift(),
+            &mut transcript,
         );
         sumcheck_proofs.push(sumcheck_proof.clone());
         sumcheck_r.push(r.clone());
 
         let mut b_star = vec![];
         let mut c_star = vec![];
-        b_star.extend_from_slice(&r[..circuit.k(i + 1)]);
-        c_star.extend_from_slice(&r[circuit.k(i + 1)..]);
+        b_star.extend_from_slice(&r[..k(i + 1)]);
+        c_star.extend_from_slice(&r[k(i + 1)..]);
 
-        let next_w = input.w(i + 1);
-        let q_i = reduce_multiple_polynomial(&b_star, &c_star, &next_w);
+        let next_w = &w[i + 1];
+        let q_i = reduce_multiple_polynomial(&b_star, &c_star, next_w);
 
+        transcript.append_vec(b"q", &q_i);
         q.push(q_i);
 
-        let mimc_r_star = sumcheck_proof[sumcheck_proof.len() - 1]
-            .iter()
-            .map(|s| convert_s_to_fr(s))
-            .collect();
-        let r_star: S = convert_fr_to_s(mimc.multi_hash(mimc_r_star, &Fr::from(0)));
+        let r_star: E = transcript.challenge(b"r star");
 
         let next_r = l_function(&b_star, &c_star, &r_star);
         z.push(next_r);
         r_stars.push(r_star);
     }
 
+    // the private part of the input layer is opened at z[d - 1], and at the public gates to
+    // show it is zero there in a random combination of the copies
+    let mut points = vec![z[circuit.depth()].clone()];
+    let rho: Vec<E> = (0..n)
+        .map(|_| transcript.challenge(b"public copies"))
+        .collect();
+    points.extend(circuit.public_points(&rho));
+    let opening = pcs.open(&input_func, &data, &points, &mut transcript, rng);
+
     Proof {
         sumcheck_proofs,
         sumcheck_r,
-        d: input.d.clone(),
         q,
         z,
         r: r_stars,
         depth: circuit.depth() + 1,
-        input_func: input.w(circuit.depth()),
-        k: circuit.get_k_list(),
+        commitment,
+        opening,
+        public,
+        masks: vec![],
+        k: (0..(circuit.depth() + 1)).map(k).collect(),
+        hash,
+    }
+}
+
+fn random_table<B: PrimeField>(num_vars: usize, rng: &mut impl RngCore) -> MultilinearPoly<B> {
+    MultilinearPoly::new((0..1 << num_vars).map(|_| B::random(&mut *rng)).collect())
+}
+
+// GKR over a single copy as in prove_copies, in zero knowledge. Every layer but the output layer
+// is reduced to two claims W(u) and W(v), which the next sumcheck takes as
+// (1 - r*)W(u) + r*W(v), and which are sent masked by Z(x)R(x_1) for the inner layers and by
+// the random half of the input layer for the input layer.
+fn prove_copies_zk<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    wires: &Vec<&(Vec<Vec<B>>, Vec<Vec<B>>)>,
+    w: &Vec<MultilinearPoly<B>>,
+    pcs: &P,
+    hash: HashType,
+    rng: &mut impl RngCore,
+) -> Proof<B, E, P> {
+    let depth = circuit.depth();
+    let k: Vec<usize> = (0..(depth + 1)).map(|i| circuit.k(i)).collect();
+    let (mut transcript, input_func, public) = bind_statement(circuit, 0, &w[depth], hash);
+    let layout = MaskLayout::new(&k);
+
+    // the last sumcheck reads only the first half of the padded input layer
+    let junk = random_table::<B>(k[depth], rng);
+    let mut padded = w[depth].evals.clone();
+    padded.extend_from_slice(&junk.evals);
+    let padded = MultilinearPoly::new(padded);
+    let mut input_padded = input_func.evals.clone();
+    input_padded.extend_from_slice(&junk.evals);
+    let input_padded = MultilinearPoly::new(input_padded);
+    // g_j of every round of every layer
+    let g: Vec<Vec<Vec<B>>> = (0..depth)
+        .map(|i| {
+            (0..layout.rounds(i))
+                .map(|_| (0..=MASK_DEGREE).map(|_| B::random(&mut *rng)).collect())
+                .collect()
+        })
+        .collect();
+    // R of layer i is r[i - 1]
+    let r: Vec<MultilinearPoly<B>> = (1..depth).map(|_| random_table(2, rng)).collect();
+    let g_tables: Vec<MultilinearPoly<B>> =
+        (0..depth).map(|i| layout.mask_table(i, &g[i])).collect();
+    let mut regions = vec![&input_padded];
+    regions.extend(g_tables.iter());
+    regions.extend(r.iter());
+    let table = layout.table(&regions);
+
+    transcript.append(b"zero knowledge", &E::one());
+    let (commitment, data) = pcs.commit(&table, rng);
+    pcs.append_commitment(&mut transcript, &commitment);
+
+    let mut sumcheck_proofs = vec![];
+    let mut sumcheck_r: Vec<Vec<E>> = vec![];
+    let mut q = vec![];
+    let mut r_stars: Vec<E> = vec![];
+    let mut masks = vec![];
+    let mut z_zero = vec![];
+    for _ in 0..k[0] {
+        z_zero.push(transcript.challenge(b"output point"));
+    }
+    // the masked values of layer i + 1, W(x) + Z(x)R(x_1) for the inner layers
+    let masked = |i: usize, x: &Vec<E>| -> E {
+        if i == depth {
+            padded.evaluate(x)
+        } else {
+            w[i].evaluate(x) + vanishing(x) * mask_at(&r[i - 1], &x[0])
+        }
+    };
+
+    for i in 0..depth {
+        transcript.append(b"layer", &E::from(i as u64));
+        let (weights, fold) = if i == 0 {
+            (eq_table(&z_zero), (E::zero(), E::zero()))
+        } else {
+            let (u, v) = sumcheck_r[i - 1].split_at(layout.rounds(i - 1) / 2);
+            let (u, v) = (u.to_vec(), v.to_vec());
+            let r_star: E = r_stars[i - 1];
+            let weights = eq_table(&u)
+                .iter()
+                .zip(eq_table(&v).iter())
+                .map(|(x, y)| (E::one() - r_star) * x + r_star * y)
+                .collect();
+            let (z_u, z_v) = (vanishing(&u), vanishing(&v));
+            let psi = |b: E| {
+                (E::one() - r_star) * z_u * r[i - 1].evaluate(&vec![u[0], b])
+                    + r_star * z_v * r[i - 1].evaluate(&vec![v[0], b])
+            };
+            (weights, (psi(E::zero()), psi(E::one())))
+        };
+        let kb = layout.rounds(i) / 2;
+        let g_i: Vec<Vec<E>> = g[i]
+            .iter()
+            .map(|g_j| g_j.iter().map(|x| E::from(*x)).collect())
+            .collect();
+        let sum = mask_sum(&g_i);
+        transcript.append(b"mask", &sum);
+        let rho = transcript.challenge(b"mask challenge");
+        masks.push(sum);
+        let next = if i + 1 < depth {
+            let mut table = MultilinearPoly::zero(kb);
+            let half = table.evals.len() / 2;
+            for (j, t) in table.evals.iter_mut().enumerate() {
+                let x_1 = if j < half { E::zero() } else { E::one() };
+                *t = mask_at(&r[i], &x_1);
+            }
+            Some(table)
+        } else {
+            None
+        };
+        let mask = SumcheckMask {
+            rho,
+            g: g_i,
+            next,
+            fold,
+        };
+        let w_next = if i + 1 < depth {
+            w[i + 1].lift()
+        } else {
+            padded.lift()
+        };
+        let (sumcheck_proof, r_i) = prove_sumcheck_zk(
+            &wires[i].0,
+            &wires[i].1,
+            &weights,
+            &w_next,
+            &mask,
+            &mut transcript,
+        );
+
+        let w_u = masked(i + 1, &r_i[..kb].to_vec());
+        let w_v = masked(i + 1, &r_i[kb..].to_vec());
+        let q_i = vec![w_v - w_u, w_u];
+        transcript.append_vec(b"q", &q_i);
+        q.push(q_i);
+        sumcheck_proofs.push(sumcheck_proof);
+        sumcheck_r.push(r_i);
+        r_stars.push(transcript.challenge(b"r star"));
+    }
+
+    let positions: Vec<usize> = public.iter().map(|(pos, _)| *pos).collect();
+    let points = layout.openings(&sumcheck_r, &positions);
+    let opening = pcs.open(&table, &data, &points, &mut transcript, rng);
+
+    Proof {
+        sumcheck_proofs,
+        sumcheck_r,
+        q,
+        z: vec![z_zero],
+        r: r_stars,
+        depth: depth + 1,
+        commitment,
+        opening,
+        public,
+        masks,
+        k,
+        hash,
     }
 }
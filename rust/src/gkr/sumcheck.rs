@@ -1,30 +1,40 @@
 use std::vec;
 
-use ff::PrimeField;
+use ff::{Field, PrimeField};
 use itertools::Itertools;
-use mimc_rs::{Fr, FrRepr, Mimc7};
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-
-use super::poly::*;
-
-pub fn convert_s_to_fr<S>(v: &S) -> mimc_rs::Fr
-where
-    S: PrimeField<Repr = [u8; 32]>,
-{
-    let v_bytes = v.to_repr();
-    let res = FrRepr(v_bytes);
-    mimc_rs::Fr::from_repr(res).unwrap()
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use super::{
+    poly::*,
+    transcript::{Transcript, TranscriptField},
+    zk::mask_sum,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SumcheckError {
+    /// The proof does not have one round polynomial per variable.
+    RoundCount { expected: usize, got: usize },
+    /// A round polynomial is empty or exceeds the degree bound.
+    Degree { round: usize },
+    /// g_j(0) + g_j(1) differs from the running claim.
+    Round { round: usize },
 }
 
-pub fn convert_fr_to_s<S: PrimeField<Repr = [u8; 32]>>(v: mimc_rs::Fr) -> S {
-    let FrRepr(v_bytes) = v.to_repr();
-    S::from_repr(v_bytes).unwrap()
+impl std::fmt::Display for SumcheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SumcheckError::RoundCount { expected, got } => {
+                write!(f, "expected {} rounds, got {}", expected, got)
+            }
+            SumcheckError::Degree { round } => write!(f, "bad degree at round {}", round),
+            SumcheckError::Round { round } => write!(f, "sum check failed at round {}", round),
+        }
+    }
 }
 
-fn n_trailing_bits<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(
-    wire: &Vec<Vec<S>>,
-    n: usize,
-) -> Vec<Vec<S>> {
+impl std::error::Error for SumcheckError {}
+
+fn n_trailing_bits<S: Field + std::hash::Hash>(wire: &Vec<Vec<S>>, n: usize) -> Vec<Vec<S>> {
     let mut res: Vec<Vec<S>> = wire
         .iter()
         .map(|inner_vec| inner_vec.iter().rev().take(n).rev().cloned().collect())
@@ -32,183 +42,676 @@ fn n_trailing_bits<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(
     res.into_iter().unique().collect()
 }
 
-// only can be run for f: add_i(f1 + f2) + mult_i(f1 * f2)
-pub fn prove_sumcheck_opt<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(
+// (curr, left, right) gate indices of a wire
+fn split_wire<S: Field>(wire: &Vec<S>, k_curr: usize, k: usize) -> (usize, usize, usize) {
+    (
+        bits_to_index(&wire[..k_curr]),
+        bits_to_index(&wire[k_curr..k_curr + k]),
+        bits_to_index(&wire[k_curr + k..]),
+    )
+}
+
+// (curr, left, right) gate indices of every wire, in a current layer of k_curr variables
+fn wire_gates<S: Field>(wires: &Vec<Vec<S>>, k_curr: usize) -> Vec<(usize, usize, usize)> {
+    wires
+        .iter()
+        .map(|wire| split_wire(wire, k_curr, (wire.len() - k_curr) / 2))
+        .collect()
+}
+
+// sumcheck over x of a(x) * w(x) + c(x), fixing the variables of a, w and c in place
+fn prove_product_sumcheck<S: TranscriptField>(
+    a: &mut MultilinearPoly<S>,
+    w: &mut MultilinearPoly<S>,
+    c: &mut MultilinearPoly<S>,
+    proof: &mut Vec<Vec<S>>,
+    transcript: &mut Transcript<S>,
+) -> Vec<S> {
+    let mut r = vec![];
+    for _ in 0..w.num_vars {
+        let half = w.evals.len() / 2;
+        // g_j(0), g_j(1) and g_j(2)
+        let evals = (0..half)
+            .into_par_iter()
+            .map(|i| {
+                let mut res = [S::zero(); 3];
+                let (mut a_t, mut w_t, mut c_t) = (a.evals[i], w.evals[i], c.evals[i]);
+                let a_d = a.evals[half + i] - a_t;
+                let w_d = w.evals[half + i] - w_t;
+                let c_d = c.evals[half + i] - c_t;
+                for res_t in res.iter_mut() {
+                    *res_t = a_t * w_t + c_t;
+                    a_t += a_d;
+                    w_t += w_d;
+                    c_t += c_d;
+                }
+                res
+            })
+            .reduce(
+                || [S::zero(); 3],
+                |x, y| [x[0] + y[0], x[1] + y[1], x[2] + y[2]],
+            );
+        let g_j = interpolate_univariate(&evals.to_vec());
+        proof.push(g_j.clone());
+
+        transcript.append_vec(b"sumcheck round", &g_j);
+        let r_j = transcript.challenge(b"sumcheck challenge");
+        *a = a.fix_variable(&r_j);
+        *w = w.fix_variable(&r_j);
+        *c = c.fix_variable(&r_j);
+        r.push(r_j);
+    }
+    r
+}
+
+/// Linear-time prover for sum_(b, c) add_i(z, b, c)(w(b) + w(c)) + mult_i(z, b, c)w(b)w(c)
+/// in two phases as in Libra: first over b with c summed out through the wires,
+/// then over c with b fixed. Sends the same messages as `prove_sumcheck_opt`.
+/// The wiring is over the circuit field B, everything else over the challenge field S.
+pub fn prove_sumcheck_libra<B: PrimeField, S: TranscriptField>(
+    add_wire: &Vec<Vec<B>>,
+    mult_wire: &Vec<Vec<B>>,
+    z: &Vec<S>,
+    w: &MultilinearPoly<S>,
+    transcript: &mut Transcript<S>,
+) -> (Vec<Vec<S>>, Vec<S>) {
+    let k = w.num_vars;
+    let mut proof = vec![];
+    if add_wire.is_empty() && mult_wire.is_empty() {
+        let mut r = vec![];
+        for _ in 0..2 * k {
+            proof.push(vec![S::zero()]);
+            transcript.append_vec(b"sumcheck round", &vec![S::zero()]);
+            r.push(transcript.challenge(b"sumcheck challenge"));
+        }
+        return (proof, r);
+    }
+    let add_gates = wire_gates(add_wire, z.len());
+    let mult_gates = wire_gates(mult_wire, z.len());
+    let eq_z = eq_table(z);
+
+    // phase 1: sum_b a(b)w(b) + c(b), where
+    // a(b) = sum_c add_i(z, b, c) + mult_i(z, b, c)w(c) and c(b) = sum_c add_i(z, b, c)w(c)
+    let mut a = MultilinearPoly::zero(k);
+    let mut c = MultilinearPoly::zero(k);
+    for (curr, left, right) in add_gates.iter() {
+        a.evals[*left] += eq_z[*curr];
+        c.evals[*left] += eq_z[*curr] * w.evals[*right];
+    }
+    for (curr, left, right) in mult_gates.iter() {
+        a.evals[*left] += eq_z[*curr] * w.evals[*right];
+    }
+    let mut w_b = w.clone();
+    let mut r = prove_product_sumcheck(&mut a, &mut w_b, &mut c, &mut proof, transcript);
+    let w_u = w_b.evals[0];
+
+    // phase 2: sum_c a(c)w(c) + c(c) with b fixed to u, where
+    // a(c) = add_i(z, u, c) + mult_i(z, u, c)w(u) and c(c) = add_i(z, u, c)w(u)
+    let eq_u = eq_table(&r);
+    let mut a = MultilinearPoly::zero(k);
+    let mut c = MultilinearPoly::zero(k);
+    for (curr, left, right) in add_gates.iter() {
+        let g = eq_z[*curr] * eq_u[*left];
+        a.evals[*right] += g;
+        c.evals[*right] += g * w_u;
+    }
+    for (curr, left, right) in mult_gates.iter() {
+        a.evals[*right] += eq_z[*curr] * eq_u[*left] * w_u;
+    }
+    let mut w_c = w.clone();
+    let mut r_c = prove_product_sumcheck(&mut a, &mut w_c, &mut c, &mut proof, transcript);
+    r.append(&mut r_c);
+
+    (proof, r)
+}
+
+/// What the zero-knowledge prover adds to the sumcheck of one layer, as in Libra.
+pub struct SumcheckMask<S: Field> {
+    /// The random mask ρg for g(x) = Σ_j g_j(x_j), with a univariate g_j of degree at most 4
+    /// per round, committed to before ρ is drawn.
+    pub rho: S,
+    pub g: Vec<Vec<S>>,
+    /// R(x_1) as a table in every variable of the next layer, whose values are masked by
+    /// Z(x)R(x_1) for the Z that vanishes on the hypercube, or None if they are not.
+    pub next: Option<MultilinearPoly<S>>,
+    /// ψ(0) and ψ(1), where ψ(0) + ψ(1) is the share of the claim that comes from the mask of
+    /// this layer. It is spread over the hypercube as ψ(c_last) / 2^(2k - 1).
+    pub fold: (S, S),
+}
+
+// sumcheck over x of Σ_t Π_j terms[t][j](x) + Σ_j mask[j](x_j), fixing the variables of every
+// table in place
+fn prove_products_sumcheck<S: TranscriptField>(
+    terms: &mut Vec<Vec<MultilinearPoly<S>>>,
+    mask: &[Vec<S>],
+    proof: &mut Vec<Vec<S>>,
+    transcript: &mut Transcript<S>,
+) -> Vec<S> {
+    let num_vars = terms[0][0].num_vars;
+    assert_eq!(mask.len(), num_vars);
+    let degree = terms
+        .iter()
+        .map(|term| term.len())
+        .chain(mask.iter().map(|g_j| g_j.len() - 1))
+        .max()
+        .unwrap();
+    let two = S::from(2);
+    // g_j(0) + g_j(1) for every mask
+    let sums: Vec<S> = mask
+        .iter()
+        .map(|g_j| eval_univariate(g_j, &S::zero()) + eval_univariate(g_j, &S::one()))
+        .collect();
+    // Σ_(i < j) g_i(r_i)
+    let mut fixed = S::zero();
+    let mut r = vec![];
+    for j in 0..num_vars {
+        let half = terms[0][0].evals.len() / 2;
+        // g_j(0), ..., g_j(degree)
+        let mut evals = (0..half)
+            .into_par_iter()
+            .map(|i| {
+                let mut res = vec![S::zero(); degree + 1];
+                for term in terms.iter() {
+                    let mut f_t: Vec<S> = term.iter().map(|f| f.evals[i]).collect();
+                    let f_d: Vec<S> = term
+                        .iter()
+                        .map(|f| f.evals[half + i] - f.evals[i])
+                        .collect();
+                    for res_t in res.iter_mut() {
+                        *res_t += f_t.iter().fold(S::one(), |acc, f| acc * f);
+                        for (f, d) in f_t.iter_mut().zip(f_d.iter()) {
+                            *f += d;
+                        }
+                    }
+                }
+                res
+            })
+            .reduce(
+                || vec![S::zero(); degree + 1],
+                |x, y| x.iter().zip(y.iter()).map(|(x, y)| *x + y).collect(),
+            );
+        // the mask over the 2^(n - j - 1) points left: mask[j](x) and the fixed part at each,
+        // and g_i(0) + g_i(1) for i > j at half of them
+        let points = two.pow_vartime([(num_vars - j - 1) as u64]);
+        let rest = sums[j + 1..].iter().fold(S::zero(), |acc, x| acc + x) * two.invert().unwrap();
+        for (x, res_x) in evals.iter_mut().enumerate() {
+            let g_x = eval_univariate(&mask[j], &S::from(x as u64));
+            *res_x += points * (fixed + g_x + rest);
+        }
+        let g_j = interpolate_univariate(&evals);
+        proof.push(g_j.clone());
+
+        transcript.append_vec(b"sumcheck round", &g_j);
+        let r_j = transcript.challenge(b"sumcheck challenge");
+        for term in terms.iter_mut() {
+            for f in term.iter_mut() {
+                *f = f.fix_variable(&r_j);
+            }
+        }
+        fixed += eval_univariate(&mask[j], &r_j);
+        r.push(r_j);
+    }
+    r
+}
+
+// Z(x) = Π x_i(1 - x_i) as the product of the tables of Π x_i and Π (1 - x_i)
+fn vanishing_tables<S: Field>(num_vars: usize) -> (MultilinearPoly<S>, MultilinearPoly<S>) {
+    let mut ones = MultilinearPoly::zero(num_vars);
+    let mut zeros = MultilinearPoly::zero(num_vars);
+    *ones.evals.last_mut().unwrap() = S::one();
+    zeros.evals[0] = S::one();
+    (ones, zeros)
+}
+
+/// Zero-knowledge version of `prove_sumcheck_libra`: the sumcheck of
+/// Σ_(b, c) f(b, c) + ρg(b, c) + ψ(c_last) / 2^(2k - 1), where f is the layer's add_i and
+/// mult_i weighted by `weights` over the current layer and applied to the masked values of
+/// the next layer. `w` is the next layer on the hypercube; it may have more variables than
+/// the wiring, which then reads its first part. Round polynomials are of degree at most 4.
+pub fn prove_sumcheck_zk<B: PrimeField, S: TranscriptField>(
+    add_wire: &Vec<Vec<B>>,
+    mult_wire: &Vec<Vec<B>>,
+    weights: &Vec<S>,
+    w: &MultilinearPoly<S>,
+    mask: &SumcheckMask<S>,
+    transcript: &mut Transcript<S>,
+) -> (Vec<Vec<S>>, Vec<S>) {
+    let k = w.num_vars;
+    assert_eq!(mask.g.len(), 2 * k);
+    let k_curr = weights.len().trailing_zeros() as usize;
+    let add_gates = wire_gates(add_wire, k_curr);
+    let mult_gates = wire_gates(mult_wire, k_curr);
+    let two = S::from(2);
+    let size = two.pow_vartime([k as u64]);
+    let scale = |g: &[Vec<S>], by: S| -> Vec<Vec<S>> {
+        g.iter()
+            .map(|g_j| g_j.iter().map(|x| *x * by).collect())
+            .collect()
+    };
+    let (ones, zeros) = vanishing_tables(k);
+    let (g_b, g_c) = mask.g.split_at(k);
+    let mut proof = vec![];
+
+    // phase 1 as in prove_sumcheck_libra, with the sums over c of the masks, which do not
+    // depend on b, in c(b), and the masks of b summed over the 2^k values of c
+    let mut a = MultilinearPoly::zero(k);
+    let mut c = MultilinearPoly::zero(k);
+    for (curr, left, right) in add_gates.iter() {
+        a.evals[*left] += weights[*curr];
+        c.evals[*left] += weights[*curr] * w.evals[*right];
+    }
+    for (curr, left, right) in mult_gates.iter() {
+        a.evals[*left] += weights[*curr] * w.evals[*right];
+    }
+    let constant = mask.rho * mask_sum(g_c) + (mask.fold.0 + mask.fold.1) * size.invert().unwrap();
+    for c_b in c.evals.iter_mut() {
+        *c_b += constant;
+    }
+    let mut terms = vec![vec![a.clone(), w.clone()], vec![c]];
+    if let Some(next) = &mask.next {
+        terms.push(vec![a, ones.clone(), zeros.clone(), next.clone()]);
+    }
+    let mut r = prove_products_sumcheck(
+        &mut terms,
+        &scale(g_b, mask.rho * size),
+        &mut proof,
+        transcript,
+    );
+    let mut w_u = terms[0][1].evals[0];
+    if mask.next.is_some() {
+        w_u += terms[2][1].evals[0] * terms[2][2].evals[0] * terms[2][3].evals[0];
+    }
+    let g_u = g_b
+        .iter()
+        .zip(r.iter())
+        .fold(S::zero(), |acc, (g_j, r_j)| acc + eval_univariate(g_j, r_j));
+
+    // phase 2 with b fixed to u, where the masks of b are now a constant and ψ depends on
+    // the last variable of c
+    let eq_u = eq_table(&r);
+    let mut a = MultilinearPoly::zero(k);
+    let mut c = MultilinearPoly::zero(k);
+    for (curr, left, right) in add_gates.iter() {
+        let g = weights[*curr] * eq_u[*left];
+        a.evals[*right] += g;
+        c.evals[*right] += g * w_u;
+    }
+    for (curr, left, right) in mult_gates.iter() {
+        a.evals[*right] += weights[*curr] * eq_u[*left] * w_u;
+    }
+    let spread = (size.square() * two.invert().unwrap()).invert().unwrap();
+    for (j, c_c) in c.evals.iter_mut().enumerate() {
+        let psi = if j % 2 == 0 { mask.fold.0 } else { mask.fold.1 };
+        *c_c += mask.rho * g_u + psi * spread;
+    }
+    let mut terms = vec![vec![a.clone(), w.clone()], vec![c]];
+    if let Some(next) = &mask.next {
+        terms.push(vec![a, ones, zeros, next.clone()]);
+    }
+    let mut r_c =
+        prove_products_sumcheck(&mut terms, &scale(g_c, mask.rho), &mut proof, transcript);
+    r.append(&mut r_c);
+
+    (proof, r)
+}
+
+// only can be run for f: add_i(f1 + f2) + mult_i(f1 * f2), where f1 = w(b) and f2 = w(c)
+// superlinear in the number of gates, kept as a reference for prove_sumcheck_libra
+pub fn prove_sumcheck_opt<S: TranscriptField + std::hash::Hash>(
     add_wire: &Vec<Vec<S>>,
     mult_wire: &Vec<Vec<S>>,
-    add_i: &Vec<Vec<S>>,
-    mult_i: &Vec<Vec<S>>,
-    f1: &Vec<Vec<S>>,
-    f2: &Vec<Vec<S>>,
+    add_i: &BinaryPoly<S>,
+    mult_i: &BinaryPoly<S>,
+    w: &MultilinearPoly<S>,
     v: usize,
+    transcript: &mut Transcript<S>,
 ) -> (Vec<Vec<S>>, Vec<S>) {
-    let mimc = Mimc7::new(91);
+    let k = w.num_vars;
+    assert_eq!(v, 2 * k);
     let mut proof = vec![];
-    let mut r = vec![];
+    let mut r: Vec<S> = vec![];
 
-    let add_assignments: Vec<Vec<S>> = n_trailing_bits(add_wire, v - 1);
-    let g_1_add = add_assignments
-        .par_iter()
-        .map(|assignment| {
-            let f2_1_sub = partial_eval_from(f2, assignment, 2);
-            let f1_1_sub = partial_eval_from(f1, assignment, 2);
-            let add_1_sub = partial_eval_from_binary_form(&add_i.clone(), assignment, 2);
-
-            let f1_1_coeffs = get_univariate_coeff(&f1_1_sub, 1, false);
-            let f2_1_coeffs = get_univariate_coeff(&f2_1_sub, 1, false);
-            let add_1_coeffs = get_univariate_coeff(&add_1_sub, 1, true);
-            let f1_f2_add = add_univariate(&f1_1_coeffs, &f2_1_coeffs);
-            mult_univariate(&f1_f2_add, &add_1_coeffs)
-        })
-        .reduce(|| vec![], |a, b| add_univariate(&a, &b));
-    let mult_assignments: Vec<Vec<S>> = n_trailing_bits(mult_wire, v - 1);
-    let g_1_mult = mult_assignments
-        .par_iter()
-        .map(|assignment| {
-            let f2_1_sub = partial_eval_from(f2, assignment, 2);
-            let f1_1_sub = partial_eval_from(f1, assignment, 2);
-            let mult_1_sub = partial_eval_from_binary_form(&mult_i.clone(), assignment, 2);
-
-            let f1_1_coeffs = get_univariate_coeff(&f1_1_sub, 1, false);
-            let f2_1_coeffs = get_univariate_coeff(&f2_1_sub, 1, false);
-            let mult_1_coeffs = get_univariate_coeff(&mult_1_sub, 1, true);
-            let f1_f2_mult = mult_univariate(&f1_1_coeffs, &f2_1_coeffs);
-            mult_univariate(&f1_f2_mult, &mult_1_coeffs)
-        })
-        .reduce(|| vec![], |a, b| add_univariate(&a, &b));
-
-    let g_1 = add_univariate(&g_1_add, &g_1_mult);
-    proof.push(g_1.clone());
-
-    let mimc_g1_coeffs = g_1.iter().map(|s| convert_s_to_fr(s)).collect();
-    let r_1 = mimc.multi_hash(mimc_g1_coeffs, &Fr::from(0));
-    r.push(convert_fr_to_s(r_1));
-    let mut f1_j = f1.clone();
-    let mut f2_j = f2.clone();
+    // w_b and w_c are w with the challenges of b and c fixed so far
+    let mut w_b = w.clone();
+    let mut w_c = w.clone();
     let mut add_j = add_i.clone();
     let mut mult_j = mult_i.clone();
-    for j in 1..v - 1 {
-        f1_j = partial_eval_i(&f1_j, &r[r.len() - 1], r.len());
-        f2_j = partial_eval_i(&f2_j, &r[r.len() - 1], r.len());
-        add_j = partial_eval_i_binary_form(&add_j, &r[r.len() - 1], r.len());
-        mult_j = partial_eval_i_binary_form(&mult_j, &r[r.len() - 1], r.len());
+    for j in 0..v {
+        if j > 0 {
+            let r_prev = r[j - 1];
+            if j <= k {
+                w_b = w_b.fix_variable(&r_prev);
+            } else {
+                w_c = w_c.fix_variable(&r_prev);
+            }
+            add_j = add_j.fix_var(j - 1, &r_prev);
+            mult_j = mult_j.fix_var(j - 1, &r_prev);
+        }
+
+        // f1 and f2 as univariates in x_(j + 1) once the trailing variables are assigned
+        let f1_f2_coeffs = |assignment: &Vec<S>| -> (Vec<S>, Vec<S>) {
+            if j < k {
+                let (b_bits, c_bits) = assignment.split_at(k - j - 1);
+                (
+                    w_b.linear_in_first(b_bits),
+                    vec![w.evals[bits_to_index(c_bits)]],
+                )
+            } else {
+                (vec![w_b.evals[0]], w_c.linear_in_first(assignment))
+            }
+        };
+
         let add_assignments: Vec<Vec<S>> = n_trailing_bits(add_wire, v - j - 1);
-        let mult_assignments: Vec<Vec<S>> = n_trailing_bits(mult_wire, v - j - 1);
         let g_j_add = add_assignments
             .par_iter()
             .map(|assignment| {
-                let f1_j_sub = partial_eval_from(&f1_j, assignment, j + 2);
-                let f2_j_sub = partial_eval_from(&f2_j, assignment, j + 2);
-                let add_j_sub = partial_eval_from_binary_form(&add_j.clone(), assignment, j + 2);
-
-                let f1_j_coeffs = get_univariate_coeff(&f1_j_sub, j + 1, false);
-                let f2_j_coeffs = get_univariate_coeff(&f2_j_sub, j + 1, false);
-                let add_j_coeffs = get_univariate_coeff(&add_j_sub, j + 1, true);
+                let (f1_j_coeffs, f2_j_coeffs) = f1_f2_coeffs(assignment);
+                let add_j_coeffs = add_j.fix_vars_from(assignment, j + 1).univariate_coeffs(j);
                 let f1_f2_add = add_univariate(&f1_j_coeffs, &f2_j_coeffs);
                 mult_univariate(&f1_f2_add, &add_j_coeffs)
             })
             .reduce(|| vec![], |a, b| add_univariate(&a, &b));
+        let mult_assignments: Vec<Vec<S>> = n_trailing_bits(mult_wire, v - j - 1);
         let g_j_mult = mult_assignments
             .par_iter()
             .map(|assignment| {
-                let f1_j_sub = partial_eval_from(&f1_j, assignment, j + 2);
-                let f2_j_sub = partial_eval_from(&f2_j, assignment, j + 2);
-                let mult_j_sub = partial_eval_from_binary_form(&mult_j.clone(), assignment, j + 2);
-
-                let f1_j_coeffs = get_univariate_coeff(&f1_j_sub, j + 1, false);
-                let f2_j_coeffs = get_univariate_coeff(&f2_j_sub, j + 1, false);
-                let mult_j_coeffs = get_univariate_coeff(&mult_j_sub, j + 1, true);
+                let (f1_j_coeffs, f2_j_coeffs) = f1_f2_coeffs(assignment);
+                let mult_j_coeffs = mult_j.fix_vars_from(assignment, j + 1).univariate_coeffs(j);
                 let f1_f2_mult = mult_univariate(&f1_j_coeffs, &f2_j_coeffs);
                 mult_univariate(&f1_f2_mult, &mult_j_coeffs)
             })
             .reduce(|| vec![], |a, b| add_univariate(&a, &b));
-        let g_j = add_univariate(&g_j_add, &g_j_mult);
+        let mut g_j = add_univariate(&g_j_add, &g_j_mult);
+        if g_j.is_empty() {
+            g_j = vec![S::zero()];
+        }
         proof.push(g_j.clone());
 
-        let mimc_gj_coeffs = g_j.iter().map(|s| convert_s_to_fr(s)).collect();
-        let r_n = mimc.multi_hash(mimc_gj_coeffs, &Fr::from(0));
-        r.push(convert_fr_to_s(r_n));
-    }
-    let mut f1_v = f1.clone();
-    let mut f2_v = f2.clone();
-    let mut add_v = add_i.clone();
-    let mut mult_v = mult_i.clone();
-    f1_v = partial_eval(&f1_v, &r);
-    f2_v = partial_eval(&f2_v, &r);
-    add_v = partial_eval_binary_form(&add_v, &r);
-    mult_v = partial_eval_binary_form(&mult_v, &r);
-
-    let f1_v_coeffs = get_univariate_coeff(&f1_v, 1, false);
-    let f2_v_coeffs = get_univariate_coeff(&f2_v, 1, false);
-    let add_v_coeffs = get_univariate_coeff(&add_v, 1, true);
-    let mult_v_coeffs = get_univariate_coeff(&mult_v, 1, true);
-    let f1_f2_add = add_univariate(&f1_v_coeffs, &f2_v_coeffs);
-    let f1_f2_mult = mult_univariate(&f1_v_coeffs, &f2_v_coeffs);
-    let add = mult_univariate(&f1_f2_add, &add_v_coeffs);
-    let mult = mult_univariate(&f1_f2_mult, &mult_v_coeffs);
-    let f = add_univariate(&add, &mult);
-    proof.push(f.clone());
-    let mimc_gv_coeffs = f.iter().map(|s| convert_s_to_fr(s)).collect();
-    let r_v = mimc.multi_hash(mimc_gv_coeffs, &Fr::from(0));
-    r.push(convert_fr_to_s(r_v));
+        transcript.append_vec(b"sumcheck round", &g_j);
+        r.push(transcript.challenge(b"sumcheck challenge"));
+    }
 
     (proof, r)
 }
 
-pub fn prove_sumcheck<S: PrimeField<Repr = [u8; 32]> + std::hash::Hash>(
-    g: &Vec<Vec<S>>,
+pub fn prove_sumcheck<S: TranscriptField>(
+    g: &SparsePoly<S>,
     v: usize,
+    transcript: &mut Transcript<S>,
 ) -> (Vec<Vec<S>>, Vec<S>) {
-    let mimc = Mimc7::new(91);
     let mut proof = vec![];
     let mut r = vec![];
 
-    let mut g_1 = get_empty(v);
+    let mut g_1 = SparsePoly::zero(v);
     let assignments: Vec<Vec<S>> = generate_binary(v - 1);
     for assignment in assignments {
         let mut g_1_sub = g.clone();
         for (i, x_i) in assignment.into_iter().enumerate() {
-            let idx = i + 2;
-            g_1_sub = partial_eval_i(&g_1_sub, &x_i, idx);
+            g_1_sub = g_1_sub.fix_var(i + 1, &x_i);
         }
-        g_1 = add_poly(&g_1, &g_1_sub);
+        g_1 = &g_1 + &g_1_sub;
     }
-    let g_1_coeffs = get_univariate_coeff(&g_1, 1, false);
+    let g_1_coeffs = g_1.univariate_coeffs(0);
     proof.push(g_1_coeffs.clone());
 
-    let mimc_g1_coeffs = g_1_coeffs.iter().map(|s| convert_s_to_fr(s)).collect();
-    let r_1 = mimc.multi_hash(mimc_g1_coeffs, &Fr::from(0));
-    r.push(convert_fr_to_s(r_1));
+    transcript.append_vec(b"sumcheck round", &g_1_coeffs);
+    r.push(transcript.challenge(b"sumcheck challenge"));
 
     for j in 1..v - 1 {
-        let mut g_j: Vec<Vec<S>> = g.clone();
+        let mut g_j = g.clone();
         let assignments: Vec<Vec<S>> = generate_binary(v - j - 1);
 
         for (i, r_i) in r.iter().enumerate() {
-            g_j = partial_eval_i(&g_j, r_i, i + 1);
+            g_j = g_j.fix_var(i, r_i);
         }
-        let mut res_g_j = get_empty(v);
+        let mut res_g_j = SparsePoly::zero(v);
         for assignment in assignments {
             let mut g_j_sub = g_j.clone();
             for (i, x_i) in assignment.into_iter().enumerate() {
-                let idx = j + i + 2;
-                g_j_sub = partial_eval_i(&g_j_sub, &x_i, idx);
+                g_j_sub = g_j_sub.fix_var(j + i + 1, &x_i);
             }
-            res_g_j = add_poly(&res_g_j, &g_j_sub);
+            res_g_j = &res_g_j + &g_j_sub;
         }
-        let g_j_coeffs = get_univariate_coeff(&res_g_j, j + 1, false);
+        let g_j_coeffs = res_g_j.univariate_coeffs(j);
         proof.push(g_j_coeffs.clone());
 
-        let mimc_gj_coeffs = g_j_coeffs.iter().map(|s| convert_s_to_fr(s)).collect();
-        let r_n = mimc.multi_hash(mimc_gj_coeffs, &Fr::from(0));
-        r.push(convert_fr_to_s(r_n));
+        transcript.append_vec(b"sumcheck round", &g_j_coeffs);
+        r.push(transcript.challenge(b"sumcheck challenge"));
     }
-    let g_v = partial_eval(&g, &r);
-    let g_v_coeffs = get_univariate_coeff(&g_v, 1, false);
+    let g_v = g.partial_eval(&r);
+    let g_v_coeffs = g_v.univariate_coeffs(0);
     proof.push(g_v_coeffs.clone());
-    let mimc_gv_coeffs = g_v_coeffs.iter().map(|s| convert_s_to_fr(s)).collect();
-    let r_v = mimc.multi_hash(mimc_gv_coeffs, &Fr::from(0));
-    r.push(convert_fr_to_s(r_v));
+    transcript.append_vec(b"sumcheck round", &g_v_coeffs);
+    r.push(transcript.challenge(b"sumcheck challenge"));
 
     (proof, r)
 }
+
+/// Replays the rounds of `prove_sumcheck` / `prove_sumcheck_libra` on the same transcript.
+/// Returns the challenges and the value the summed polynomial must take at them,
+/// which the caller still has to check against an oracle.
+/// The claim is not absorbed here; the caller binds it beforehand if it is not already implied.
+pub fn verify_sumcheck<S: TranscriptField>(
+    claim: S,
+    proof: &Vec<Vec<S>>,
+    degree_bound: usize,
+    num_vars: usize,
+    transcript: &mut Transcript<S>,
+) -> Result<(Vec<S>, S), SumcheckError> {
+    if proof.len() != num_vars {
+        return Err(SumcheckError::RoundCount {
+            expected: num_vars,
+            got: proof.len(),
+        });
+    }
+
+    let mut r = vec![];
+    let mut expected = claim;
+    for (j, g_j) in proof.iter().enumerate() {
+        if g_j.is_empty() || g_j.len() > degree_bound + 1 {
+            return Err(SumcheckError::Degree { round: j });
+        }
+        let g_zero = eval_univariate(g_j, &S::zero());
+        let g_one = eval_univariate(g_j, &S::one());
+        if g_zero + g_one != expected {
+            return Err(SumcheckError::Round { round: j });
+        }
+        transcript.append_vec(b"sumcheck round", g_j);
+        let r_j = transcript.challenge(b"sumcheck challenge");
+        expected = eval_univariate(g_j, &r_j);
+        r.push(r_j);
+    }
+    Ok((r, expected))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        prove_sumcheck, prove_sumcheck_libra, prove_sumcheck_opt, prove_sumcheck_zk,
+        verify_sumcheck, SumcheckError, SumcheckMask,
+    };
+    use crate::gkr::{
+        poly::*,
+        transcript::{HashType, Transcript},
+        zk::mask_sum,
+    };
+    use ff::Field;
+    use halo2curves::bn256::Fr;
+    use rand_core::{impls, Error, RngCore};
+
+    // splitmix64, so that the masks are the same on every run
+    pub(crate) struct FixedRng(pub(crate) u64);
+
+    impl RngCore for FixedRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_libra_matches_reference() {
+        let (k, k_next) = (2, 3);
+        // gates are (is_mult, curr, left, right), with a repeated gate and a gate reading one wire twice
+        let gates = [
+            (false, 0, 1, 6),
+            (true, 1, 3, 3),
+            (true, 2, 7, 0),
+            (false, 3, 5, 2),
+            (false, 3, 5, 2),
+            (true, 0, 4, 1),
+        ];
+        let mut add = BinaryPoly::zero(k + 2 * k_next);
+        let mut mult = BinaryPoly::zero(k + 2 * k_next);
+        let mut add_wire = vec![];
+        let mut mult_wire = vec![];
+        for (is_mult, curr, left, right) in gates {
+            let s = format!("{:02b}{:03b}{:03b}", curr, left, right);
+            let bits: Vec<Fr> = s
+                .chars()
+                .map(|c| if c == '1' { Fr::one() } else { Fr::zero() })
+                .collect();
+            if is_mult {
+                mult = &mult + &chi_w_for_binary(&s);
+                mult_wire.push(bits);
+            } else {
+                add = &add + &chi_w_for_binary(&s);
+                add_wire.push(bits);
+            }
+        }
+        let w = MultilinearPoly::new((0..8).map(|i| Fr::from(i * i + 3)).collect());
+        let z = vec![Fr::from(5), Fr::from(11)];
+
+        let (proof, r) = prove_sumcheck_libra(
+            &add_wire,
+            &mult_wire,
+            &z,
+            &w,
+            &mut Transcript::new(b"test", HashType::Mimc7),
+        );
+        let (expected_proof, expected_r) = prove_sumcheck_opt(
+            &add_wire,
+            &mult_wire,
+            &add.partial_eval(&z),
+            &mult.partial_eval(&z),
+            &w,
+            2 * k_next,
+            &mut Transcript::new(b"test", HashType::Mimc7),
+        );
+        assert_eq!(proof, expected_proof);
+        assert_eq!(r, expected_r);
+    }
+
+    #[test]
+    fn test_verify_sumcheck() {
+        // g = x_1 * x_2 + 3 * x_3, which sums to 2 + 12 over the cube
+        let g = SparsePoly::new(
+            3,
+            vec![
+                SparseTerm::new(Fr::one(), vec![1, 1, 0]),
+                SparseTerm::new(Fr::from(3), vec![0, 0, 1]),
+            ],
+        );
+        let (proof, r) = prove_sumcheck(&g, 3, &mut Transcript::new(b"test", HashType::Mimc7));
+
+        let (point, value) = verify_sumcheck(
+            Fr::from(14),
+            &proof,
+            1,
+            3,
+            &mut Transcript::new(b"test", HashType::Mimc7),
+        )
+        .unwrap();
+        assert_eq!(point, r);
+        assert_eq!(value, g.evaluate(&point));
+
+        assert_eq!(
+            verify_sumcheck(
+                Fr::from(13),
+                &proof,
+                1,
+                3,
+                &mut Transcript::new(b"test", HashType::Mimc7)
+            ),
+            Err(SumcheckError::Round { round: 0 })
+        );
+        assert_eq!(
+            verify_sumcheck(
+                Fr::from(14),
+                &proof,
+                1,
+                4,
+                &mut Transcript::new(b"test", HashType::Mimc7)
+            ),
+            Err(SumcheckError::RoundCount {
+                expected: 4,
+                got: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_zk_round_independent_of_witness() {
+        // out_0 = w_0 + w_1 and out_1 = w_2 * w_3, with two witnesses of the same outputs
+        let wire = |curr: usize, left: usize, right: usize| -> Vec<Fr> {
+            index_to_bits((curr << 4) | (left << 2) | right, 5)
+        };
+        let (add_wire, mult_wire) = (vec![wire(0, 0, 1)], vec![wire(1, 2, 3)]);
+        let w_a = MultilinearPoly::new([1, 5, 2, 6].iter().map(|x| Fr::from(*x)).collect());
+        let w_b = MultilinearPoly::new([4, 2, 3, 4].iter().map(|x| Fr::from(*x)).collect());
+        let weights = eq_table(&vec![Fr::from(9)]);
+        let mut rng = FixedRng(7);
+        let mask = SumcheckMask {
+            rho: Fr::from(3),
+            g: (0..4)
+                .map(|_| (0..5).map(|_| Fr::random(&mut rng)).collect())
+                .collect(),
+            next: None,
+            fold: (Fr::zero(), Fr::zero()),
+        };
+        let round_zero = |w: &MultilinearPoly<Fr>, mask: &SumcheckMask<Fr>| -> Vec<Fr> {
+            let mut transcript = Transcript::new(b"test", HashType::Mimc7);
+            let (proof, _) =
+                prove_sumcheck_zk(&add_wire, &mult_wire, &weights, w, mask, &mut transcript);
+            proof[0].clone()
+        };
+        let g_a = round_zero(&w_a, &mask);
+        let g_b = round_zero(&w_b, &mask);
+        assert_ne!(g_a, g_b);
+
+        // the messages differ by d with d(0) + d(1) = 0, which the mask g_0 + d / (ρ 2^3) of
+        // the same sum sends witness B to, and as g_0 is uniform so is g_0 + d: the first
+        // message is distributed the same for both witnesses
+        let d: Vec<Fr> = g_a.iter().zip(g_b.iter()).map(|(a, b)| *a - b).collect();
+        assert_eq!(
+            eval_univariate(&d, &Fr::zero()) + eval_univariate(&d, &Fr::one()),
+            Fr::zero()
+        );
+        let by = (mask.rho * Fr::from(8)).invert().unwrap();
+        let mut shifted = SumcheckMask {
+            g: mask.g.clone(),
+            ..mask
+        };
+        for (c, d) in shifted.g[0].iter_mut().zip(d.iter()) {
+            *c += *d * by;
+        }
+        assert_eq!(mask_sum(&shifted.g), mask_sum(&mask.g));
+        assert_eq!(round_zero(&w_b, &shifted), g_a);
+    }
+}
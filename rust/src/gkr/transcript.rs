@@ -0,0 +1,313 @@
+use super::{poly::SparsePoly, GKRCircuit};
+use crate::field::{goldilocks::*, modulus_le, CircuitField};
+use ethers_core::utils::keccak256;
+use ff::{Field, PrimeField};
+use halo2curves::bn256;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
+use mimc_rs::{Fr, FrRepr, Mimc7};
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+/// Hash used to derive Fiat-Shamir challenges. It is recorded in the proof so the
+/// verifier replays the transcript with the same function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashType {
+    /// MiMC7 with 91 rounds, as in circomlib's `MultiMiMC7`.
+    #[default]
+    Mimc7,
+    /// circomlib's Poseidon over the BN254 scalar field. Cheapest inside the recursive verifier.
+    Poseidon,
+    /// Keccak256 reduced modulo the field, which is what an EVM verifier computes natively.
+    Keccak256,
+}
+
+impl HashType {
+    /// MiMC7 and Poseidon are defined over the BN254 scalar field, so they only take limbs
+    /// below its modulus. Keccak256 works over any field.
+    pub fn supports<S: TranscriptField>(&self) -> bool {
+        match self {
+            HashType::Mimc7 | HashType::Poseidon => {
+                // little-endian, so compare from the most significant byte
+                let limb = S::limb_modulus();
+                let bn254 = modulus_le::<bn256::Fr>();
+                limb.iter().rev().cmp(bn254.iter().rev()) != std::cmp::Ordering::Greater
+            }
+            HashType::Keccak256 => true,
+        }
+    }
+
+    pub fn hasher(&self) -> Box<dyn TranscriptHash> {
+        match self {
+            HashType::Mimc7 => Box::new(Mimc7Hash(Mimc7::new(91))),
+            HashType::Poseidon => Box::new(PoseidonHash),
+            HashType::Keccak256 => Box::new(Keccak256Hash),
+        }
+    }
+}
+
+impl fmt::Display for HashType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashType::Mimc7 => write!(f, "mimc7"),
+            HashType::Poseidon => write!(f, "poseidon"),
+            HashType::Keccak256 => write!(f, "keccak256"),
+        }
+    }
+}
+
+impl FromStr for HashType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mimc" | "mimc7" => Ok(HashType::Mimc7),
+            "poseidon" => Ok(HashType::Poseidon),
+            "keccak" | "keccak256" => Ok(HashType::Keccak256),
+            _ => Err(format!(
+                "unknown hash {}, expected mimc7, poseidon or keccak256",
+                s
+            )),
+        }
+    }
+}
+
+/// Little-endian 32-byte word, the unit the transcript hashes work on.
+pub type Limb = [u8; 32];
+
+/// Field the Fiat-Shamir challenges live in. An element is absorbed as `LIMBS` limbs,
+/// each below `limb_modulus`, and a challenge is squeezed as `LIMBS` hash outputs.
+pub trait TranscriptField: Field + From<u64> {
+    const LIMBS: usize;
+    fn limb_modulus() -> Limb;
+    fn to_limbs(&self) -> Vec<Limb>;
+    fn from_limbs(limbs: &[Limb]) -> Self;
+}
+
+impl<F: CircuitField> TranscriptField for F {
+    const LIMBS: usize = 1;
+
+    fn limb_modulus() -> Limb {
+        modulus_le::<F>()
+    }
+
+    fn to_limbs(&self) -> Vec<Limb> {
+        vec![self.to_repr()]
+    }
+
+    fn from_limbs(limbs: &[Limb]) -> Self {
+        from_le_bytes_mod_order(&limbs[0])
+    }
+}
+
+impl TranscriptField for GoldilocksExt2 {
+    const LIMBS: usize = 2;
+
+    fn limb_modulus() -> Limb {
+        u64_to_limb(MODULUS)
+    }
+
+    fn to_limbs(&self) -> Vec<Limb> {
+        vec![u64_to_limb(self.c0.value()), u64_to_limb(self.c1.value())]
+    }
+
+    fn from_limbs(limbs: &[Limb]) -> Self {
+        GoldilocksExt2::new(
+            from_le_bytes_mod_order(&limbs[0]),
+            from_le_bytes_mod_order(&limbs[1]),
+        )
+    }
+}
+
+pub trait TranscriptHash {
+    /// Compresses `inputs` into a single limb under `key`.
+    fn hash(&self, inputs: &Vec<Limb>, key: &Limb) -> Limb;
+}
+
+pub struct Mimc7Hash(Mimc7);
+
+fn limb_to_fr(limb: &Limb) -> Fr {
+    Fr::from_repr(FrRepr(*limb)).unwrap()
+}
+
+impl TranscriptHash for Mimc7Hash {
+    fn hash(&self, inputs: &Vec<Limb>, key: &Limb) -> Limb {
+        let inputs_fr: Vec<Fr> = inputs.iter().map(limb_to_fr).collect();
+        let FrRepr(res) = self.0.multi_hash(inputs_fr, &limb_to_fr(key)).to_repr();
+        res
+    }
+}
+
+// circomlib's Poseidon takes at most 12 inputs, one of which is the chaining value.
+const POSEIDON_RATE: usize = 11;
+
+pub struct PoseidonHash;
+
+impl TranscriptHash for PoseidonHash {
+    fn hash(&self, inputs: &Vec<Limb>, key: &Limb) -> Limb {
+        let mut acc = *key;
+        let mut chunks: Vec<&[Limb]> = inputs.chunks(POSEIDON_RATE).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        for chunk in chunks {
+            let mut bytes: Vec<&[u8]> = vec![&acc];
+            bytes.extend(chunk.iter().map(|l| l.as_slice()));
+            let mut poseidon = Poseidon::<ark_bn254::Fr>::new_circom(bytes.len()).unwrap();
+            acc = poseidon
+                .hash_bytes_le(&bytes)
+                .expect("Poseidon transcript works over the BN254 scalar field");
+        }
+        acc
+    }
+}
+
+pub struct Keccak256Hash;
+
+impl TranscriptHash for Keccak256Hash {
+    fn hash(&self, inputs: &Vec<Limb>, key: &Limb) -> Limb {
+        // abi.encodePacked of uint256 values
+        let mut bytes = vec![];
+        for limb in std::iter::once(key).chain(inputs.iter()) {
+            let mut be = *limb;
+            be.reverse();
+            bytes.extend_from_slice(&be);
+        }
+        let mut digest = keccak256(bytes);
+        digest.reverse();
+        digest
+    }
+}
+
+pub fn from_le_bytes_mod_order<S: Field + From<u64>>(bytes: &[u8]) -> S {
+    let base = S::from(256);
+    bytes
+        .iter()
+        .rev()
+        .fold(S::zero(), |acc, b| acc * base + S::from(*b as u64))
+}
+
+fn u64_to_limb(value: u64) -> Limb {
+    let mut limb = [0u8; 32];
+    limb[..8].copy_from_slice(&value.to_le_bytes());
+    limb
+}
+
+fn label_to_limb(label: &[u8]) -> Limb {
+    assert!(label.len() < 32, "transcript label is too long");
+    let mut limb = [0u8; 32];
+    limb[..label.len()].copy_from_slice(label);
+    limb
+}
+
+// wires are bit strings of at most 3 * k bits, so each fits in a single limb
+fn pack_wires<B: Field>(wires: &Vec<Vec<B>>) -> Vec<Limb> {
+    wires
+        .iter()
+        .map(|w| {
+            assert!(w.len() < 253, "wire is too long to pack");
+            let mut limb = [0u8; 32];
+            for (i, b) in w.iter().rev().enumerate() {
+                if *b == B::one() {
+                    limb[i / 8] |= 1 << (i % 8);
+                }
+            }
+            limb
+        })
+        .collect()
+}
+
+/// Fiat-Shamir transcript. Every message is absorbed into a running state
+/// together with a label, and every challenge is squeezed out of that state,
+/// so a challenge depends on the whole history of the protocol up to it.
+pub struct Transcript<S: TranscriptField> {
+    hasher: Box<dyn TranscriptHash>,
+    state: Limb,
+    _field: PhantomData<S>,
+}
+
+impl<S: TranscriptField> Transcript<S> {
+    /// Panics if `hash` is not defined over S, which the prover and the verifier check first.
+    pub fn new(label: &[u8], hash: HashType) -> Self {
+        assert!(
+            hash.supports::<S>(),
+            "{} transcript needs limbs below the BN254 modulus",
+            hash
+        );
+        let hasher = hash.hasher();
+        let state = hasher.hash(&vec![label_to_limb(label)], &[0u8; 32]);
+        Transcript {
+            hasher,
+            state,
+            _field: PhantomData,
+        }
+    }
+
+    // len is the number of messages, each of which may take several limbs
+    fn absorb(&mut self, label: &[u8], len: usize, limbs: Vec<Limb>) {
+        let mut inputs = vec![label_to_limb(label), u64_to_limb(len as u64)];
+        inputs.extend(limbs);
+        self.state = self.hasher.hash(&inputs, &self.state);
+    }
+
+    pub fn append(&mut self, label: &[u8], value: &S) {
+        self.append_vec(label, &vec![*value]);
+    }
+
+    pub fn append_vec(&mut self, label: &[u8], values: &Vec<S>) {
+        let limbs = values.iter().flat_map(|v| v.to_limbs()).collect();
+        self.absorb(label, values.len(), limbs);
+    }
+
+    /// Absorbs bytes that are not field elements, such as a commitment.
+    pub fn append_bytes(&mut self, label: &[u8], bytes: &[u8]) {
+        // a chunk one byte shorter than the limb modulus is always below it
+        let width = S::limb_modulus().iter().rposition(|b| *b != 0).unwrap_or(1);
+        let limbs = bytes
+            .chunks(width)
+            .map(|chunk| {
+                let mut limb = [0u8; 32];
+                limb[..chunk.len()].copy_from_slice(chunk);
+                limb
+            })
+            .collect();
+        self.absorb(label, bytes.len(), limbs);
+    }
+
+    pub fn append_poly(&mut self, label: &[u8], poly: &SparsePoly<S>) {
+        self.append(label, &S::from(poly.terms.len() as u64));
+        for t in poly.terms.iter() {
+            let mut term = vec![t.coeff];
+            term.extend(t.exps.iter().map(|e| S::from(*e as u64)));
+            self.append_vec(label, &term);
+        }
+    }
+
+    /// Binds the layer sizes and the wiring of every layer.
+    /// The circuit may be over a subfield of the challenge field.
+    pub fn append_circuit<B: PrimeField>(&mut self, circuit: &GKRCircuit<B>) {
+        let ks = circuit
+            .get_k_list()
+            .iter()
+            .map(|k| S::from(*k as u64))
+            .collect();
+        self.append_vec(b"circuit k", &ks);
+        for layer in circuit.layer.iter() {
+            self.absorb(b"add wire", layer.wire.0.len(), pack_wires(&layer.wire.0));
+            self.absorb(b"mult wire", layer.wire.1.len(), pack_wires(&layer.wire.1));
+        }
+        let public = circuit
+            .public()
+            .iter()
+            .map(|p| S::from(*p as u64))
+            .collect();
+        self.append_vec(b"public gates", &public);
+    }
+
+    pub fn challenge(&mut self, label: &[u8]) -> S {
+        let mut limbs = vec![];
+        for _ in 0..S::LIMBS {
+            self.state = self.hasher.hash(&vec![label_to_limb(label)], &self.state);
+            limbs.push(self.state);
+        }
+        S::from_limbs(&limbs)
+    }
+}
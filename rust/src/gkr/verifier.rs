@@ -0,0 +1,865 @@
+use super::{
+    pcs::{Clear, Pcs},
+    poly::*,
+    sumcheck::*,
+    transcript::{HashType, Transcript, TranscriptField},
+    zk::{powers_point, vanishing, MaskLayout},
+    GKRCircuit, Proof,
+};
+use ff::{Field, PrimeField};
+use std::fmt;
+
+/// Reason a GKR proof was rejected. Layers are counted from the output layer,
+/// rounds from the first sumcheck round of that layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The proof does not have the shape the circuit expects.
+    Malformed(String),
+    /// z[0] differs from the output point derived from the transcript.
+    OutputPoint,
+    /// g_j(0) + g_j(1) differs from the running claim.
+    SumcheckRound { layer: usize, round: usize },
+    /// A sumcheck challenge differs from the one derived from the transcript.
+    Challenge { layer: usize, round: usize },
+    /// The last round does not match add_i and mult_i at (z, b*, c*) with W(b*), W(c*) taken from q.
+    GateEvaluation { layer: usize },
+    /// r* differs from the one derived from the transcript.
+    LineChallenge { layer: usize },
+    /// z[i + 1] is not the line through b* and c* at r*.
+    NextPoint { layer: usize },
+    /// The last claim differs from the input layer evaluated at z[d - 1], with the public
+    /// gates set to the claimed values.
+    InputLayer,
+    /// The opening of the input layer commitment is invalid.
+    Opening,
+    /// The hash of the proof is not defined over the challenge field.
+    UnsupportedHash(HashType),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Malformed(reason) => write!(f, "malformed proof: {}", reason),
+            VerifyError::OutputPoint => write!(f, "wrong output point"),
+            VerifyError::SumcheckRound { layer, round } => {
+                write!(f, "sumcheck failed at layer {} round {}", layer, round)
+            }
+            VerifyError::Challenge { layer, round } => {
+                write!(f, "wrong challenge at layer {} round {}", layer, round)
+            }
+            VerifyError::GateEvaluation { layer } => {
+                write!(f, "gate evaluation failed at layer {}", layer)
+            }
+            VerifyError::LineChallenge { layer } => {
+                write!(f, "wrong line challenge at layer {}", layer)
+            }
+            VerifyError::NextPoint { layer } => write!(f, "wrong next point at layer {}", layer),
+            VerifyError::InputLayer => write!(f, "input layer evaluation failed"),
+            VerifyError::Opening => write!(f, "input layer opening failed"),
+            VerifyError::UnsupportedHash(hash) => {
+                write!(f, "{} transcript needs limbs below the BN254 modulus", hash)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl VerifyError {
+    /// Layer the check failed at, if the failure is specific to one.
+    pub fn layer(&self) -> Option<usize> {
+        match self {
+            VerifyError::SumcheckRound { layer, .. }
+            | VerifyError::Challenge { layer, .. }
+            | VerifyError::GateEvaluation { layer }
+            | VerifyError::LineChallenge { layer }
+            | VerifyError::NextPoint { layer } => Some(*layer),
+            VerifyError::OutputPoint => Some(0),
+            VerifyError::Malformed(_)
+            | VerifyError::InputLayer
+            | VerifyError::Opening
+            | VerifyError::UnsupportedHash(_) => None,
+        }
+    }
+}
+
+// the largest batch verify_batch accepts is 2^MAX_BATCH_BITS copies
+const MAX_BATCH_BITS: usize = 32;
+
+fn malformed<T>(reason: String) -> Result<T, VerifyError> {
+    Err(VerifyError::Malformed(reason))
+}
+
+pub fn verify<B: PrimeField, E: TranscriptField + From<B>>(
+    circuit: &GKRCircuit<B>,
+    proof: &Proof<B, E>,
+) -> Result<(), VerifyError> {
+    verify_copies(circuit, 0, &Clear, proof)
+}
+
+/// Verifies a proof of `prover::prove_with`, whose input layer is committed to by `pcs`.
+pub fn verify_with<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    pcs: &P,
+    proof: &Proof<B, E, P>,
+) -> Result<(), VerifyError> {
+    verify_copies(circuit, 0, pcs, proof)
+}
+
+/// Verifies a proof of `prover::prove_batch`, or of `prover::prove` for a batch of one.
+/// The number of copies is read from the proof and bound by the transcript.
+pub fn verify_batch<B: PrimeField, E: TranscriptField + From<B>>(
+    circuit: &GKRCircuit<B>,
+    proof: &Proof<B, E>,
+) -> Result<(), VerifyError> {
+    let n = batch_bits(circuit, proof)?;
+    let input_vars = circuit.k(circuit.depth()) + n;
+    if proof.commitment.num_vars != input_vars || proof.commitment.evals.len() != 1 << input_vars {
+        return malformed(format!(
+            "a batch of 2^{} copies does not match the input layer",
+            n
+        ));
+    }
+    verify_copies(circuit, n, &Clear, proof)
+}
+
+/// Verifies a proof whose input layer is committed to by `pcs`, of one copy or of a batch.
+pub fn verify_batch_with<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    pcs: &P,
+    proof: &Proof<B, E, P>,
+) -> Result<(), VerifyError> {
+    let n = batch_bits(circuit, proof)?;
+    verify_copies(circuit, n, pcs, proof)
+}
+
+// the batch size comes from the proof, so it is bounded, and checked against the public
+// values, before anything is sized by it
+fn batch_bits<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    proof: &Proof<B, E, P>,
+) -> Result<usize, VerifyError> {
+    let n = match proof.k.first() {
+        Some(k) if *k >= circuit.k(0) => k - circuit.k(0),
+        _ => return malformed(String::from("k does not match the circuit")),
+    };
+    if n > MAX_BATCH_BITS || proof.public.len() != circuit.public().len() << n {
+        return malformed(format!(
+            "a batch of 2^{} copies does not match the public gates",
+            n
+        ));
+    }
+    Ok(n)
+}
+
+// the wiring of 2^n copies at (g || z, g' || b, g'' || c) is the wiring of one copy at
+// (z, b, c) times the predicate that g, g' and g'' are the same copy
+fn copy_eq<E: Field>(n: usize, z: &[E], b: &[E], c: &[E]) -> E {
+    let mut res = E::one();
+    for j in 0..n {
+        res *= z[j] * b[j] * c[j] + (E::one() - z[j]) * (E::one() - b[j]) * (E::one() - c[j]);
+    }
+    res
+}
+
+// add_i and mult_i of layer i of 2^n copies at (z, b, c)
+fn wiring<B: PrimeField, E: TranscriptField + From<B>>(
+    circuit: &GKRCircuit<B>,
+    i: usize,
+    n: usize,
+    z: &[E],
+    b: &[E],
+    c: &[E],
+) -> (E, E) {
+    let mut point = z[n..].to_vec();
+    point.extend_from_slice(&b[n..]);
+    point.extend_from_slice(&c[n..]);
+    let eq = copy_eq(n, z, b, c);
+    (
+        circuit.layer[i].add.evaluate(&point) * eq,
+        circuit.layer[i].mult.evaluate(&point) * eq,
+    )
+}
+
+// replays the statement that prove_copies binds before the commitment
+fn bind_statement<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    n: usize,
+    proof: &Proof<B, E, P>,
+) -> Transcript<E> {
+    let mut transcript = Transcript::new(b"gkr", proof.hash);
+    transcript.append_circuit(circuit);
+    if n > 0 {
+        transcript.append(b"batch", &E::from(n as u64));
+    }
+    let values = proof.public.iter().map(|(_, v)| E::from(*v)).collect();
+    transcript.append_vec(b"public", &values);
+    transcript
+}
+
+fn verify_copies<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    n: usize,
+    pcs: &P,
+    proof: &Proof<B, E, P>,
+) -> Result<(), VerifyError> {
+    if !proof.hash.supports::<E>() {
+        return Err(VerifyError::UnsupportedHash(proof.hash));
+    }
+    let depth = circuit.depth();
+    let k = |i: usize| circuit.k(i) + n;
+
+    // a zero-knowledge proof keeps only the output point
+    let zk = !proof.masks.is_empty();
+    if proof.depth != depth + 1
+        || proof.sumcheck_proofs.len() != depth
+        || proof.sumcheck_r.len() != depth
+        || proof.q.len() != depth
+        || proof.r.len() != depth
+        || proof.z.len() != if zk { 1 } else { depth + 1 }
+        || (zk && proof.masks.len() != depth)
+    {
+        return malformed(format!("expected {} layers", depth + 1));
+    }
+    if proof.k != (0..(depth + 1)).map(k).collect::<Vec<_>>() {
+        return malformed(String::from("k does not match the circuit"));
+    }
+    let positions = circuit.public_positions(n);
+    if proof.public.len() != positions.len()
+        || proof
+            .public
+            .iter()
+            .zip(positions.iter())
+            .any(|((p, _), q)| p != q)
+    {
+        return malformed(String::from("public gates do not match the circuit"));
+    }
+
+    if zk {
+        return verify_copies_zk(circuit, n, pcs, proof);
+    }
+    let mut transcript = bind_statement(circuit, n, proof);
+    pcs.append_commitment(&mut transcript, &proof.commitment);
+
+    // the output layer is claimed to be zero everywhere, so W_0(z[0]) = 0
+    let mut z_zero = vec![];
+    for _ in 0..k(0) {
+        z_zero.push(transcript.challenge(b"output point"));
+    }
+    if proof.z[0] != z_zero {
+        return Err(VerifyError::OutputPoint);
+    }
+    let mut m = E::zero();
+
+    for i in 0..depth {
+        transcript.append(b"layer", &E::from(i as u64));
+        let k_next = k(i + 1);
+        let v = 2 * k_next;
+        let sumcheck_proof = &proof.sumcheck_proofs[i];
+        let r = &proof.sumcheck_r[i];
+        // a next layer of a single gate has no variables, so its sumcheck has no rounds
+        if r.len() != v {
+            return malformed(format!("layer {} should have {} sumcheck rounds", i, v));
+        }
+        if proof.z[i].len() != k(i) {
+            return malformed(format!("z[{}] should have {} variables", i, k(i)));
+        }
+
+        // add_i * (W(b) + W(c)) + mult_i * W(b) * W(c) is at most cubic in each variable.
+        let (challenges, expected) = match verify_sumcheck(m, sumcheck_proof, 3, v, &mut transcript)
+        {
+            Ok(res) => res,
+            Err(SumcheckError::Round { round }) => {
+                return Err(VerifyError::SumcheckRound { layer: i, round })
+            }
+            Err(e) => return malformed(format!("layer {}: {}", i, e)),
+        };
+        for (j, (r_j, c_j)) in r.iter().zip(challenges.iter()).enumerate() {
+            if r_j != c_j {
+                return Err(VerifyError::Challenge { layer: i, round: j });
+            }
+        }
+
+        let q_i = &proof.q[i];
+        if q_i.is_empty() || q_i.len() > k_next + 1 {
+            return malformed(format!("q[{}] has a bad degree", i));
+        }
+        let w_b = eval_univariate(q_i, &E::zero());
+        let w_c = eval_univariate(q_i, &E::one());
+        let (add, mult) = wiring(circuit, i, n, &proof.z[i], &r[..k_next], &r[k_next..]);
+        if expected != add * (w_b + w_c) + mult * w_b * w_c {
+            return Err(VerifyError::GateEvaluation { layer: i });
+        }
+
+        transcript.append_vec(b"q", q_i);
+        let r_star = transcript.challenge(b"r star");
+        if proof.r[i] != r_star {
+            return Err(VerifyError::LineChallenge { layer: i });
+        }
+        let b_star = r[..k_next].to_vec();
+        let c_star = r[k_next..].to_vec();
+        if proof.z[i + 1] != l_function(&b_star, &c_star, &r_star) {
+            return Err(VerifyError::NextPoint { layer: i });
+        }
+        m = eval_univariate(q_i, &r_star);
+    }
+
+    // the private part of the input layer is zero at the public gates, whose share is
+    // eq(z, gate) times the claimed value
+    let z = &proof.z[depth];
+    let mut points = vec![z.clone()];
+    let rho: Vec<E> = (0..n)
+        .map(|_| transcript.challenge(b"public copies"))
+        .collect();
+    points.extend(circuit.public_points(&rho));
+    let values = match pcs.verify(&proof.commitment, &points, &proof.opening, &mut transcript) {
+        Some(values) if values.len() == points.len() => values,
+        _ => return Err(VerifyError::Opening),
+    };
+    for (j, pos) in circuit.public().iter().enumerate() {
+        if values[j + 1] != E::zero() {
+            return malformed(format!("input layer sets public gate {}", pos));
+        }
+    }
+    let mut input = values[0];
+    for (pos, value) in proof.public.iter() {
+        input += eq_eval(z, &index_to_bits(*pos, k(depth))) * E::from(*value);
+    }
+    if m != input {
+        return Err(VerifyError::InputLayer);
+    }
+    Ok(())
+}
+
+// the rest of verify_copies for a zero-knowledge proof of prove_copies_zk, whose shape is checked
+fn verify_copies_zk<B: PrimeField, E: TranscriptField + From<B>, P: Pcs<B, E>>(
+    circuit: &GKRCircuit<B>,
+    n: usize,
+    pcs: &P,
+    proof: &Proof<B, E, P>,
+) -> Result<(), VerifyError> {
+    let depth = circuit.depth();
+    if let Some(i) = (1..depth).find(|i| proof.k[*i] == 0) {
+        return malformed(format!("layer {} of a single gate cannot be masked", i));
+    }
+    let layout = MaskLayout::new(&proof.k);
+    let mut transcript = bind_statement(circuit, n, proof);
+    transcript.append(b"zero knowledge", &E::one());
+    pcs.append_commitment(&mut transcript, &proof.commitment);
+
+    let mut z_zero = vec![];
+    for _ in 0..proof.k[0] {
+        z_zero.push(transcript.challenge(b"output point"));
+    }
+    if proof.z[0] != z_zero {
+        return Err(VerifyError::OutputPoint);
+    }
+    let mut m = E::zero();
+    // what the last round of every layer should be, short of the masks that are opened last
+    let mut expected = vec![];
+    let mut rhos = vec![];
+
+    for i in 0..depth {
+        transcript.append(b"layer", &E::from(i as u64));
+        let kb = layout.rounds(i) / 2;
+        let r = &proof.sumcheck_r[i];
+        if r.len() != 2 * kb {
+            return malformed(format!(
+                "layer {} should have {} sumcheck rounds",
+                i,
+                2 * kb
+            ));
+        }
+        transcript.append(b"mask", &proof.masks[i]);
+        let rho: E = transcript.challenge(b"mask challenge");
+        // the masked values of the next layer are cubic in x_1, times the wiring, and the
+        // masks are quartic
+        let claim = m + rho * proof.masks[i];
+        let (challenges, last) =
+            match verify_sumcheck(claim, &proof.sumcheck_proofs[i], 4, 2 * kb, &mut transcript) {
+                Ok(res) => res,
+                Err(SumcheckError::Round { round }) => {
+                    return Err(VerifyError::SumcheckRound { layer: i, round })
+                }
+                Err(e) => return malformed(format!("layer {}: {}", i, e)),
+            };
+        for (j, (r_j, c_j)) in r.iter().zip(challenges.iter()).enumerate() {
+            if r_j != c_j {
+                return Err(VerifyError::Challenge { layer: i, round: j });
+            }
+        }
+
+        let q_i = &proof.q[i];
+        if q_i.is_empty() || q_i.len() > 2 {
+            return malformed(format!("q[{}] has a bad degree", i));
+        }
+        let w_b = eval_univariate(q_i, &E::zero());
+        let w_c = eval_univariate(q_i, &E::one());
+        // the wiring of the last layer reads the first half of the padded input layer
+        let (mut b, mut c) = (&r[..kb], &r[kb..]);
+        let mut pad = E::one();
+        if i + 1 == depth {
+            pad = (E::one() - b[0]) * (E::one() - c[0]);
+            (b, c) = (&b[1..], &c[1..]);
+        }
+        let (add, mult) = if i == 0 {
+            wiring(circuit, i, n, &z_zero, b, c)
+        } else {
+            let r_star = proof.r[i - 1];
+            let (u, v) = proof.sumcheck_r[i - 1].split_at(proof.k[i]);
+            let (add_u, mult_u) = wiring(circuit, i, n, u, b, c);
+            let (add_v, mult_v) = wiring(circuit, i, n, v, b, c);
+            (
+                (E::one() - r_star) * add_u + r_star * add_v,
+                (E::one() - r_star) * mult_u + r_star * mult_v,
+            )
+        };
+        expected.push(last - pad * (add * (w_b + w_c) + mult * w_b * w_c));
+        rhos.push(rho);
+
+        transcript.append_vec(b"q", q_i);
+        let r_star = transcript.challenge(b"r star");
+        if proof.r[i] != r_star {
+            return Err(VerifyError::LineChallenge { layer: i });
+        }
+        m = eval_univariate(q_i, &r_star);
+    }
+
+    let positions: Vec<usize> = proof.public.iter().map(|(pos, _)| *pos).collect();
+    let points = layout.openings(&proof.sumcheck_r, &positions);
+    let values = match pcs.verify(&proof.commitment, &points, &proof.opening, &mut transcript) {
+        Some(values) if values.len() == points.len() => values,
+        _ => return Err(VerifyError::Opening),
+    };
+    let public = &values[2..2 + proof.public.len()];
+    let g_values = &values[2 + proof.public.len()..];
+    let rounds: usize = (0..depth).map(|i| layout.rounds(i)).sum();
+    let r_values = &g_values[rounds..];
+
+    let mut g_openings = g_values.iter();
+    for i in 0..depth {
+        let kb = layout.rounds(i) / 2;
+        // g(b*, c*) = Σ_j g_j(r_j), each opened divided by the factor of its point
+        let mut g = E::zero();
+        for r_j in proof.sumcheck_r[i].iter() {
+            let factor = powers_point(r_j).1;
+            if factor == E::zero() {
+                return Err(VerifyError::Opening);
+            }
+            g += factor * g_openings.next().unwrap();
+        }
+        let mut mask = rhos[i] * g;
+        if i > 0 {
+            // ψ(c_last) / 2^(2k - 1) for ψ(x) = (1 - r*)Z(u)R(u_1, x) + r*Z(v)R(v_1, x)
+            let r_star = proof.r[i - 1];
+            let (u, v) = proof.sumcheck_r[i - 1].split_at(proof.k[i]);
+            let psi = (E::one() - r_star) * vanishing(u) * r_values[2 * (i - 1)]
+                + r_star * vanishing(v) * r_values[2 * (i - 1) + 1];
+            let spread = E::from(2).pow_vartime([2 * kb as u64 - 1]);
+            mask += psi * spread.invert().unwrap();
+        }
+        if expected[i] != mask {
+            return Err(VerifyError::GateEvaluation { layer: i });
+        }
+    }
+
+    // the masked values of the last layer are the padded input layer at u and v, with the
+    // public values in its first half
+    for (j, (pos, _)) in proof.public.iter().enumerate() {
+        if public[j] != E::zero() {
+            return malformed(format!("input layer sets public gate {}", pos));
+        }
+    }
+    let k_input = layout.vars(layout.input());
+    let (u, v) = proof.sumcheck_r[depth - 1].split_at(k_input);
+    let q_last = &proof.q[depth - 1];
+    let claims = [
+        eval_univariate(q_last, &E::zero()),
+        eval_univariate(q_last, &E::one()),
+    ];
+    for (j, point) in [u, v].iter().enumerate() {
+        let mut input = values[j];
+        for (pos, value) in proof.public.iter() {
+            input += eq_eval(&point.to_vec(), &index_to_bits(*pos, k_input)) * E::from(*value);
+        }
+        if input != claims[j] {
+            return Err(VerifyError::InputLayer);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{verify, verify_batch, verify_batch_with, verify_with, VerifyError};
+    use crate::field::{goldilocks::*, CircuitField};
+    use crate::gkr::{
+        pcs::{kzg::Kzg, ligero::Ligero},
+        poly::*,
+        prover::{self, ProveError, ProverOptions},
+        sumcheck::tests::FixedRng,
+        transcript::HashType,
+        GKRCircuit, Input, Layer, Proof,
+    };
+    use ff::{Field, PrimeField};
+    use halo2curves::bn256::Fr;
+    use rand_core::OsRng;
+
+    // gates are (is_mult, left, right)
+    fn layer<S: PrimeField>(k: usize, k_next: usize, gates: &[(bool, usize, usize)]) -> Layer<S> {
+        let v = k + 2 * k_next;
+        let mut add = BinaryPoly::zero(v);
+        let mut mult = BinaryPoly::zero(v);
+        let mut add_wire = vec![];
+        let mut mult_wire = vec![];
+        for (curr, (is_mult, left, right)) in gates.iter().enumerate() {
+            let s = format!(
+                "{}{:0k_next$b}{:0k_next$b}",
+                format!("{:0k$b}", curr, k = k),
+                left,
+                right,
+                k_next = k_next
+            );
+            let bits = s
+                .chars()
+                .map(|c| if c == '1' { S::one() } else { S::zero() })
+                .collect();
+            if *is_mult {
+                mult = &mult + &chi_w_for_binary(&s);
+                mult_wire.push(bits);
+            } else {
+                add = &add + &chi_w_for_binary(&s);
+                add_wire.push(bits);
+            }
+        }
+        Layer::new(k, add, mult, (add_wire, mult_wire))
+    }
+
+    // out[0] = (a * b) + (c + 0) and out[1] = (0 + 0) * (a * b), both zero when a * b = -c
+    pub(crate) fn example<S: PrimeField>(c: S) -> (GKRCircuit<S>, Input<S>) {
+        let circuit = GKRCircuit::new(
+            vec![
+                layer(1, 2, &[(false, 0, 1), (true, 2, 3)]),
+                layer(
+                    2,
+                    2,
+                    &[(true, 0, 1), (false, 2, 3), (false, 3, 3), (true, 0, 1)],
+                ),
+            ],
+            2,
+        );
+        let a = S::from(2);
+        let b = S::from(3);
+        let values = vec![
+            vec![a * b + c, S::zero()],
+            vec![a * b, c, S::zero(), a * b],
+            vec![a, b, c, S::zero()],
+        ];
+        let w = values.into_iter().map(MultilinearPoly::new).collect();
+        (circuit, Input { w })
+    }
+
+    #[test]
+    fn test_verify() {
+        let (circuit, input) = example(Fr::zero() - Fr::from(6));
+        for hash in [HashType::Mimc7, HashType::Poseidon, HashType::Keccak256] {
+            let proof = prover::prove(&circuit, &input, hash).unwrap();
+            assert_eq!(verify(&circuit, &proof), Ok(()));
+        }
+
+        let proof = prover::prove(&circuit, &input, HashType::Mimc7).unwrap();
+        let mut other_hash = proof.clone();
+        other_hash.hash = HashType::Keccak256;
+        assert!(verify(&circuit, &other_hash).is_err());
+
+        let mut bad_round = proof.clone();
+        bad_round.sumcheck_proofs[1][2][0] += Fr::one();
+        assert_eq!(
+            verify(&circuit, &bad_round),
+            Err(VerifyError::SumcheckRound { layer: 1, round: 2 })
+        );
+
+        // the input layer is bound by the transcript before the output point is drawn
+        let mut bad_input = proof.clone();
+        bad_input.commitment.evals[0] += Fr::one();
+        assert_eq!(verify(&circuit, &bad_input), Err(VerifyError::OutputPoint));
+
+        // a nonzero output gate is caught even though it is not the first one
+        let (circuit, input) = example(Fr::zero() - Fr::from(5));
+        let proof = prover::prove(&circuit, &input, HashType::Mimc7).unwrap();
+        assert!(verify(&circuit, &proof).is_err());
+    }
+
+    fn check_field<S: CircuitField>() {
+        assert!(!HashType::Mimc7.supports::<S>());
+        let (circuit, input) = example(S::zero() - S::from(6));
+        let proof = prover::prove(&circuit, &input, HashType::Keccak256).unwrap();
+        assert_eq!(verify(&circuit, &proof), Ok(()));
+
+        // mimc7 is not defined over S, which is an error rather than a panic
+        assert_eq!(
+            prover::prove(&circuit, &input, HashType::Mimc7).unwrap_err(),
+            ProveError::UnsupportedHash(HashType::Mimc7)
+        );
+        let mut mimc = proof.clone();
+        mimc.hash = HashType::Mimc7;
+        assert_eq!(
+            verify(&circuit, &mimc),
+            Err(VerifyError::UnsupportedHash(HashType::Mimc7))
+        );
+    }
+
+    #[test]
+    fn test_single_gate_layer() {
+        // out[0] = a * b + a * b over a middle layer of the single gate a * b, whose sumcheck
+        // has no rounds
+        let (zero, one) = (Fr::zero(), Fr::one());
+        let circuit = GKRCircuit::new(
+            vec![
+                Layer::from_wires(0, 0, (vec![vec![]], vec![])),
+                Layer::from_wires(0, 1, (vec![], vec![vec![zero, one]])),
+            ],
+            1,
+        );
+        let input = |a: u64| {
+            let (a, b) = (Fr::from(a), Fr::from(5));
+            let values = vec![vec![a * b + a * b], vec![a * b], vec![a, b]];
+            Input {
+                w: values.into_iter().map(MultilinearPoly::new).collect(),
+            }
+        };
+        let proof = prover::prove(&circuit, &input(0), HashType::Keccak256).unwrap();
+        assert!(proof.sumcheck_proofs[0].is_empty());
+        assert_eq!(verify(&circuit, &proof), Ok(()));
+        let proof = prover::prove(&circuit, &input(1), HashType::Keccak256).unwrap();
+        assert!(verify(&circuit, &proof).is_err());
+
+        // whose value cannot be masked in zero knowledge
+        let kzg = Kzg::setup(8, FixedRng(0));
+        let options = ProverOptions { zk: true };
+        let proof = prover::prove_with_options(
+            &circuit,
+            &input(0),
+            &kzg,
+            HashType::Keccak256,
+            &options,
+            &mut FixedRng(1),
+        );
+        assert_eq!(proof.unwrap_err(), ProveError::SingleGateLayer { layer: 1 });
+    }
+
+    #[test]
+    fn test_verify_other_fields() {
+        check_field::<bls12_381::Scalar>();
+        check_field::<pasta_curves::Fp>();
+        check_field::<pasta_curves::Fq>();
+    }
+
+    #[test]
+    fn test_verify_goldilocks() {
+        let (circuit, input) = example(Goldilocks::zero() - Goldilocks::from(6));
+        for hash in [HashType::Mimc7, HashType::Poseidon, HashType::Keccak256] {
+            let proof = prover::prove_ext::<_, GoldilocksExt2>(&circuit, &input, hash).unwrap();
+            assert_eq!(verify(&circuit, &proof), Ok(()));
+        }
+
+        let (circuit, input) = example(Goldilocks::zero() - Goldilocks::from(5));
+        let proof =
+            prover::prove_ext::<_, GoldilocksExt2>(&circuit, &input, HashType::Keccak256).unwrap();
+        assert!(verify(&circuit, &proof).is_err());
+    }
+
+    #[test]
+    fn test_verify_public() {
+        // c is public, a and b stay in input_func
+        let c = Fr::zero() - Fr::from(6);
+        let (circuit, input) = example(c);
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![2]);
+        let proof = prover::prove(&circuit, &input, HashType::Mimc7).unwrap();
+        assert_eq!(proof.public, vec![(2, c)]);
+        assert_eq!(proof.input_func().evals[2], Fr::zero());
+        assert_eq!(proof.input_layer().evals, input.w(2).evals);
+        assert_eq!(verify(&circuit, &proof), Ok(()));
+
+        // the proof is for these public values only
+        let mut other_value = proof.clone();
+        other_value.public[0].1 += Fr::one();
+        assert!(verify(&circuit, &other_value).is_err());
+        let mut hidden = proof.clone();
+        hidden.commitment.evals[2] = c;
+        assert!(verify(&circuit, &hidden).is_err());
+        let mut moved = proof.clone();
+        moved.public[0].0 = 3;
+        assert!(matches!(
+            verify(&circuit, &moved),
+            Err(VerifyError::Malformed(_))
+        ));
+
+        let inputs = vec![example(c).1, example(c).1, example(c).1];
+        let proof = prover::prove_batch(&circuit, &inputs, HashType::Mimc7).unwrap();
+        assert_eq!(
+            proof.public.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+            vec![2, 6, 10, 14]
+        );
+        assert_eq!(verify_batch(&circuit, &proof), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_kzg() {
+        let c = Fr::zero() - Fr::from(6);
+        let (circuit, input) = example(c);
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![2]);
+        let kzg = Kzg::setup(4, OsRng);
+        let vk = kzg.verifier_key();
+        let proof = prover::prove_with(&circuit, &input, &kzg, HashType::Poseidon).unwrap();
+        // z[d - 1] and the public gate
+        assert_eq!(proof.opening.len(), 2);
+        assert_eq!(verify_with(&circuit, &vk, &proof), Ok(()));
+
+        let mut bad_opening = proof.clone();
+        bad_opening.opening[0].value += Fr::one();
+        assert_eq!(
+            verify_with(&circuit, &vk, &bad_opening),
+            Err(VerifyError::Opening)
+        );
+        let mut other_value = proof.clone();
+        other_value.public[0].1 += Fr::one();
+        assert!(verify_with(&circuit, &vk, &other_value).is_err());
+    }
+
+    #[test]
+    fn test_verify_zk() {
+        let c = Fr::zero() - Fr::from(6);
+        let (circuit, input) = example(c);
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![2]);
+        let kzg = Kzg::setup(8, FixedRng(0));
+        let vk = kzg.verifier_key();
+        let options = ProverOptions { zk: true };
+        let prove = |input: &Input<Fr>, seed: u64| {
+            let hash = HashType::Poseidon;
+            prover::prove_with_options(&circuit, input, &kzg, hash, &options, &mut FixedRng(seed))
+        };
+        let proof = prove(&input, 1).unwrap();
+        assert_eq!(verify_with(&circuit, &vk, &proof), Ok(()));
+        assert_eq!(prove(&input, 1).unwrap().to_bytes(), proof.to_bytes());
+        // the masks are fresh for every proof
+        let again = prove(&input, 2).unwrap();
+        assert_eq!(verify_with(&circuit, &vk, &again), Ok(()));
+        assert_ne!(again.q, proof.q);
+        assert_ne!(again.sumcheck_proofs[0], proof.sumcheck_proofs[0]);
+
+        let mut bad_mask = proof.clone();
+        bad_mask.masks[1] += Fr::one();
+        assert!(verify_with(&circuit, &vk, &bad_mask).is_err());
+        let mut bad_value = proof.clone();
+        bad_value.q[0][0] += Fr::one();
+        assert!(verify_with(&circuit, &vk, &bad_value).is_err());
+        let mut other_value = proof.clone();
+        other_value.public[0].1 += Fr::one();
+        assert!(verify_with(&circuit, &vk, &other_value).is_err());
+
+        let (_, bad_input) = example(c + Fr::one());
+        let proof = prove(&bad_input, 3).unwrap();
+        assert!(verify_with(&circuit, &vk, &proof).is_err());
+
+        // a setup too small for the masks, and a commitment that does not hide
+        let small = Kzg::setup(4, FixedRng(0));
+        let needed = prover::committed_vars(&circuit, &options);
+        assert_eq!(
+            prover::prove_with_options(
+                &circuit,
+                &input,
+                &small,
+                HashType::Poseidon,
+                &options,
+                &mut FixedRng(1)
+            )
+            .unwrap_err(),
+            ProveError::TooManyVars { needed, max: 4 }
+        );
+        let ligero: Result<Proof<_, Fr, _>, _> = prover::prove_with_options(
+            &circuit,
+            &input,
+            &Ligero::default(),
+            HashType::Poseidon,
+            &options,
+            &mut FixedRng(1),
+        );
+        assert_eq!(ligero.unwrap_err(), ProveError::NotHiding);
+    }
+
+    #[test]
+    fn test_verify_ligero() {
+        let c = Fr::zero() - Fr::from(6);
+        let (circuit, input) = example(c);
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![2]);
+        let proof: Proof<_, Fr, _> =
+            prover::prove_with(&circuit, &input, &Ligero::default(), HashType::Mimc7).unwrap();
+        assert_eq!(verify_with(&circuit, &Ligero::default(), &proof), Ok(()));
+        let mut bad_column = proof.clone();
+        bad_column.opening.columns[0][0] += Fr::one();
+        assert_eq!(
+            verify_with(&circuit, &Ligero::default(), &bad_column),
+            Err(VerifyError::Opening)
+        );
+
+        let (circuit, input) = example(Goldilocks::zero() - Goldilocks::from(6));
+        let proof: Proof<_, GoldilocksExt2, _> =
+            prover::prove_with(&circuit, &input, &Ligero::default(), HashType::Keccak256).unwrap();
+        assert_eq!(verify_with(&circuit, &Ligero::default(), &proof), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let (circuit, _) = example(Fr::zero() - Fr::from(6));
+        let good = || example(Fr::zero() - Fr::from(6)).1;
+        let inputs = vec![good(), good(), good()];
+        let proof = prover::prove_batch(&circuit, &inputs, HashType::Mimc7).unwrap();
+        assert_eq!(proof.k, vec![3, 4, 4]);
+        assert_eq!(verify_batch(&circuit, &proof), Ok(()));
+        assert!(verify(&circuit, &proof).is_err());
+
+        // a batch of one is the plain proof
+        let single = prover::prove_batch(&circuit, &inputs[..1], HashType::Mimc7).unwrap();
+        assert_eq!(verify(&circuit, &single), Ok(()));
+
+        // one copy with a nonzero output breaks the whole batch
+        let (_, bad) = example(Fr::zero() - Fr::from(5));
+        let inputs = vec![good(), bad, good()];
+        let proof = prover::prove_batch(&circuit, &inputs, HashType::Mimc7).unwrap();
+        assert!(verify_batch(&circuit, &proof).is_err());
+
+        // a claimed batch larger than the input layer is rejected up front
+        let mut huge = proof.clone();
+        huge.k[0] += 40;
+        assert!(matches!(
+            verify_batch(&circuit, &huge),
+            Err(VerifyError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_batch_kzg() {
+        let (circuit, _) = example(Fr::zero() - Fr::from(6));
+        let circuit = GKRCircuit::with_public(circuit.layer, 2, vec![2]);
+        let good = || example(Fr::zero() - Fr::from(6)).1;
+        let kzg = Kzg::setup(5, OsRng);
+        let vk = kzg.verifier_key();
+        let inputs: Vec<_> = (0..7).map(|_| good()).collect();
+        let proof = prover::prove_batch_with(&circuit, &inputs, &kzg, HashType::Poseidon).unwrap();
+        assert_eq!(proof.k, vec![4, 5, 5]);
+        // z[d - 1] and the public gate of a random copy, for any number of copies
+        assert_eq!(proof.opening.len(), 2);
+        assert_eq!(proof.public.len(), 8);
+        assert_eq!(verify_batch_with(&circuit, &vk, &proof), Ok(()));
+
+        let mut other_value = proof.clone();
+        other_value.public[5].1 += Fr::one();
+        assert!(verify_batch_with(&circuit, &vk, &other_value).is_err());
+        let (_, bad) = example(Fr::zero() - Fr::from(5));
+        let mut inputs = inputs;
+        inputs[3] = bad;
+        let proof = prover::prove_batch_with(&circuit, &inputs, &kzg, HashType::Poseidon).unwrap();
+        assert!(verify_batch_with(&circuit, &vk, &proof).is_err());
+    }
+}
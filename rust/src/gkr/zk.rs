@@ -0,0 +1,236 @@
+use super::poly::{eval_univariate, index_to_bits, MultilinearPoly};
+use ff::Field;
+
+/// Degree of the univariate masks g_j of the sumchecks, that of their round polynomials.
+pub const MASK_DEGREE: usize = 4;
+
+// a mask g_j takes a block of 2^3 >= MASK_DEGREE + 1 coefficients
+const COEFFICIENT_VARS: usize = 3;
+
+/// Where a zero-knowledge proof keeps what it commits to, in one table: the input layer
+/// padded with a random half, the coefficients of the masks g_j of every sumcheck and the
+/// masks R of the layers between the output and the input. Regions are placed largest first,
+/// so each is aligned to its size and is the table with its first variables fixed to its
+/// position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaskLayout {
+    pub num_vars: usize,
+    depth: usize,
+    // number of sumcheck rounds of every layer
+    rounds: Vec<usize>,
+    // (offset, number of variables) of every region
+    regions: Vec<(usize, usize)>,
+}
+
+impl MaskLayout {
+    /// Layout for the layers of `k[i]` variables, from the output layer to the input layer.
+    pub fn new(k: &Vec<usize>) -> Self {
+        let depth = k.len() - 1;
+        let rounds: Vec<usize> = (0..depth)
+            .map(|i| 2 * (k[i + 1] + if i + 1 == depth { 1 } else { 0 }))
+            .collect();
+        let mut vars = vec![k[depth] + 1];
+        for n in rounds.iter() {
+            vars.push(n.next_power_of_two().trailing_zeros() as usize + COEFFICIENT_VARS);
+        }
+        vars.extend(vec![2; depth.saturating_sub(1)]);
+        let mut order: Vec<usize> = (0..vars.len()).collect();
+        order.sort_by(|x, y| vars[*y].cmp(&vars[*x]));
+        let mut regions = vec![(0, 0); vars.len()];
+        let mut offset: usize = 0;
+        for j in order {
+            regions[j] = (offset, vars[j]);
+            offset += 1 << vars[j];
+        }
+        MaskLayout {
+            num_vars: offset.next_power_of_two().trailing_zeros() as usize,
+            depth,
+            rounds,
+            regions,
+        }
+    }
+
+    pub fn input(&self) -> usize {
+        0
+    }
+
+    /// Region of the coefficients of the masks g_j of the sumcheck of `layer`.
+    pub fn g(&self, layer: usize) -> usize {
+        1 + layer
+    }
+
+    /// Region of R for `layer`, which is between 1 and depth - 1.
+    pub fn r(&self, layer: usize) -> usize {
+        self.depth + layer
+    }
+
+    pub fn vars(&self, region: usize) -> usize {
+        self.regions[region].1
+    }
+
+    /// Number of rounds of the sumcheck of `layer`, over b and c of the next layer.
+    pub fn rounds(&self, layer: usize) -> usize {
+        self.rounds[layer]
+    }
+
+    /// The point of the whole table at which it takes the value of `region` at `x`.
+    pub fn point<E: Field>(&self, region: usize, x: &Vec<E>) -> Vec<E> {
+        let (offset, vars) = self.regions[region];
+        assert_eq!(x.len(), vars);
+        let mut point = index_to_bits(offset >> vars, self.num_vars - vars);
+        point.extend_from_slice(x);
+        point
+    }
+
+    /// The point of the whole table at which it takes the value g_j(x) / `powers_point(x).1`
+    /// for the mask g_j of round j of the sumcheck of `layer`.
+    pub fn mask_point<E: Field>(&self, layer: usize, j: usize, x: &E) -> Vec<E> {
+        let region = self.g(layer);
+        let mut y = index_to_bits(j, self.vars(region) - COEFFICIENT_VARS);
+        y.extend(powers_point(x).0);
+        self.point(region, &y)
+    }
+
+    /// The points the table is opened at, given the sumcheck challenges: the padded input
+    /// layer at b* and c* of the last layer and at the public gates, the mask g_j of every
+    /// round of every layer at its challenge, and R of every inner layer at (u_1, c_last) and
+    /// (v_1, c_last), where u and v are b* and c* of the layer before.
+    pub fn openings<E: Field>(&self, r: &Vec<Vec<E>>, public: &Vec<usize>) -> Vec<Vec<E>> {
+        let k_input = self.vars(self.input());
+        let (u, v) = r[self.depth - 1].split_at(k_input);
+        let mut points = vec![
+            self.point(self.input(), &u.to_vec()),
+            self.point(self.input(), &v.to_vec()),
+        ];
+        for pos in public.iter() {
+            points.push(self.point(self.input(), &index_to_bits(*pos, k_input)));
+        }
+        for (i, r_i) in r.iter().enumerate() {
+            for (j, r_j) in r_i.iter().enumerate() {
+                points.push(self.mask_point(i, j, r_j));
+            }
+        }
+        for i in 1..self.depth {
+            let (u, v) = r[i - 1].split_at(self.rounds(i - 1) / 2);
+            let c_last = *r[i].last().unwrap();
+            points.push(self.point(self.r(i), &vec![u[0], c_last]));
+            points.push(self.point(self.r(i), &vec![v[0], c_last]));
+        }
+        points
+    }
+
+    /// The region of the masks g_j of the sumcheck of `layer`, given their coefficients from
+    /// the highest degree down: that of x^t of g_j is at t of the block j of 2^3 values.
+    pub fn mask_table<S: Field>(&self, layer: usize, g: &[Vec<S>]) -> MultilinearPoly<S> {
+        assert_eq!(g.len(), self.rounds(layer));
+        let mut table = MultilinearPoly::zero(self.vars(self.g(layer)));
+        for (j, g_j) in g.iter().enumerate() {
+            assert!(g_j.len() <= MASK_DEGREE + 1);
+            for (t, c) in g_j.iter().rev().enumerate() {
+                table.evals[(j << COEFFICIENT_VARS) + t] = *c;
+            }
+        }
+        table
+    }
+
+    /// The table from the regions in the order input, masks of every layer, R of every layer.
+    pub fn table<S: Field>(&self, regions: &Vec<&MultilinearPoly<S>>) -> MultilinearPoly<S> {
+        assert_eq!(regions.len(), self.regions.len());
+        let mut table = MultilinearPoly::zero(self.num_vars);
+        for (f, (offset, vars)) in regions.iter().zip(self.regions.iter()) {
+            assert_eq!(f.num_vars, *vars);
+            table.evals[*offset..*offset + (1 << vars)].copy_from_slice(&f.evals);
+        }
+        table
+    }
+}
+
+/// The point y, and the factor s = Π_l (1 + x^(2^l)), at which a block of coefficients c_t
+/// takes the value Σ_t c_t x^t / s: eq(y, t) is x^t / s for y_l = x^(2^l) / (1 + x^(2^l)),
+/// most significant first. No point does if s is zero, which the verifier rejects.
+pub fn powers_point<E: Field>(x: &E) -> (Vec<E>, E) {
+    let mut y = vec![];
+    let mut factor = E::one();
+    let mut power = *x;
+    for _ in 0..COEFFICIENT_VARS {
+        let d = E::one() + power;
+        y.push(power * d.invert().unwrap_or(E::zero()));
+        factor *= d;
+        power = power.square();
+    }
+    y.reverse();
+    (y, factor)
+}
+
+/// Σ_x g(x) over the hypercube for g(x) = Σ_j g_j(x_j), one univariate per variable.
+pub fn mask_sum<E: Field>(g: &[Vec<E>]) -> E {
+    let sum = g.iter().fold(E::zero(), |acc, g_j| {
+        acc + eval_univariate(g_j, &E::zero()) + eval_univariate(g_j, &E::one())
+    });
+    sum * E::one().double().pow_vartime([g.len() as u64 - 1])
+}
+
+/// Z(x) = Π x_i(1 - x_i), which is zero on the hypercube.
+pub fn vanishing<E: Field>(x: &[E]) -> E {
+    x.iter()
+        .fold(E::one(), |acc, x_i| acc * x_i * (E::one() - x_i))
+}
+
+/// R(x_1) = R(x_1, 0) + R(x_1, 1) for the mask R of a layer, a table in two variables.
+pub fn mask_at<E: Field + From<S>, S: Field>(r: &MultilinearPoly<S>, x_1: &E) -> E {
+    r.evaluate(&vec![*x_1, E::zero()]) + r.evaluate(&vec![*x_1, E::one()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{powers_point, MaskLayout};
+    use crate::gkr::poly::{eval_univariate, MultilinearPoly};
+    use halo2curves::bn256::Fr;
+
+    #[test]
+    fn test_regions() {
+        // input of 2 variables padded to 3, sumchecks of 4 and 6 rounds, one R
+        let layout = MaskLayout::new(&vec![1, 2, 2]);
+        let regions: Vec<MultilinearPoly<Fr>> = [3, 5, 6, 2]
+            .iter()
+            .enumerate()
+            .map(|(j, v)| {
+                MultilinearPoly::new((0..1 << v).map(|i| Fr::from(100 * j as u64 + i)).collect())
+            })
+            .collect();
+        let table = layout.table(&regions.iter().collect());
+        assert_eq!(layout.num_vars, 7);
+        let x = vec![Fr::from(3), Fr::from(5)];
+        assert_eq!(
+            table.evaluate(&layout.point(layout.r(1), &x)),
+            regions[3].evaluate(&x)
+        );
+        let x = vec![Fr::from(2), Fr::from(9), Fr::from(4)];
+        assert_eq!(
+            table.evaluate(&layout.point(layout.input(), &x)),
+            regions[0].evaluate(&x)
+        );
+    }
+
+    #[test]
+    fn test_mask_point() {
+        let layout = MaskLayout::new(&vec![1, 2, 2]);
+        let g: Vec<Vec<Fr>> = (0..layout.rounds(1))
+            .map(|j| (0..5).map(|t| Fr::from(10 * j as u64 + t + 1)).collect())
+            .collect();
+        let masks = layout.mask_table(1, &g);
+        let mut regions = [
+            MultilinearPoly::zero(layout.vars(layout.input())),
+            MultilinearPoly::zero(layout.vars(layout.g(0))),
+            masks,
+            MultilinearPoly::zero(2),
+        ];
+        regions[0].evals[1] = Fr::from(7);
+        let table = layout.table(&regions.iter().collect());
+        let x = Fr::from(12345);
+        for (j, g_j) in g.iter().enumerate() {
+            let value = table.evaluate(&layout.mask_point(1, j, &x));
+            assert_eq!(value * powers_point(&x).1, eval_univariate(g_j, &x));
+        }
+    }
+}
@@ -1,6 +1,11 @@
 #![feature(let_chains)]
 
 pub mod aggregator;
-mod convert;
+pub mod compiled;
+pub mod convert;
+pub mod error;
+pub mod field;
 mod file_utils;
-mod gkr;
+pub mod gkr;
+pub mod witness;
+pub mod workspace;
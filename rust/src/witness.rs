@@ -0,0 +1,425 @@
+use crate::error::{io_error, AggregatorError};
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{Num, Zero};
+use serde_json::Value;
+use std::{fs, path::Path};
+use wasmtime::{Caller, Engine, Instance, Linker, Module, Store, WasmParams, WasmResults};
+use wtns_file::{FieldElement, WtnsFile};
+
+// messages the circuit wrote through the runtime imports
+#[derive(Default)]
+struct Runtime {
+    error: String,
+    message: String,
+    // the lines log() printed during the last calculation
+    logged: Vec<String>,
+}
+
+fn tool_error(reason: String) -> AggregatorError {
+    AggregatorError::Tool {
+        tool: String::from("witness calculator"),
+        reason,
+    }
+}
+
+fn exception(code: i32) -> &'static str {
+    match code {
+        1 => "signal not found",
+        2 => "too many signals set",
+        3 => "signal already set",
+        4 => "assert failed",
+        5 => "not enough memory",
+        6 => "input signal array access exceeds the size",
+        _ => "unknown error",
+    }
+}
+
+// the null terminated message the circuit left before calling the runtime
+fn read_message(caller: &mut Caller<'_, Runtime>) -> wasmtime::Result<String> {
+    let get_char = caller
+        .get_export("getMessageChar")
+        .and_then(|e| e.into_func())
+        .ok_or_else(|| wasmtime::Error::msg("getMessageChar is not exported"))?
+        .typed::<(), i32>(&*caller)?;
+    let mut message = String::new();
+    loop {
+        let c = get_char.call(&mut *caller, ())?;
+        if c == 0 {
+            break;
+        }
+        message.push(c as u8 as char);
+    }
+    Ok(message)
+}
+
+// the field element in the shared memory, as showSharedRWMemory of witness_calculator.js
+// prints it
+fn read_shared_decimal(caller: &mut Caller<'_, Runtime>) -> wasmtime::Result<String> {
+    let export = |caller: &mut Caller<'_, Runtime>, name: &str| {
+        caller
+            .get_export(name)
+            .and_then(|e| e.into_func())
+            .ok_or_else(|| wasmtime::Error::msg(format!("{} is not exported", name)))
+    };
+    let n32 = export(caller, "getFieldNumLen32")?
+        .typed::<(), i32>(&*caller)?
+        .call(&mut *caller, ())?;
+    let read = export(caller, "readSharedRWMemory")?.typed::<i32, i32>(&*caller)?;
+    let mut words = vec![];
+    for j in 0..n32 {
+        words.push(read.call(&mut *caller, j)? as u32);
+    }
+    Ok(BigUint::new(words).to_str_radix(10))
+}
+
+// appends an item of a log() line, which is printed once the circuit ends it with "\n"
+fn append_log(runtime: &mut Runtime, item: &str) {
+    if item == "\n" {
+        let line = std::mem::take(&mut runtime.message);
+        println!("{}", line);
+        runtime.logged.push(line);
+        return;
+    }
+    if !runtime.message.is_empty() {
+        runtime.message.push(' ');
+    }
+    runtime.message.push_str(item);
+}
+
+/// 64-bit FNV-1a of a signal name, split into the (msb, lsb) words circom looks signals up by.
+pub fn fnv_hash(name: &str) -> (i32, i32) {
+    let mut hash: u64 = 0xCBF29CE484222325;
+    // over UTF-16 code units, as charCodeAt in circom's witness_calculator.js
+    for c in name.encode_utf16() {
+        hash ^= c as u64;
+        hash = hash.wrapping_mul(0x100000001B3);
+    }
+    ((hash >> 32) as u32 as i32, hash as u32 as i32)
+}
+
+// values of an input signal, with arrays flattened in row-major order
+fn flatten(value: &Value, res: &mut Vec<BigInt>) -> Result<(), String> {
+    match value {
+        Value::Array(values) => {
+            for v in values.iter() {
+                flatten(v, res)?;
+            }
+            Ok(())
+        }
+        Value::Number(n) => match BigInt::from_str_radix(&n.to_string(), 10) {
+            Ok(v) => {
+                res.push(v);
+                Ok(())
+            }
+            Err(_) => Err(format!("{} is not an integer", n)),
+        },
+        Value::String(s) => {
+            let parsed = match s.strip_prefix("0x") {
+                Some(hex) => BigInt::from_str_radix(hex, 16),
+                None => BigInt::from_str_radix(s, 10),
+            };
+            match parsed {
+                Ok(v) => {
+                    res.push(v);
+                    Ok(())
+                }
+                Err(_) => Err(format!("{} is not an integer", s)),
+            }
+        }
+        _ => Err(format!("{} is not an integer", value)),
+    }
+}
+
+/// circom's witness calculator, run in process on the `.wasm` that `circom --wasm` emits
+/// instead of with node and `generate_witness.js`.
+pub struct WitnessCalculator {
+    store: Store<Runtime>,
+    instance: Instance,
+    n32: usize,
+}
+
+impl WitnessCalculator {
+    pub fn from_file(wasm_path: &Path) -> Result<Self, AggregatorError> {
+        let wasm = fs::read(wasm_path).map_err(io_error(wasm_path.display()))?;
+        Self::new(&wasm)
+    }
+
+    pub fn new(wasm: &[u8]) -> Result<Self, AggregatorError> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).map_err(|e| tool_error(e.to_string()))?;
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap(
+                "runtime",
+                "exceptionHandler",
+                |mut caller: Caller<'_, Runtime>, code: i32| -> wasmtime::Result<()> {
+                    let error = std::mem::take(&mut caller.data_mut().error);
+                    let mut reason = String::from(exception(code));
+                    if !error.trim().is_empty() {
+                        reason = format!("{}: {}", reason, error.trim());
+                    }
+                    Err(wasmtime::Error::msg(reason))
+                },
+            )
+            .map_err(|e| tool_error(e.to_string()))?;
+        linker
+            .func_wrap(
+                "runtime",
+                "printErrorMessage",
+                |mut caller: Caller<'_, Runtime>| -> wasmtime::Result<()> {
+                    let message = read_message(&mut caller)?;
+                    caller.data_mut().error.push_str(&message);
+                    Ok(())
+                },
+            )
+            .map_err(|e| tool_error(e.to_string()))?;
+        linker
+            .func_wrap(
+                "runtime",
+                "writeBufferMessage",
+                |mut caller: Caller<'_, Runtime>| -> wasmtime::Result<()> {
+                    let message = read_message(&mut caller)?;
+                    append_log(caller.data_mut(), &message);
+                    Ok(())
+                },
+            )
+            .map_err(|e| tool_error(e.to_string()))?;
+        linker
+            .func_wrap(
+                "runtime",
+                "showSharedRWMemory",
+                |mut caller: Caller<'_, Runtime>| -> wasmtime::Result<()> {
+                    let value = read_shared_decimal(&mut caller)?;
+                    append_log(caller.data_mut(), &value);
+                    Ok(())
+                },
+            )
+            .map_err(|e| tool_error(e.to_string()))?;
+        // hooks for tracing signals and components, which witness_calculator.js also leaves
+        // unset
+        let no_trace = |linker: &mut Linker<Runtime>, name: &str, params: usize| {
+            let result = match params {
+                1 => linker.func_wrap("runtime", name, |_: i32| {}),
+                _ => linker.func_wrap("runtime", name, |_: i32, _: i32| {}),
+            };
+            result.map(|_| ()).map_err(|e| tool_error(e.to_string()))
+        };
+        no_trace(&mut linker, "logSetSignal", 2)?;
+        no_trace(&mut linker, "logGetSignal", 2)?;
+        no_trace(&mut linker, "logStartComponent", 1)?;
+        no_trace(&mut linker, "logFinishComponent", 1)?;
+        no_trace(&mut linker, "log", 1)?;
+        // imports of later runtimes default to doing nothing
+        linker
+            .define_unknown_imports_as_default_values(&module)
+            .map_err(|e| tool_error(e.to_string()))?;
+
+        let mut store = Store::new(&engine, Runtime::default());
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .map_err(|e| tool_error(e.to_string()))?;
+        let mut calculator = WitnessCalculator {
+            store,
+            instance,
+            n32: 0,
+        };
+        if calculator
+            .instance
+            .get_func(&mut calculator.store, "getVersion")
+            .is_none()
+        {
+            return Err(AggregatorError::Unsupported(String::from(
+                "witness calculators before circom 2",
+            )));
+        }
+        let n32 = calculator.call::<(), i32>("getFieldNumLen32", ())?;
+        if n32 != 8 {
+            return Err(AggregatorError::Unsupported(format!(
+                "witness over a {}-byte field",
+                4 * n32
+            )));
+        }
+        calculator.n32 = n32 as usize;
+        Ok(calculator)
+    }
+
+    fn call<P: WasmParams, R: WasmResults>(
+        &mut self,
+        name: &str,
+        params: P,
+    ) -> Result<R, AggregatorError> {
+        let f = self
+            .instance
+            .get_typed_func::<P, R>(&mut self.store, name)
+            .map_err(|e| tool_error(format!("{}: {}", name, e)))?;
+        f.call(&mut self.store, params)
+            .map_err(|e| tool_error(e.root_cause().to_string()))
+    }
+
+    // little-endian 32-bit words of the field element in the shared memory
+    fn read_shared(&mut self) -> Result<[u8; 32], AggregatorError> {
+        let mut bytes = [0u8; 32];
+        for j in 0..self.n32 {
+            let word = self.call::<i32, i32>("readSharedRWMemory", j as i32)? as u32;
+            bytes[4 * j..4 * j + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    fn write_shared(&mut self, value: &BigUint) -> Result<(), AggregatorError> {
+        let words = value.to_u32_digits();
+        for j in 0..self.n32 {
+            let word = words.get(j).cloned().unwrap_or(0);
+            self.call::<(i32, i32), ()>("writeSharedRWMemory", (j as i32, word as i32))?;
+        }
+        Ok(())
+    }
+
+    pub fn prime(&mut self) -> Result<BigUint, AggregatorError> {
+        self.call::<(), ()>("getRawPrime", ())?;
+        Ok(BigUint::from_bytes_le(&self.read_shared()?))
+    }
+
+    /// The witness of the circuit on an input JSON, as `generate_witness.js` writes it.
+    pub fn calculate(&mut self, input_json: &str) -> Result<WtnsFile<32>, AggregatorError> {
+        let input: serde_json::Map<String, Value> = serde_json::from_str(input_json)
+            .map_err(|e| AggregatorError::WitnessMismatch(format!("bad input: {}", e)))?;
+        let prime = BigInt::from_biguint(Sign::Plus, self.prime()?);
+
+        let runtime = self.store.data_mut();
+        runtime.message.clear();
+        runtime.logged.clear();
+        self.call::<i32, ()>("init", 0)?;
+        let mut input_counter = 0;
+        for (name, value) in input.iter() {
+            let (msb, lsb) = fnv_hash(name);
+            let mut values = vec![];
+            flatten(value, &mut values).map_err(|e| {
+                AggregatorError::WitnessMismatch(format!("input signal {}: {}", name, e))
+            })?;
+            let size = self.call::<(i32, i32), i32>("getInputSignalSize", (msb, lsb))?;
+            if size < 0 {
+                return Err(AggregatorError::WitnessMismatch(format!(
+                    "signal {} not found",
+                    name
+                )));
+            }
+            if values.len() != size as usize {
+                return Err(AggregatorError::WitnessMismatch(format!(
+                    "input signal {} has {} values, expected {}",
+                    name,
+                    values.len(),
+                    size
+                )));
+            }
+            for (i, v) in values.iter().enumerate() {
+                let mut v = v % &prime;
+                if v < BigInt::zero() {
+                    v += &prime;
+                }
+                self.write_shared(v.magnitude())?;
+                self.call::<(i32, i32, i32), ()>("setInputSignal", (msb, lsb, i as i32))?;
+                input_counter += 1;
+            }
+        }
+        let input_size = self.call::<(), i32>("getInputSize", ())?;
+        if input_counter < input_size {
+            return Err(AggregatorError::WitnessMismatch(format!(
+                "only {} of {} inputs are set",
+                input_counter, input_size
+            )));
+        }
+
+        let witness_size = self.call::<(), i32>("getWitnessSize", ())?;
+        let mut witness = vec![];
+        for i in 0..witness_size {
+            self.call::<i32, ()>("getWitness", i)?;
+            witness.push(FieldElement::from(self.read_shared()?));
+        }
+        let prime = self.prime()?.to_bytes_le();
+        let mut prime_bytes = [0u8; 32];
+        prime_bytes[..prime.len()].copy_from_slice(&prime);
+        Ok(WtnsFile::from_vec(witness, FieldElement::from(prime_bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fnv_hash, WitnessCalculator};
+    use crate::field::modulus_le;
+    use halo2curves::bn256::Fr;
+
+    // the exports of a circom 2 witness calculator for `signal input a`, witness [1, a]
+    const CIRCUIT: &str = r#"(module
+      (import "runtime" "exceptionHandler" (func $exception (param i32)))
+      (import "runtime" "printErrorMessage" (func))
+      (import "runtime" "showSharedRWMemory" (func $show))
+      (import "runtime" "writeBufferMessage" (func $write))
+      (import "runtime" "logSetSignal" (func (param i32 i32)))
+      (memory 1)
+      (data (i32.const 64) "\01\00\00\f0\93\f5\e1\43\91\70\b9\79\48\e8\33\28\5d\58\81\81\b6\45\50\b8\29\a0\31\e1\72\4e\64\30")
+      (data (i32.const 128) "a\00\n\00")
+      (global $set (mut i32) (i32.const 0))
+      (global $message (mut i32) (i32.const 128))
+      (func (export "getVersion") (result i32) (i32.const 2))
+      (func (export "getFieldNumLen32") (result i32) (i32.const 8))
+      (func (export "getRawPrime") (memory.copy (i32.const 0) (i32.const 64) (i32.const 32)))
+      (func (export "readSharedRWMemory") (param i32) (result i32)
+        (i32.load (i32.mul (local.get 0) (i32.const 4))))
+      (func (export "writeSharedRWMemory") (param i32 i32)
+        (i32.store (i32.mul (local.get 0) (i32.const 4)) (local.get 1)))
+      (func (export "init") (param i32) (global.set $set (i32.const 0)))
+      (func (export "getInputSize") (result i32) (i32.const 1))
+      (func (export "getInputSignalSize") (param i32 i32) (result i32)
+        (if (result i32)
+          (i32.and (i32.eq (local.get 0) (i32.const 0xaf63dc4c)) (i32.eq (local.get 1) (i32.const 0x8601ec8c)))
+          (then (i32.const 1))
+          (else (i32.const -1))))
+      (func (export "setInputSignal") (param i32 i32 i32)
+        (if (global.get $set) (then (call $exception (i32.const 3))))
+        (memory.copy (i32.const 96) (i32.const 0) (i32.const 32))
+        (global.set $set (i32.const 1))
+        ;; log("a", a)
+        (global.set $message (i32.const 128))
+        (call $write)
+        (call $show)
+        (call $write))
+      (func (export "getWitnessSize") (result i32) (i32.const 2))
+      (func (export "getWitness") (param i32)
+        (if (local.get 0)
+          (then (memory.copy (i32.const 0) (i32.const 96) (i32.const 32)))
+          (else (memory.fill (i32.const 0) (i32.const 0) (i32.const 32))
+                (i32.store (i32.const 0) (i32.const 1)))))
+      (func (export "getMessageChar") (result i32)
+        (global.set $message (i32.add (global.get $message) (i32.const 1)))
+        (i32.load8_u (i32.sub (global.get $message) (i32.const 1)))))"#;
+
+    #[test]
+    fn test_fnv_hash() {
+        // FNV-1a test vectors
+        assert_eq!(fnv_hash(""), (0xcbf29ce4u32 as i32, 0x84222325u32 as i32));
+        assert_eq!(fnv_hash("a"), (0xaf63dc4cu32 as i32, 0x8601ec8cu32 as i32));
+    }
+
+    #[test]
+    fn test_calculate() {
+        let mut calculator = WitnessCalculator::new(CIRCUIT.as_bytes()).unwrap();
+        let wtns = calculator.calculate(r#"{"a": "-1"}"#).unwrap();
+        let p = modulus_le::<Fr>();
+        assert_eq!(*wtns.header.prime, p);
+        assert_eq!(wtns.witness.0.len(), 2);
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        assert_eq!(*wtns.witness.0[0], one);
+        let mut minus_one = p;
+        minus_one[0] -= 1;
+        assert_eq!(*wtns.witness.0[1], minus_one);
+
+        let wtns = calculator.calculate(r#"{"a": [5]}"#).unwrap();
+        assert_eq!(wtns.witness.0[1][0], 5);
+        assert_eq!(calculator.store.data().logged, vec![String::from("a 5")]);
+        assert!(calculator.calculate(r#"{"b": 1}"#).is_err());
+        assert!(calculator.calculate(r#"{"a": [1, 2]}"#).is_err());
+        assert!(calculator.calculate("{}").is_err());
+    }
+}
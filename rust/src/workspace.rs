@@ -0,0 +1,89 @@
+use crate::error::{io_error, AggregatorError};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory of the compiled circuit cache inside the output directory.
+pub const CACHE_DIR: &str = ".gkr_cache";
+
+/// Owns every path a run reads and writes. Intermediate files and outputs go to a fresh
+/// subdirectory of the output directory, so runs in the same directory never share a file.
+/// Only the compiled circuit cache, which is keyed by the r1cs hash, is shared between runs.
+pub struct Workspace {
+    out_dir: PathBuf,
+    run_dir: PathBuf,
+}
+
+impl Workspace {
+    /// Creates a new run directory `run-<seconds>-<pid>[-<n>]` in `out_dir`.
+    pub fn new(out_dir: &Path) -> Result<Self, AggregatorError> {
+        fs::create_dir_all(out_dir).map_err(io_error(out_dir.display()))?;
+        let out_dir = fs::canonicalize(out_dir).map_err(io_error(out_dir.display()))?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let name = format!("run-{}-{}", secs, std::process::id());
+        let mut n = 0;
+        loop {
+            let run_dir = if n == 0 {
+                out_dir.join(&name)
+            } else {
+                out_dir.join(format!("{}-{}", name, n))
+            };
+            // create_dir fails if another run took the name first
+            match fs::create_dir(&run_dir) {
+                Ok(()) => return Ok(Workspace { out_dir, run_dir }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(io_error(run_dir.display())(e)),
+            }
+        }
+    }
+
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    pub fn run_dir(&self) -> &Path {
+        &self.run_dir
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.out_dir.join(CACHE_DIR)
+    }
+
+    /// Where circom writes the r1cs, sym and witness generator, with a trailing `/`.
+    pub fn root_path(&self) -> String {
+        format!("{}/", self.run_dir.display())
+    }
+
+    pub fn witness(&self) -> PathBuf {
+        self.run_dir.join("witness.wtns")
+    }
+
+    pub fn aggregated_input(&self) -> PathBuf {
+        self.run_dir.join("aggregated.json")
+    }
+
+    pub fn aggregated_circuit(&self) -> PathBuf {
+        self.run_dir.join("aggregated.circom")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Workspace;
+
+    #[test]
+    fn test_runs_do_not_share_a_directory() {
+        let out_dir = std::env::temp_dir().join(format!("gkr-workspace-{}", std::process::id()));
+        let first = Workspace::new(&out_dir).unwrap();
+        let second = Workspace::new(&out_dir).unwrap();
+        assert_ne!(first.run_dir(), second.run_dir());
+        assert_eq!(first.cache_dir(), second.cache_dir());
+        assert!(first.witness().starts_with(first.run_dir()));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}